@@ -0,0 +1,145 @@
+use anyhow::{bail, Context, Result};
+use std::io::BufRead;
+
+/// Checks the header of an ESRI ASCII grid line by line, so malformed files fail with an
+/// error naming the line and field instead of an opaque parse error.
+///
+/// The header must hold `ncols`, `nrows`, `xllcorner` or `xllcenter`, `yllcorner` or
+/// `yllcenter` and `cellsize` in that order, optionally followed by `NODATA_value`.
+/// Keywords are case-insensitive.
+pub fn validate_ascii_header(reader: impl BufRead) -> Result<()> {
+    let mut lines = reader.lines();
+    let mut next_line = |number: usize, expected: &str| -> Result<(String, String)> {
+        let line = lines
+            .next()
+            .with_context(|| format!("line {}: expected {}, found the end of the file", number, expected))?
+            .with_context(|| format!("line {}: the header is not readable text", number))?;
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next().unwrap_or_default().to_string();
+        let value = tokens.next().unwrap_or_default().to_string();
+        Ok((keyword, value))
+    };
+
+    let ncols = expect_field(1, next_line(1, "ncols")?, "ncols")?;
+    positive_count(1, "ncols", &ncols)?;
+    let nrows = expect_field(2, next_line(2, "nrows")?, "nrows")?;
+    positive_count(2, "nrows", &nrows)?;
+
+    let (x_keyword, x) = next_line(3, "xllcorner or xllcenter")?;
+    let x_kind = corner_kind(3, "xll", &x_keyword)?;
+    parse_number(3, &x_keyword, &x)?;
+    let (y_keyword, y) = next_line(4, "yllcorner or yllcenter")?;
+    let y_kind = corner_kind(4, "yll", &y_keyword)?;
+    parse_number(4, &y_keyword, &y)?;
+    if x_kind != y_kind {
+        bail!("line 4: {} doesn't match {} on line 3; use corner or center for both", y_keyword, x_keyword);
+    }
+
+    let (keyword, value) = next_line(5, "cellsize")?;
+    if matches!(keyword.to_lowercase().as_str(), "dx" | "dy") {
+        bail!("line 5: expected cellsize, found {} (separate dx and dy cell sizes are not supported)", keyword);
+    }
+    if !keyword.eq_ignore_ascii_case("cellsize") {
+        bail!("line 5: expected cellsize, found '{}'; pass --cell-size if the header has none", keyword);
+    }
+    let cellsize = expect_field(5, (keyword, value), "cellsize")?;
+    if parse_number(5, "cellsize", &cellsize)? <= 0.0 {
+        bail!("line 5: cellsize must be positive, found {}", cellsize);
+    }
+
+    // NODATA_value is optional; data can start right after the cell size.
+    let Ok((keyword, value)) = next_line(6, "NODATA_value") else {
+        return Ok(());
+    };
+    if keyword.parse::<f64>().is_err() && !keyword.is_empty() {
+        if !keyword.eq_ignore_ascii_case("nodata_value") {
+            bail!("line 6: expected NODATA_value or the first row of data, found '{}'", keyword);
+        }
+        parse_number(6, "NODATA_value", &value)?;
+    }
+    Ok(())
+}
+
+/// Rewrites the header of an ASCII grid to use `cell_size`, replacing its `cellsize` line,
+/// or `dx` and `dy` lines, or inserting one after the corner coordinates when it has none.
+///
+/// Returns the rewritten file and the header's own cell size, if it had a readable one.
+pub fn override_cell_size(data: &[u8], cell_size: f64) -> (Vec<u8>, Option<f64>) {
+    // The cell size follows the four size and corner lines.
+    let mut start = 0;
+    for _ in 0..4 {
+        match data[start..].iter().position(|&b| b == b'\n') {
+            Some(end) => start += end + 1,
+            None => start = data.len(),
+        }
+    }
+
+    let mut end = start;
+    let mut original = None;
+    while end < data.len() {
+        let line_end = data[end..].iter().position(|&b| b == b'\n').map_or(data.len(), |i| end + i + 1);
+        let line = String::from_utf8_lossy(&data[end..line_end]);
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next().unwrap_or_default().to_lowercase();
+        if !matches!(keyword.as_str(), "cellsize" | "dx" | "dy") {
+            break;
+        }
+        if keyword == "cellsize" {
+            original = tokens.next().and_then(|v| v.parse::<f64>().ok()).filter(|v| v.is_finite() && *v > 0.0);
+        }
+        end = line_end;
+    }
+
+    let mut patched = data[..start].to_vec();
+    if !patched.is_empty() && !patched.ends_with(b"\n") {
+        patched.push(b'\n');
+    }
+    patched.extend_from_slice(format!("cellsize {}\n", cell_size).as_bytes());
+    patched.extend_from_slice(&data[end..]);
+    (patched, original)
+}
+
+/// Checks that a header line holds `expected` and returns its value.
+fn expect_field(number: usize, (keyword, value): (String, String), expected: &str) -> Result<String> {
+    if !keyword.eq_ignore_ascii_case(expected) {
+        if keyword.is_empty() {
+            bail!("line {}: expected {}, found an empty line", number, expected);
+        }
+        bail!("line {}: expected {}, found '{}'", number, expected, keyword);
+    }
+    if value.is_empty() {
+        bail!("line {}: {} has no value", number, expected);
+    }
+    Ok(value)
+}
+
+/// Returns whether a corner keyword such as `xllcorner` refers to the cell center.
+fn corner_kind(number: usize, prefix: &str, keyword: &str) -> Result<bool> {
+    match keyword.to_lowercase().strip_prefix(prefix) {
+        Some("corner") => Ok(false),
+        Some("center") => Ok(true),
+        _ if keyword.is_empty() => {
+            bail!("line {}: expected {}corner or {}center, found an empty line", number, prefix, prefix)
+        }
+        _ => bail!("line {}: expected {}corner or {}center, found '{}'", number, prefix, prefix, keyword),
+    }
+}
+
+/// Parses the value of a header field as a finite number.
+fn parse_number(number: usize, field: &str, value: &str) -> Result<f64> {
+    if value.is_empty() {
+        bail!("line {}: {} has no value", number, field);
+    }
+    match value.parse::<f64>() {
+        Ok(parsed) if parsed.is_finite() || field.eq_ignore_ascii_case("nodata_value") => Ok(parsed),
+        _ => bail!("line {}: {} must be a number, found '{}'", number, field, value),
+    }
+}
+
+/// Checks that a row or column count is a whole number above zero.
+fn positive_count(number: usize, field: &str, value: &str) -> Result<()> {
+    match value.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(()),
+        _ => bail!("line {}: {} must be a whole number above 0, found '{}'", number, field, value),
+    }
+}
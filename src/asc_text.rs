@@ -0,0 +1,97 @@
+use std::io::{self, BufRead, Read};
+
+/// UTF-8 byte order mark some Windows tools write at the start of text files.
+const BYTE_ORDER_MARK: &[u8] = b"\xEF\xBB\xBF";
+
+/// Reads the text of an ASCII grid with the quirks of other tools tidied away, so the
+/// header and cells parse like those of a clean file.
+///
+/// Lines may end in `\r\n`, `\r` or `\n`; each comes out ending in `\n`, with surrounding
+/// whitespace trimmed. Blank lines and a leading byte order mark are dropped. Only one
+/// line is held at a time.
+#[derive(Debug)]
+pub struct AsciiText<R> {
+    inner: R,
+    line: Vec<u8>,
+    pos: usize,
+    started: bool,
+}
+
+impl<R: BufRead> AsciiText<R> {
+    /// Wraps buffered text.
+    pub fn new(inner: R) -> Self {
+        Self { inner, line: Vec::new(), pos: 0, started: false }
+    }
+
+    /// Reads the next line that isn't blank into `line`, returning false at the end of the text.
+    fn next_line(&mut self) -> io::Result<bool> {
+        let mut raw = Vec::new();
+        loop {
+            raw.clear();
+            let ended = self.read_raw_line(&mut raw)?;
+            let mut text = raw.as_slice();
+            if !self.started {
+                self.started = true;
+                text = text.strip_prefix(BYTE_ORDER_MARK).unwrap_or(text);
+            }
+            let text = text.trim_ascii();
+            if !text.is_empty() {
+                self.line.clear();
+                self.line.extend_from_slice(text);
+                self.line.push(b'\n');
+                self.pos = 0;
+                return Ok(true);
+            }
+            if ended {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Appends the bytes up to the next `\r` or `\n` to `raw`, consuming the line break.
+    /// Returns true if the text ended before a line break.
+    fn read_raw_line(&mut self, raw: &mut Vec<u8>) -> io::Result<bool> {
+        loop {
+            let available = self.inner.fill_buf()?;
+            if available.is_empty() {
+                return Ok(true);
+            }
+            match available.iter().position(|&b| b == b'\r' || b == b'\n') {
+                Some(end) => {
+                    raw.extend_from_slice(&available[..end]);
+                    self.inner.consume(end + 1);
+                    return Ok(false);
+                }
+                None => {
+                    let len = available.len();
+                    raw.extend_from_slice(available);
+                    self.inner.consume(len);
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for AsciiText<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for AsciiText<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.line.len() && !self.next_line()? {
+            self.line.clear();
+            self.pos = 0;
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.line.len());
+    }
+}
@@ -1,19 +1,685 @@
-use clap::Parser;
+use crate::colormaps;
+use crate::config::config_arguments;
+use crate::crs::Crs;
+use clap::builder::PossibleValuesParser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use log::LevelFilter;
+use image::Rgb;
+use std::env;
+use std::ffi::OsString;
 use std::path::PathBuf;
 
-/// Convert ASC DEM files to grayscale or hillshaded PNGs
+/// Convert ASC and GeoTIFF DEM files to grayscale, hillshaded, slope, aspect or color-relief images
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 pub struct Args {
-    /// Input directory containing ASC files
-    #[arg(short, long)]
-    pub input_dir: PathBuf,
+    /// Input directory containing ASC or single-band GeoTIFF files, or a single such file
+    #[arg(short, long, required_unless_present_any = ["from_file", "compare"], conflicts_with = "from_file")]
+    pub input_dir: Option<PathBuf>,
+
+    /// Process the files listed one per line in LIST (or standard input for `-`) in that order,
+    /// instead of searching the input directory
+    #[arg(long, value_name = "LIST")]
+    pub from_file: Option<PathBuf>,
+
+    /// Descend into subdirectories of the input directory (the default)
+    #[arg(long, overrides_with = "no_recursive")]
+    pub recursive: bool,
+
+    /// Only process files directly inside the input directory
+    #[arg(long)]
+    pub no_recursive: bool,
+
+    /// Maximum directory depth to descend into, where 1 is the input directory itself
+    #[arg(long, conflicts_with = "no_recursive")]
+    pub max_depth: Option<usize>,
+
+    /// Only process files of the input directory matching this glob, e.g. `n45*.asc`; patterns
+    /// match the file name or the path below the input directory. Repeat for several patterns
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Skip files of the input directory matching this glob, e.g. `*_temp.asc`, even if
+    /// --include matches them. Repeat for several patterns
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Order the files found in the input directory are processed and reported in: name
+    /// (by path), size (smallest first) or mtime (oldest first). --from-file lists keep their order
+    #[arg(long, default_value = "name", value_parser = ["name", "size", "mtime"])]
+    pub sort: String,
 
     /// Output directory for PNG files
     #[arg(short, long)]
     pub output_dir: PathBuf,
 
-    /// Rendering mode: grayscale or hillshade
-    #[arg(short, long, default_value = "grayscale")]
-    pub mode: String,
+    /// Compare two rendered images instead of processing DEMs: log the RMS and largest
+    /// difference and write an image of where they differ to the output directory
+    #[arg(long, num_args = 2, value_names = ["A", "B"], conflicts_with_all = ["input_dir", "from_file"])]
+    pub compare: Option<Vec<PathBuf>>,
+
+    /// Recreate the subdirectories of the input directory under the output directory,
+    /// so tiles with the same name in different folders don't overwrite each other
+    #[arg(long, requires = "input_dir")]
+    pub preserve_tree: bool,
+
+    /// Rendering mode: grayscale, hillshade, shade (the hillshade alone, independent of each file's elevation range so tiles match), slope, aspect, color-relief, tri (terrain ruggedness index), tpi (topographic position index), curvature, svf (sky-view factor), slope-class (slope binned by --slope-breaks), occlusion (ambient occlusion) or flow-accum (D8 flow accumulation).
+    /// Repeat the option or separate modes with commas to render several from one read of each file.
+    /// Color relief colors raw elevations in meters (or by --colormap-file) so tiles match.
+    /// Aspect follows the ArcGIS convention: compass degrees clockwise from north (0-360), -1 for flat cells
+    #[arg(short, long, default_value = "grayscale", value_delimiter = ',')]
+    pub mode: Vec<String>,
+
+    /// A bundle of settings for a common map style; options given explicitly or by --config still win.
+    /// topo: hillshade mode with the terrain colormap, hsv-value blending at 0.6 opacity and 20 m contours
+    #[arg(long, value_parser = ["topo"])]
+    pub preset: Option<String>,
+
+    /// TOML (or .json) file of settings keyed by long option name, e.g. `colormap = "terrain"`;
+    /// options given on the command line win over the file, which wins over the defaults
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Output format: png, jpeg, webp, geotiff (grayscale GeoTIFFs hold the raw elevations and NoData value)
+    /// multi-tiff (every image of the batch as a page of the single TIFF given by --output-file)
+    /// or gpkg (a GeoPackage of PNG tiles placed in --source-crs, or --reproject if set).
+    /// JPEG can't store transparency, so NoData is filled with --nodata-color or black; WebP is lossless
+    #[arg(short, long, default_value = "png", value_parser = ["png", "jpeg", "webp", "geotiff", "multi-tiff", "gpkg"])]
+    pub format: String,
+
+    /// The multi-page TIFF that --format multi-tiff appends every image to
+    #[arg(long, value_name = "FILE", required_if_eq("format", "multi-tiff"))]
+    pub output_file: Option<PathBuf>,
+
+    /// Don't add internal overviews (halved copies down to 256 pixels) to GeoTIFFs, which
+    /// make large files quick to draw zoomed out in QGIS or ArcGIS
+    #[arg(long)]
+    pub no_overviews: bool,
+
+    /// Output file name without extension, using {stem}, {mode}, {azimuth} and {altitude},
+    /// e.g. "{stem}_hs{azimuth}" (defaults to {stem}, {stem}_hillshade, {stem}_slope, ...)
+    #[arg(long, value_name = "TEMPLATE")]
+    pub name_template: Option<String>,
+
+    /// Bits per channel of grayscale PNGs; 16 keeps more precision (water and contour overlays stay 8-bit)
+    #[arg(long, default_value_t = 8, value_parser = parse_bit_depth)]
+    pub bit_depth: u8,
+
+    /// JPEG quality from 1 (smallest) to 100 (best)
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub quality: u8,
+
+    /// PNG compression: fast (quickest saves, largest files), default (zlib's default level, about
+    /// 40% smaller) or best (smallest files, slowest saves)
+    #[arg(long, default_value = "fast", value_parser = ["fast", "default", "best"])]
+    pub png_compression: String,
+
+    /// Log more: -v logs every file instead of showing a progress bar, -vv adds trace output
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Log less: -q leaves only errors on stderr, -qq nothing
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "verbose")]
+    pub quiet: u8,
+
+    /// Write a world file (.pgw, .jgw, ...) next to each image so GIS tools can place it
+    #[arg(long)]
+    pub world_file: bool,
+
+    /// Also write a PNG thumbnail of each image, named like it with a _thumb suffix, scaled
+    /// down to fit SIZE x SIZE pixels
+    #[arg(long, value_name = "SIZE", value_parser = clap::value_parser!(u32).range(1..))]
+    pub thumbnail: Option<u32>,
+
+    /// Fit every image into a WxH canvas, keeping its aspect ratio and padding the rest, for
+    /// galleries of same-sized images
+    #[arg(long, value_name = "WxH", value_parser = parse_canvas)]
+    pub canvas: Option<[u32; 2]>,
+
+    /// Color of the canvas padding as R,G,B or #RRGGBB (transparent by default)
+    #[arg(long, value_parser = parse_color, requires = "canvas")]
+    pub pad_color: Option<Rgb<u8>>,
+
+    /// Don't record the source file and rendering parameters in PNG text chunks
+    #[arg(long)]
+    pub no_metadata: bool,
+
+    /// Overwrite output files that already exist
+    #[arg(long)]
+    pub force: bool,
+
+    /// Skip inputs whose output file already exists (the default)
+    #[arg(long, conflicts_with = "force")]
+    pub skip_existing: bool,
+
+    /// Check every input header and print the planned outputs without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Merge all input tiles into one seamless grid by their corners, rendered as "merged"
+    #[arg(long)]
+    pub merge: bool,
+
+    /// Print the min, max, mean, standard deviation, percentiles and NoData count of every file
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Also write those statistics to <name>_stats.json in the output directory
+    #[arg(long)]
+    pub stats_json: bool,
+
+    /// Also write <name>_legend.png showing the colors of the elevation range, with labels
+    #[arg(long)]
+    pub legend: bool,
+
+    /// Report how long reading, rendering and writing took for each file at the end of the run
+    #[arg(long)]
+    pub timing: bool,
+
+    /// Write a JSON summary of the batch to this file: per input its outputs, modes, success
+    /// or error, elevation range and processing time
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Open the written images in the default viewer, when the batch has at most N files
+    /// (1 unless given); ignored with --quiet
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1")]
+    pub preview: Option<usize>,
+
+    /// Number of files to process in parallel (defaults to the number of CPUs); without
+    /// --threads-per-file the same threads also split the work within each file
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+
+    /// Give each file its own pool of this many threads for hillshading and other per-cell
+    /// work, so -j files run at once with N threads each (1 parallelizes across files only;
+    /// -j 1 with N above 1 parallelizes within files only)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads_per_file: Option<u32>,
+
+    /// Vertical unit of the input elevations
+    #[arg(long, default_value = "meters", value_parser = ["meters", "feet"])]
+    pub input_units: String,
+
+    /// Vertical unit to convert elevations to before any terrain math or coloring.
+    /// Slopes assume the cell size is in this unit too; otherwise adjust --z-factor
+    #[arg(long, default_value = "meters", value_parser = ["meters", "feet"])]
+    pub output_units: String,
+
+    /// Crop each grid to MINX,MINY,MAXX,MAXY in map coordinates before rendering; parts of the
+    /// box beyond the grid are NoData
+    #[arg(long, value_name = "MINX,MINY,MAXX,MAXY", value_parser = parse_bbox, allow_hyphen_values = true)]
+    pub bbox: Option<[f64; 4]>,
+
+    /// Crop each grid to the W x H pixels at X,Y of its full-resolution image before rendering
+    #[arg(long, value_name = "X,Y,W,H", value_parser = parse_crop_pixels, conflicts_with = "bbox")]
+    pub crop_pixels: Option<[u32; 4]>,
+
+    /// GeoJSON file of Polygon or MultiPolygon boundaries, in the coordinates of the rendered
+    /// grid; cells centered outside them become NoData before rendering
+    #[arg(long, value_name = "GEOJSON")]
+    pub mask_file: Option<PathBuf>,
+
+    /// Average NxN blocks of cells before rendering, for quick overviews of large DEMs
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub downsample: u32,
+
+    /// Read only every Nth row and column of each file, for fast previews of huge DEMs;
+    /// unlike --downsample nothing is averaged, and skipped cells are never parsed or stored
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub sample_every: u32,
+
+    /// How grids and images are resampled when downsampling, reprojecting, letterboxing or
+    /// making thumbnails: nearest keeps NoData edges and class codes intact, bilinear
+    /// smooths (downsampling averages each block) and bicubic is sharper; NoData is never
+    /// blended into valid cells
+    #[arg(long, default_value = "bilinear", value_parser = ["nearest", "bilinear", "bicubic"])]
+    pub resample: String,
+
+    /// Fill small NoData holes by interpolating from the surrounding cells before rendering
+    #[arg(long)]
+    pub fill_nodata: bool,
+
+    /// Largest NoData region, in cells, that --fill-nodata fills; bigger voids stay NoData
+    #[arg(long, value_name = "CELLS", default_value_t = 1000, requires = "fill_nodata")]
+    pub fill_max_size: usize,
+
+    /// Fill sinks so every cell can drain to the grid edge or NoData (Planchon-Darboux),
+    /// before flow accumulation and every other mode
+    #[arg(long)]
+    pub fill_sinks: bool,
+
+    /// Smallest drop from a filled cell to the cell it drains to, in elevation units; 0 leaves filled sinks flat
+    #[arg(long, value_name = "DZ", default_value_t = 0.001, requires = "fill_sinks", value_parser = parse_fill_tolerance)]
+    pub fill_tolerance: f64,
+
+    /// Stream grayscale images from ASCII grids in two passes instead of loading the grid,
+    /// for files too large for memory; other modes and grid-wide processing are unavailable
+    #[arg(long)]
+    pub low_memory: bool,
+
+    /// Cell size in map units replacing the one in the headers, for files with a missing or broken cellsize
+    #[arg(long, value_name = "SIZE", value_parser = parse_cell_size)]
+    pub cell_size: Option<f64>,
+
+    /// Coordinate system of the input coordinates, such as EPSG:32633 (ASC files carry none).
+    /// GeoTIFF, GeoPackage and GeoJSON outputs record it, and world files get a .prj beside them
+    #[arg(long, visible_alias = "crs", value_name = "EPSG", value_parser = parse_crs)]
+    pub source_crs: Option<Crs>,
+
+    /// Warp the grid into another coordinate system before rendering, such as EPSG:3857
+    #[arg(long, value_name = "EPSG", value_parser = parse_crs, requires = "source_crs")]
+    pub reproject: Option<Crs>,
+
+    /// Cut each image into a z/x/y slippy-map tile pyramid in a directory named like the image
+    #[arg(long, requires = "source_crs")]
+    pub tiles: bool,
+
+    /// Shallowest zoom level of the tiles (defaults to the level where the DEM fits one tile)
+    #[arg(long, value_name = "Z", requires = "tiles", value_parser = clap::value_parser!(u8).range(0..=24))]
+    pub min_zoom: Option<u8>,
+
+    /// Deepest zoom level of the tiles (defaults to the level matching the DEM resolution)
+    #[arg(long, value_name = "Z", requires = "tiles", value_parser = clap::value_parser!(u8).range(0..=24))]
+    pub max_zoom: Option<u8>,
+
+    /// Tile row numbering: xyz counts rows from the north, tms from the south
+    #[arg(long, default_value = "xyz", value_parser = ["xyz", "tms"])]
+    pub tile_scheme: String,
+
+    /// Vertical exaggeration applied to elevations before hillshading
+    /// (use ~111320 when horizontal units are degrees and elevations are meters)
+    #[arg(long, default_value_t = 1.0)]
+    pub z_factor: f64,
+
+    /// Color ramp blended with the hillshade: classic, viridis, magma, terrain or grayscale
+    #[arg(short, long, default_value = "classic", value_parser = PossibleValuesParser::new(colormaps::NAMES))]
+    pub colormap: String,
+
+    /// GDAL color-relief file (`elevation R G B`, `value% R G B` or `nv R G B A` per line)
+    /// used instead of --colormap to color the hillshade and color relief by elevation
+    #[arg(long)]
+    pub colormap_file: Option<PathBuf>,
+
+    /// Reverse the colormap or color-relief file, e.g. to color high ground blue and low ground red
+    #[arg(long)]
+    pub invert_colormap: bool,
+
+    /// Compass direction the hillshade is lit from, in degrees (0-360)
+    #[arg(long, default_value_t = 315.0, value_parser = parse_azimuth)]
+    pub azimuth: f64,
+
+    /// Render hillshade and shade modes once per azimuth from START up to END (exclusive) in
+    /// STEP degrees, as animation frames named {stem}_az000, {stem}_az010, ...
+    #[arg(long, value_name = "START:END:STEP", value_parser = parse_azimuth_sweep, conflicts_with = "multidirectional")]
+    pub azimuth_sweep: Option<[f64; 3]>,
+
+    /// Height of the hillshade light above the horizon, in degrees (0-90)
+    #[arg(long, default_value_t = 45.0, value_parser = parse_altitude)]
+    pub altitude: f64,
+
+    /// Light the hillshade from 225, 270, 315 and 360 degrees combined, like GDAL's -multidirectional
+    #[arg(long)]
+    pub multidirectional: bool,
+
+    /// Light the hillshade from several tinted lights instead, as AZIMUTH:COLOR pairs such
+    /// as `135:#ffd9a0 315:#a0c0ff`; shade mode then renders the colored relief itself
+    #[arg(long, value_name = "AZIMUTH:COLOR", num_args = 1.., value_parser = parse_colored_light,
+          conflicts_with_all = ["multidirectional", "azimuth_sweep"])]
+    pub colored_lights: Vec<(f64, Rgb<u8>)>,
+
+    /// Darken steep slopes in the hillshade regardless of the light direction, for crisper cliffs
+    #[arg(long)]
+    pub slope_blend: bool,
+
+    /// Blur the elevations with a Gaussian of this radius in cells before shading, to calm
+    /// noisy lidar DEMs (0 disables)
+    #[arg(long, value_name = "RADIUS", default_value_t = 0)]
+    pub smooth: u32,
+
+    /// How the hillshade combines with the colors: multiply, overlay, soft-light,
+    /// or hsv-value (replace the brightness of each color with the shade)
+    #[arg(long, default_value = "multiply", value_parser = ["multiply", "overlay", "soft-light", "hsv-value"])]
+    pub blend_mode: String,
+
+    /// How much of the hillshade blend shows, from 0 (colors only) to 1 (fully shaded)
+    #[arg(long, default_value_t = 1.0, value_parser = parse_opacity)]
+    pub hillshade_opacity: f64,
+
+    /// Fade hillshade and color-relief images toward --haze-color with distance from the
+    /// viewer, for panorama-like relief; the farthest terrain gets this much haze, from 0 to 1
+    #[arg(long, value_name = "STRENGTH", value_parser = parse_opacity)]
+    pub aerial_perspective: Option<f64>,
+
+    /// Color distant terrain fades toward with --aerial-perspective, as R,G,B or #RRGGBB
+    #[arg(long, default_value = "200,215,230", value_parser = parse_color)]
+    pub haze_color: Rgb<u8>,
+
+    /// What makes terrain distant under --aerial-perspective: elevation (low ground fades,
+    /// as seen from above) or distance (northern ground fades, as seen from the southern edge)
+    #[arg(long, default_value = "elevation", value_parser = ["elevation", "distance"])]
+    pub haze_by: String,
+
+    /// Radius in cells of the window each cell is compared with in tpi mode
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    pub tpi_radius: u32,
+
+    /// Curvature rendered in curvature mode: profile (along the slope), plan (across it) or total
+    #[arg(long, default_value = "total", value_parser = ["profile", "plan", "total"])]
+    pub curvature_type: String,
+
+    /// Slopes in degrees at which slope-class mode starts a new class, in ascending order
+    #[arg(long, value_name = "DEGREES", default_value = "5,15,30", value_delimiter = ',')]
+    pub slope_breaks: Vec<f64>,
+
+    /// Colors of the slope classes from flattest to steepest, one more than --slope-breaks,
+    /// as R,G,B or #RRGGBB
+    #[arg(long, num_args = 1.., default_values = ["#1a9850", "#a6d96a", "#fdae61", "#d73027"], value_parser = parse_color)]
+    pub slope_colors: Vec<Rgb<u8>>,
+
+    /// Distance in cells searched for the horizon in svf mode
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub svf_radius: u32,
+
+    /// Number of azimuth directions sampled for the horizon in svf mode
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..))]
+    pub svf_directions: u32,
+
+    /// Distance in cells searched for sheltering terrain in occlusion mode
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub ao_radius: u32,
+
+    /// How strongly sheltering terrain darkens cells in occlusion mode (1 = natural)
+    #[arg(long, default_value_t = 1.0, value_parser = parse_ao_strength)]
+    pub ao_strength: f64,
+
+    /// Stretch between the P and 100-P percentiles of each file, clamping outliers (0-50)
+    #[arg(long, value_name = "P", default_value_t = 0.0, value_parser = parse_clip_percent)]
+    pub clip_percent: f64,
+
+    /// Elevation stretch: linear, log to bring out detail in low-lying areas, or equalize to
+    /// spread gray levels evenly over the elevations present (histogram equalization)
+    #[arg(long, default_value = "linear", value_parser = ["linear", "log", "equalize"])]
+    pub normalize: String,
+
+    /// Elevation mapped to black, shared by every file (defaults to each file's minimum)
+    #[arg(long)]
+    pub min_elev: Option<f64>,
+
+    /// Elevation mapped to white, shared by every file (defaults to each file's maximum)
+    #[arg(long)]
+    pub max_elev: Option<f64>,
+
+    /// Gray level (0-255) of every cell in a flat file, where all elevations are equal and
+    /// there is no range to stretch
+    #[arg(long, value_name = "LEVEL", default_value_t = 128)]
+    pub flat_level: u8,
+
+    /// Opaque color for NoData cells as R,G,B or #RRGGBB (transparent by default)
+    #[arg(long, value_parser = parse_color)]
+    pub nodata_color: Option<Rgb<u8>>,
+
+    /// How NoData cells are handled: transparent (black in JPEG, or --nodata-color), black
+    /// (opaque in every format), skip-file (fail files with any NoData, for QC) or mean-fill
+    /// (replace NoData with the mean elevation before rendering)
+    #[arg(long, default_value = "transparent", value_parser = ["transparent", "black", "skip-file", "mean-fill"])]
+    pub nodata_policy: String,
+
+    /// Also make cells within this many cells of NoData transparent, trimming the ragged
+    /// fringe around voids and tile edges (0 disables)
+    #[arg(long, value_name = "CELLS", default_value_t = 0)]
+    pub edge_trim: u32,
+
+    /// Gamma correction of the final image; values above 1 brighten the midtones
+    #[arg(long, value_name = "G", default_value_t = 1.0, value_parser = parse_gamma)]
+    pub gamma: f64,
+
+    /// Paint cells at or below this elevation as flat water in grayscale, hillshade and color-relief modes
+    #[arg(long, value_name = "E")]
+    pub water_level: Option<f64>,
+
+    /// Color of water cells, as R,G,B or #RRGGBB
+    #[arg(long, default_value = "120,170,210", value_parser = parse_color)]
+    pub water_color: Rgb<u8>,
+
+    /// Draw contour lines every INTERVAL elevation units over PNG, JPEG and WebP outputs
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_contour_interval)]
+    pub contours: Option<f64>,
+
+    /// Draw contours on the image, or write them to <name>_contours.geojson as LineStrings with an elevation
+    #[arg(long, default_value = "image", value_parser = ["image", "geojson"])]
+    pub contour_format: String,
+
+    /// Draw every Nth contour thicker as an index contour (0 disables)
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub index_contour: u32,
+
+    /// Color of contour lines, as R,G,B or #RRGGBB
+    #[arg(long, default_value = "120,80,40", value_parser = parse_color)]
+    pub contour_color: Rgb<u8>,
+}
+
+impl Args {
+    /// Parses the command line like [`Parser::parse`], with the settings of `--config` it
+    /// doesn't give itself, then fills in the settings of `--preset`.
+    pub fn parse_with_preset() -> Self {
+        let mut argv: Vec<OsString> = env::args_os().collect();
+        // The config file is found first, since it may hold options the command line needs.
+        let given = Self::command().ignore_errors(true).get_matches_from(&argv);
+        if let Some(path) = given.get_one::<PathBuf>("config") {
+            let settings = config_arguments(path, &Self::command(), |id| {
+                given.value_source(id) == Some(ValueSource::CommandLine)
+            })
+            .unwrap_or_else(|e| Self::command().error(ErrorKind::Io, format!("{:#}", e)).exit());
+            argv.splice(1..1, settings);
+        }
+        let matches = Self::command().get_matches_from(argv);
+        let mut args = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        args.apply_preset(&matches);
+        args
+    }
+
+    /// Sets the options of the selected preset that weren't given on the command line.
+    pub fn apply_preset(&mut self, matches: &ArgMatches) {
+        let unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+        if self.preset.as_deref() == Some("topo") {
+            if unset("mode") {
+                self.mode = vec!["hillshade".to_string()];
+            }
+            if unset("colormap") {
+                self.colormap = "terrain".to_string();
+            }
+            if unset("blend_mode") {
+                self.blend_mode = "hsv-value".to_string();
+            }
+            if unset("hillshade_opacity") {
+                self.hillshade_opacity = 0.6;
+            }
+            if unset("contours") {
+                self.contours = Some(20.0);
+            }
+        }
+    }
+
+    /// Returns the log level selected by the `-v` and `-q` flags, starting from `info`.
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (0, 0) => LevelFilter::Info,
+            (0, 1) => LevelFilter::Debug,
+            (0, _) => LevelFilter::Trace,
+            (1, _) => LevelFilter::Error,
+            _ => LevelFilter::Off,
+        }
+    }
+
+    /// Returns the deepest directory level to walk, or `None` for no limit.
+    pub fn walk_depth(&self) -> Option<usize> {
+        if self.no_recursive {
+            Some(1)
+        } else {
+            self.max_depth
+        }
+    }
+}
+
+/// Parses an azimuth in degrees, which must lie in 0-360.
+pub fn parse_azimuth(s: &str) -> Result<f64, String> {
+    parse_in_range(s, 0.0, 360.0)
+}
+
+/// Parses a `START:END:STEP` azimuth sweep, whose start and end must lie in 0-360 with the
+/// start below the end, and whose step must be greater than 0.
+pub fn parse_azimuth_sweep(s: &str) -> Result<[f64; 3], String> {
+    let parts: Vec<&str> = s.split(':').collect();
+    let [start, end, step] = parts[..] else {
+        return Err(format!("invalid azimuth sweep '{}', expected START:END:STEP", s));
+    };
+    let (start, end, step) = (parse_azimuth(start)?, parse_azimuth(end)?, parse_positive(step)?);
+    if start >= end {
+        return Err(format!("the sweep must start below its end ({} >= {})", start, end));
+    }
+    Ok([start, end, step])
+}
+
+/// Parses an `AZIMUTH:COLOR` light, whose azimuth must lie in 0-360 and whose color is
+/// `R,G,B` or `#RRGGBB`.
+pub fn parse_colored_light(s: &str) -> Result<(f64, Rgb<u8>), String> {
+    let (azimuth, color) = s
+        .split_once(':')
+        .ok_or_else(|| format!("invalid light '{}', expected AZIMUTH:COLOR", s))?;
+    Ok((parse_azimuth(azimuth)?, parse_color(color)?))
+}
+
+/// Parses an altitude in degrees, which must lie in 0-90.
+pub fn parse_altitude(s: &str) -> Result<f64, String> {
+    parse_in_range(s, 0.0, 90.0)
+}
+
+/// Parses a clipping percentage, which must lie in 0-50.
+pub fn parse_clip_percent(s: &str) -> Result<f64, String> {
+    parse_in_range(s, 0.0, 50.0)
+}
+
+/// Parses a contour interval, which must be positive.
+pub fn parse_contour_interval(s: &str) -> Result<f64, String> {
+    parse_positive(s)
+}
+
+/// Parses a cell size, which must be positive.
+pub fn parse_cell_size(s: &str) -> Result<f64, String> {
+    parse_positive(s)
+}
+
+/// Parses a gamma value, which must be positive.
+pub fn parse_gamma(s: &str) -> Result<f64, String> {
+    parse_positive(s)
+}
+
+/// Parses an ambient-occlusion strength, which must not be negative.
+pub fn parse_ao_strength(s: &str) -> Result<f64, String> {
+    parse_in_range(s, 0.0, f64::INFINITY)
+}
+
+/// Parses a sink-fill tolerance, which must not be negative.
+pub fn parse_fill_tolerance(s: &str) -> Result<f64, String> {
+    parse_in_range(s, 0.0, f64::INFINITY)
+}
+
+/// Parses an opacity, which must lie in 0-1.
+pub fn parse_opacity(s: &str) -> Result<f64, String> {
+    parse_in_range(s, 0.0, 1.0)
+}
+
+/// Parses an output bit depth, which must be 8 or 16.
+pub fn parse_bit_depth(s: &str) -> Result<u8, String> {
+    match s {
+        "8" => Ok(8),
+        "16" => Ok(16),
+        _ => Err(format!("'{}' is not a supported bit depth (8 or 16)", s)),
+    }
+}
+
+/// Parses an `EPSG:xxxx` coordinate system code.
+pub fn parse_crs(s: &str) -> Result<Crs, String> {
+    s.parse().map_err(|e: anyhow::Error| e.to_string())
+}
+
+/// Parses a number that must be greater than zero.
+fn parse_positive(s: &str) -> Result<f64, String> {
+    let value = parse_in_range(s, 0.0, f64::INFINITY)?;
+    if value == 0.0 {
+        return Err("the value must be greater than 0".to_string());
+    }
+    Ok(value)
+}
+
+/// Parses a number that must lie within `min..=max`.
+fn parse_in_range(s: &str, min: f64, max: f64) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| format!("'{}' is not a number", s))?;
+    if !(min..=max).contains(&value) {
+        return Err(format!("{} is outside the allowed range {}-{}", value, min, max));
+    }
+    Ok(value)
+}
+
+/// Parses a `MINX,MINY,MAXX,MAXY` bounding box, whose minimums must lie below its maximums.
+pub fn parse_bbox(s: &str) -> Result<[f64; 4], String> {
+    let invalid = || format!("invalid bounding box '{}', expected MINX,MINY,MAXX,MAXY", s);
+    let bounds: Vec<f64> = s
+        .split(',')
+        .map(|c| c.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    match bounds[..] {
+        [min_x, min_y, max_x, max_y] if min_x < max_x && min_y < max_y => Ok([min_x, min_y, max_x, max_y]),
+        [_, _, _, _] => Err(format!("the minimums of bounding box '{}' must lie below its maximums", s)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses an `X,Y,W,H` pixel rectangle, whose width and height must be greater than 0.
+pub fn parse_crop_pixels(s: &str) -> Result<[u32; 4], String> {
+    let invalid = || format!("invalid pixel rectangle '{}', expected X,Y,W,H", s);
+    let numbers: Vec<u32> = s
+        .split(',')
+        .map(|c| c.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    match numbers[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok([x, y, width, height]),
+        [_, _, _, _] => Err(format!("pixel rectangle '{}' must be at least 1x1", s)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses a `WxH` canvas size, both of which must be greater than 0.
+pub fn parse_canvas(s: &str) -> Result<[u32; 2], String> {
+    let size = s.split_once(['x', 'X']).and_then(|(w, h)| Some([w.trim().parse().ok()?, h.trim().parse().ok()?]));
+    match size {
+        Some([width, height]) if width > 0 && height > 0 => Ok([width, height]),
+        Some(_) => Err(format!("canvas '{}' must be at least 1x1", s)),
+        None => Err(format!("invalid canvas size '{}', expected WxH such as 1920x1080", s)),
+    }
+}
+
+/// Parses a color given as `R,G,B` or `#RRGGBB`.
+pub fn parse_color(s: &str) -> Result<Rgb<u8>, String> {
+    let invalid = || format!("invalid color '{}', expected R,G,B or #RRGGBB", s);
+
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        return Ok(Rgb([channel(0)?, channel(2)?, channel(4)?]));
+    }
+
+    let channels: Vec<u8> = s
+        .split(',')
+        .map(|c| c.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    match channels[..] {
+        [r, g, b] => Ok(Rgb([r, g, b])),
+        _ => Err(invalid()),
+    }
 }
@@ -0,0 +1,105 @@
+use crate::colormaps::Colormap;
+use anyhow::{bail, Context, Result};
+use image::{Rgb, Rgba};
+use std::fs;
+use std::path::Path;
+
+/// Where a color-relief entry sits on the elevation axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReliefValue {
+    /// An absolute elevation.
+    Elevation(f64),
+    /// A percentage of the way from the grid minimum to its maximum.
+    Percent(f64),
+}
+
+/// A color table in the format read by `gdaldem color-relief`.
+///
+/// Each line is `value R G B [A]`, where `value` is an elevation, a percentage such as `50%`,
+/// or `nv` for the NoData color. Fields may be separated by spaces, tabs, commas or colons,
+/// and `#` starts a comment. Alpha is only used for the NoData color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorRelief {
+    entries: Vec<(ReliefValue, Rgb<u8>)>,
+    nodata: Option<Rgba<u8>>,
+}
+
+impl ColorRelief {
+    /// Reads a color-relief file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("reading color file {:?}", path))?;
+        Self::parse(&text).with_context(|| format!("parsing color file {:?}", path))
+    }
+
+    /// Parses the contents of a color-relief file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut nodata = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let fields: Vec<&str> = line
+                .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+                .filter(|f| !f.is_empty())
+                .collect();
+            if fields.is_empty() {
+                continue;
+            }
+            if !(4..=5).contains(&fields.len()) {
+                bail!("line {}: expected `value R G B [A]`, found '{}'", i + 1, line.trim());
+            }
+
+            let channel = |f: &str| {
+                f.parse::<u8>()
+                    .with_context(|| format!("line {}: invalid color component '{}'", i + 1, f))
+            };
+            let color = Rgba([
+                channel(fields[1])?,
+                channel(fields[2])?,
+                channel(fields[3])?,
+                fields.get(4).map_or(Ok(255), |f| channel(f))?,
+            ]);
+
+            let value = fields[0];
+            if value.eq_ignore_ascii_case("nv") {
+                nodata = Some(color);
+                continue;
+            }
+            let position = match value.strip_suffix('%') {
+                Some(percent) => ReliefValue::Percent(percent.parse().with_context(|| {
+                    format!("line {}: invalid percentage '{}'", i + 1, value)
+                })?),
+                None => ReliefValue::Elevation(value.parse().with_context(|| {
+                    format!("line {}: invalid elevation '{}'", i + 1, value)
+                })?),
+            };
+            entries.push((position, Rgb([color[0], color[1], color[2]])));
+        }
+
+        if entries.is_empty() {
+            bail!("no color entries found");
+        }
+        Ok(Self { entries, nodata })
+    }
+
+    /// The color given to NoData cells by an `nv` line, if any.
+    pub fn nodata_color(&self) -> Option<Rgba<u8>> {
+        self.nodata
+    }
+
+    /// Resolves the entries to a colormap sampled by elevation, for a grid spanning `min..=max`.
+    pub fn to_colormap(&self, min: f64, max: f64) -> Colormap {
+        let stops = self
+            .entries
+            .iter()
+            .map(|&(value, color)| {
+                let elevation = match value {
+                    ReliefValue::Elevation(e) => e,
+                    ReliefValue::Percent(p) => min + (max - min) * p / 100.0,
+                };
+                (elevation, color)
+            })
+            .collect();
+        Colormap::new(stops)
+    }
+}
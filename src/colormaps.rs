@@ -0,0 +1,142 @@
+use image::Rgb;
+
+/// Names accepted by [`Colormap::by_name`].
+pub const NAMES: [&str; 5] = ["classic", "viridis", "magma", "terrain", "grayscale"];
+
+/// The original red-green-blue ramp of this tool.
+const CLASSIC: &[(f64, [u8; 3])] = &[(0.0, [0, 127, 128]), (0.5, [127, 255, 64]), (1.0, [255, 127, 0])];
+
+/// Matplotlib's viridis, sampled every eighth.
+const VIRIDIS: &[(f64, [u8; 3])] = &[
+    (0.0, [68, 1, 84]),
+    (0.125, [72, 40, 120]),
+    (0.25, [62, 73, 137]),
+    (0.375, [49, 104, 142]),
+    (0.5, [38, 130, 142]),
+    (0.625, [31, 158, 137]),
+    (0.75, [53, 183, 121]),
+    (0.875, [110, 206, 88]),
+    (1.0, [253, 231, 37]),
+];
+
+/// Matplotlib's magma, sampled every eighth.
+const MAGMA: &[(f64, [u8; 3])] = &[
+    (0.0, [0, 0, 4]),
+    (0.125, [28, 16, 68]),
+    (0.25, [79, 18, 123]),
+    (0.375, [129, 37, 129]),
+    (0.5, [181, 54, 122]),
+    (0.625, [229, 80, 100]),
+    (0.75, [251, 135, 97]),
+    (0.875, [254, 194, 135]),
+    (1.0, [252, 253, 191]),
+];
+
+/// Green lowlands through tan and brown highlands to white peaks.
+const TERRAIN: &[(f64, [u8; 3])] = &[
+    (0.0, [32, 128, 64]),
+    (0.25, [120, 180, 80]),
+    (0.5, [230, 220, 140]),
+    (0.75, [140, 100, 70]),
+    (0.9, [190, 180, 170]),
+    (1.0, [255, 255, 255]),
+];
+
+/// Hypsometric tints keyed to elevation in meters, with sea level in blue-green.
+const HYPSOMETRIC: &[(f64, [u8; 3])] = &[
+    (-500.0, [0, 60, 120]),
+    (0.0, [0, 150, 140]),
+    (50.0, [60, 160, 80]),
+    (300.0, [150, 190, 90]),
+    (800.0, [220, 210, 130]),
+    (1500.0, [170, 120, 70]),
+    (2500.0, [140, 120, 110]),
+    (4000.0, [255, 255, 255]),
+];
+
+/// Blue through white to red, for values diverging from zero in either direction.
+const DIVERGING: &[(f64, [u8; 3])] = &[
+    (-1.0, [33, 102, 172]),
+    (-0.5, [103, 169, 207]),
+    (0.0, [247, 247, 247]),
+    (0.5, [239, 138, 98]),
+    (1.0, [178, 24, 43]),
+];
+
+const GRAYSCALE: &[(f64, [u8; 3])] = &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])];
+
+/// A color ramp defined by control points, linearly interpolated in between.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colormap {
+    /// Control points as `(position, color)`, sorted by position.
+    stops: Vec<(f64, Rgb<u8>)>,
+}
+
+impl Default for Colormap {
+    fn default() -> Self {
+        Self::from_table(CLASSIC)
+    }
+}
+
+impl Colormap {
+    /// Creates a colormap from control points; they are sorted by position.
+    ///
+    /// # Panics
+    /// Panics if `stops` is empty.
+    pub fn new(mut stops: Vec<(f64, Rgb<u8>)>) -> Self {
+        assert!(!stops.is_empty(), "a colormap needs at least one control point");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    /// Looks up one of the built-in colormaps listed in [`NAMES`].
+    pub fn by_name(name: &str) -> Option<Self> {
+        let table = match name {
+            "classic" => CLASSIC,
+            "viridis" => VIRIDIS,
+            "magma" => MAGMA,
+            "terrain" => TERRAIN,
+            "grayscale" => GRAYSCALE,
+            _ => return None,
+        };
+        Some(Self::from_table(table))
+    }
+
+    /// A ramp keyed to absolute elevations in meters rather than normalized values, so the
+    /// same elevation gets the same color in every tile.
+    pub fn hypsometric() -> Self {
+        Self::from_table(HYPSOMETRIC)
+    }
+
+    /// A blue-white-red ramp centered at zero that reaches full color at `-limit` and `limit`.
+    pub fn diverging(limit: f64) -> Self {
+        let limit = limit.max(f64::EPSILON);
+        Self::new(DIVERGING.iter().map(|&(t, c)| (t * limit, Rgb(c))).collect())
+    }
+
+    /// Returns the colormap running the other way over the same range of positions.
+    pub fn reversed(&self) -> Self {
+        let (first, last) = (self.stops[0].0, self.stops[self.stops.len() - 1].0);
+        Self::new(self.stops.iter().map(|&(pos, color)| (first + last - pos, color)).collect())
+    }
+
+    fn from_table(table: &[(f64, [u8; 3])]) -> Self {
+        Self::new(table.iter().map(|&(t, c)| (t, Rgb(c))).collect())
+    }
+
+    /// Returns the color at `t`, clamped to the first and last control points.
+    pub fn sample(&self, t: f64) -> Rgb<u8> {
+        let upper = self.stops.partition_point(|&(pos, _)| pos < t);
+        if upper == 0 {
+            return self.stops[0].1;
+        }
+        let Some(&(hi_pos, hi)) = self.stops.get(upper) else {
+            return self.stops[self.stops.len() - 1].1;
+        };
+        let (lo_pos, lo) = self.stops[upper - 1];
+
+        let f = (t - lo_pos) / (hi_pos - lo_pos);
+        let lerp = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * f) as u8;
+        Rgb([lerp(lo[0], hi[0]), lerp(lo[1], hi[1]), lerp(lo[2], hi[2])])
+    }
+}
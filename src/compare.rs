@@ -0,0 +1,52 @@
+use anyhow::{bail, Result};
+use image::{DynamicImage, GenericImageView, GrayImage, Luma};
+
+/// How much two images differ, in 8-bit levels over every channel of every pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ImageDiff {
+    /// Root-mean-square difference of the channels.
+    pub rms: f64,
+    /// Largest difference of any channel.
+    pub max: f64,
+    /// Number of pixels with any channel differing.
+    pub differing: usize,
+    /// Number of pixels compared.
+    pub pixels: usize,
+}
+
+/// Compares two images of the same size as RGBA and returns their differences along with
+/// an image of the largest channel difference of each pixel.
+///
+/// The difference image is stretched so the largest difference is white, making small
+/// changes visible; identical images give a black image. 16-bit images keep their precision.
+pub fn compare_images(a: &DynamicImage, b: &DynamicImage) -> Result<(ImageDiff, GrayImage)> {
+    if a.dimensions() != b.dimensions() {
+        let ((aw, ah), (bw, bh)) = (a.dimensions(), b.dimensions());
+        bail!("can't compare a {}x{} image with a {}x{} one", aw, ah, bw, bh);
+    }
+    let (a, b) = (a.to_rgba32f(), b.to_rgba32f());
+    let mut largest = Vec::with_capacity(a.len() / 4);
+    let mut sum_squares = 0.0;
+    for (p, q) in a.pixels().zip(b.pixels()) {
+        let mut pixel_max: f64 = 0.0;
+        for (&x, &y) in p.0.iter().zip(&q.0) {
+            let difference = (x as f64 - y as f64).abs() * 255.0;
+            sum_squares += difference * difference;
+            pixel_max = pixel_max.max(difference);
+        }
+        largest.push(pixel_max);
+    }
+
+    let max = largest.iter().copied().fold(0.0, f64::max);
+    let diff = ImageDiff {
+        rms: (sum_squares / (largest.len() * 4).max(1) as f64).sqrt(),
+        max,
+        differing: largest.iter().filter(|&&difference| difference > 0.0).count(),
+        pixels: largest.len(),
+    };
+    let scale = if max > 0.0 { 255.0 / max } else { 0.0 };
+    let image = GrayImage::from_fn(a.width(), a.height(), |x, y| {
+        Luma([(largest[(y * a.width() + x) as usize] * scale).round() as u8])
+    });
+    Ok((diff, image))
+}
@@ -0,0 +1,72 @@
+use anyhow::{bail, Context, Result};
+use clap::Command;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+/// A setting of a config file: a flag, a value, or the values of a repeatable option.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Setting {
+    Flag(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    List(Vec<Setting>),
+}
+
+/// Reads a TOML or JSON (by extension) config file of command-line settings and returns
+/// them as arguments of `command`, leaving out those `given` says were set on the command line.
+///
+/// Keys are long option names, with dashes or underscores, and take values as written on the
+/// command line: `colormap = "terrain"`, `contour_color = "120,80,40"`, `mode = ["hillshade",
+/// "slope"]`. Flags are `true` or `false`, and `verbose` also takes a count.
+pub fn config_arguments(path: &Path, command: &Command, given: impl Fn(&str) -> bool) -> Result<Vec<OsString>> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading config {:?}", path))?;
+    let settings: BTreeMap<String, Setting> = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str(&text).with_context(|| format!("parsing config {:?}", path))?,
+        _ => toml::from_str(&text).with_context(|| format!("parsing config {:?}", path))?,
+    };
+
+    let mut arguments = Vec::new();
+    for (key, setting) in settings {
+        let long = key.replace('_', "-");
+        let Some(arg) = command.get_arguments().find(|arg| arg.get_long() == Some(long.as_str())) else {
+            bail!("unknown setting '{}' in config {:?}", key, path);
+        };
+        if long == "config" {
+            bail!("config {:?} can't name another config file", path);
+        }
+        if given(arg.get_id().as_str()) {
+            continue;
+        }
+        let flag = format!("--{}", long);
+        match (setting, arg.get_action().takes_values()) {
+            (Setting::Flag(set), false) => arguments.extend(set.then(|| flag.clone().into())),
+            (Setting::Integer(count), false) if count >= 0 => {
+                arguments.extend((0..count).map(|_| flag.clone().into()));
+            }
+            (_, false) => bail!("'{}' in config {:?} is a flag; set it to true or false", key, path),
+            (Setting::List(values), true) => {
+                for value in values {
+                    arguments.push(format!("{}={}", flag, value_text(&key, value, path)?).into());
+                }
+            }
+            (value, true) => arguments.push(format!("{}={}", flag, value_text(&key, value, path)?).into()),
+        }
+    }
+    Ok(arguments)
+}
+
+/// Writes a single setting as it would be typed on the command line.
+fn value_text(key: &str, setting: Setting, path: &Path) -> Result<String> {
+    Ok(match setting {
+        Setting::Flag(value) => value.to_string(),
+        Setting::Integer(value) => value.to_string(),
+        Setting::Float(value) => value.to_string(),
+        Setting::Text(value) => value,
+        Setting::List(_) => bail!("'{}' in config {:?} can't hold nested lists", key, path),
+    })
+}
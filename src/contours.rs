@@ -0,0 +1,224 @@
+use crate::crs::Crs;
+use crate::geotiff;
+use crate::processor::ElevationGrid;
+use anyhow::Result;
+use image::{Rgb, RgbImage};
+use imageproc::drawing::draw_line_segment_mut;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// A piece of a contour line crossing one 2x2 block of cells.
+///
+/// Points are fractional `(row, col)` grid positions, where whole numbers are cell centers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContourSegment {
+    pub elevation: f64,
+    pub start: (f64, f64),
+    pub end: (f64, f64),
+}
+
+/// A contour line made of joined segments, as fractional `(row, col)` grid positions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourLine {
+    pub elevation: f64,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Traces contour lines every `interval` elevation units with marching squares.
+///
+/// Blocks touching a NoData cell are skipped. Saddles are resolved by the mean of the four
+/// corners, so the lines never cross.
+pub fn trace_contours(grid: &ElevationGrid, interval: f64) -> Vec<ContourSegment> {
+    let mut segments = Vec::new();
+    if grid.rows() < 2 || grid.cols() < 2 {
+        return segments;
+    }
+    let (min, max) = grid.value_range();
+
+    // Levels are counted in whole intervals; adding up the interval would drift.
+    let mut k = (min / interval).ceil() as i64;
+    loop {
+        let level = contour_level(k, interval);
+        if level > max {
+            break;
+        }
+        for row in 0..grid.rows() - 1 {
+            for col in 0..grid.cols() - 1 {
+                trace_block(grid, row, col, level, &mut segments);
+            }
+        }
+        k += 1;
+    }
+
+    segments
+}
+
+/// Returns the `k`th multiple of `interval`, rounded to the decimals the interval is
+/// written with so that levels such as 1.2 don't come out as 1.2000000000000002.
+fn contour_level(k: i64, interval: f64) -> f64 {
+    let decimals = interval.to_string().split_once('.').map_or(0, |(_, fraction)| fraction.len());
+    let scale = 10f64.powi(decimals as i32);
+    (k as f64 * interval * scale).round() / scale
+}
+
+/// Adds the segments of one contour level crossing the block whose top-left cell is `(row, col)`.
+fn trace_block(grid: &ElevationGrid, row: usize, col: usize, level: f64, segments: &mut Vec<ContourSegment>) {
+    // Corners clockwise from the top left.
+    let corners = [(row, col), (row, col + 1), (row + 1, col + 1), (row + 1, col)];
+    let z = corners.map(|(r, c)| grid.values[r][c]);
+    if z.iter().any(|&v| grid.is_nodata(v)) {
+        return;
+    }
+
+    // Where the level crosses the edge from corner `a` to corner `b`.
+    let crossing = |a: usize, b: usize| {
+        let t = (level - z[a]) / (z[b] - z[a]);
+        let (ra, ca) = corners[a];
+        let (rb, cb) = corners[b];
+        (ra as f64 + (rb as f64 - ra as f64) * t, ca as f64 + (cb as f64 - ca as f64) * t)
+    };
+    let top = || crossing(0, 1);
+    let right = || crossing(1, 2);
+    let bottom = || crossing(3, 2);
+    let left = || crossing(0, 3);
+
+    let above = z.map(|v| v >= level);
+    let case = above.iter().fold(0, |case, &a| case << 1 | a as u8);
+    let center_above = z.iter().sum::<f64>() / 4.0 >= level;
+
+    let mut add = |start, end| segments.push(ContourSegment { elevation: level, start, end });
+    match case {
+        0b0001 | 0b1110 => add(left(), bottom()),
+        0b0010 | 0b1101 => add(bottom(), right()),
+        0b0011 | 0b1100 => add(left(), right()),
+        0b0100 | 0b1011 => add(top(), right()),
+        0b0110 | 0b1001 => add(top(), bottom()),
+        0b0111 | 0b1000 => add(left(), top()),
+        // Saddles: the corners on the same side as the center are connected through it.
+        0b0101 if center_above => {
+            add(left(), top());
+            add(bottom(), right());
+        }
+        0b0101 => {
+            add(top(), right());
+            add(left(), bottom());
+        }
+        0b1010 if center_above => {
+            add(top(), right());
+            add(left(), bottom());
+        }
+        0b1010 => {
+            add(left(), top());
+            add(bottom(), right());
+        }
+        _ => {}
+    }
+}
+
+/// Draws contour segments onto an image rendered from `grid`.
+///
+/// Every `index_every`th contour (counting from elevation zero) is drawn thicker; 0 disables
+/// index contours.
+pub fn draw_contours(
+    image: &mut RgbImage,
+    grid: &ElevationGrid,
+    segments: &[ContourSegment],
+    interval: f64,
+    index_every: u32,
+    color: Rgb<u8>,
+) {
+    for segment in segments {
+        let (x0, y0) = grid.image_position(segment.start.0, segment.start.1);
+        let (x1, y1) = grid.image_position(segment.end.0, segment.end.1);
+        let is_index = index_every > 0 && ((segment.elevation / interval).round() as i64) % index_every as i64 == 0;
+        let offsets: &[(f64, f64)] = if is_index { &[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)] } else { &[(0.0, 0.0)] };
+        for &(dx, dy) in offsets {
+            draw_line_segment_mut(
+                image,
+                ((x0 + dx) as f32, (y0 + dy) as f32),
+                ((x1 + dx) as f32, (y1 + dy) as f32),
+                color,
+            );
+        }
+    }
+}
+
+/// Joins segments that share endpoints into continuous lines, one contour level at a time.
+///
+/// Neighboring blocks compute a shared crossing from the same two cells, so their endpoints
+/// match exactly.
+pub fn join_segments(segments: &[ContourSegment]) -> Vec<ContourLine> {
+    let key = |(row, col): (f64, f64)| (row.to_bits(), col.to_bits());
+    let mut lines = Vec::new();
+
+    let mut levels: Vec<f64> = segments.iter().map(|s| s.elevation).collect();
+    levels.dedup();
+    for level in levels {
+        let level_segments: Vec<&ContourSegment> = segments.iter().filter(|s| s.elevation == level).collect();
+        let mut by_endpoint: HashMap<_, Vec<usize>> = HashMap::new();
+        for (i, segment) in level_segments.iter().enumerate() {
+            by_endpoint.entry(key(segment.start)).or_default().push(i);
+            by_endpoint.entry(key(segment.end)).or_default().push(i);
+        }
+
+        let mut used = vec![false; level_segments.len()];
+        // Returns the far end of an unused segment touching `point`, marking it used.
+        let mut next = |point: (f64, f64), used: &mut Vec<bool>| {
+            let i = *by_endpoint.get_mut(&key(point))?.iter().find(|&&i| !used[i])?;
+            used[i] = true;
+            let segment = level_segments[i];
+            Some(if key(segment.start) == key(point) { segment.end } else { segment.start })
+        };
+
+        for i in 0..level_segments.len() {
+            if used[i] {
+                continue;
+            }
+            used[i] = true;
+            let mut points = vec![level_segments[i].start, level_segments[i].end];
+            while let Some(point) = next(points[points.len() - 1], &mut used) {
+                points.push(point);
+            }
+            let mut head = Vec::new();
+            while let Some(point) = next(head.last().copied().unwrap_or(points[0]), &mut used) {
+                head.push(point);
+            }
+            head.reverse();
+            head.extend(points);
+            lines.push(ContourLine { elevation: level, points: head });
+        }
+    }
+
+    lines
+}
+
+/// Writes contour lines as a GeoJSON feature collection of LineStrings in map coordinates,
+/// each with an `elevation` property, naming `crs` if known.
+pub fn write_geojson(path: &Path, grid: &ElevationGrid, lines: &[ContourLine], crs: Option<Crs>) -> Result<()> {
+    let transform = grid.geotransform();
+    let mut features = Vec::with_capacity(lines.len());
+    for line in lines {
+        let mut coordinates = String::new();
+        for (i, &(row, col)) in line.points.iter().enumerate() {
+            let (px, py) = grid.image_position(row, col);
+            let (x, y) = geotiff::pixel_to_map(&transform, px, py);
+            if i > 0 {
+                coordinates.push(',');
+            }
+            write!(coordinates, "[{},{}]", x, y)?;
+        }
+        features.push(format!(
+            r#"{{"type":"Feature","properties":{{"elevation":{}}},"geometry":{{"type":"LineString","coordinates":[{}]}}}}"#,
+            line.elevation, coordinates
+        ));
+    }
+    // RFC 7946 dropped the crs member, but GIS tools still read it for projected coordinates.
+    let crs = match crs {
+        Some(crs) => format!(r#""crs":{{"type":"name","properties":{{"name":"urn:ogc:def:crs:EPSG::{}"}}}},"#, crs.epsg()),
+        None => String::new(),
+    };
+    fs::write(path, format!(r#"{{"type":"FeatureCollection",{}"features":[{}]}}"#, crs, features.join(",\n")))?;
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use anyhow::{bail, Context, Result};
+use std::f64::consts::{FRAC_PI_4, PI};
+use std::fmt;
+use std::str::FromStr;
+
+/// Semi-major axis of the WGS84 ellipsoid in meters.
+const WGS84_A: f64 = 6_378_137.0;
+/// Flattening of the WGS84 ellipsoid.
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+/// Scale factor on the central meridian of UTM zones.
+const UTM_K0: f64 = 0.9996;
+/// False easting of UTM zones in meters.
+const UTM_FALSE_EASTING: f64 = 500_000.0;
+/// False northing of southern UTM zones in meters.
+const UTM_FALSE_NORTHING_SOUTH: f64 = 10_000_000.0;
+
+/// WKT 1 definition of WGS84 longitude and latitude (EPSG:4326).
+const GEOGRAPHIC_WKT: &str = concat!(
+    r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],"#,
+    r#"AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],"#,
+    r#"UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]]"#,
+);
+/// ESRI definition of WGS84 longitude and latitude.
+const ESRI_GEOGRAPHIC_WKT: &str = concat!(
+    r#"GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984",SPHEROID["WGS_1984",6378137.0,298.257223563]],"#,
+    r#"PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]]"#,
+);
+/// PROJ.4 definition of web Mercator, which spells out the sphere it projects on.
+const WEB_MERCATOR_PROJ4: &str =
+    "+proj=merc +a=6378137 +b=6378137 +lat_ts=0 +lon_0=0 +x_0=0 +y_0=0 +k=1 +units=m +nadgrids=@null +wktext +no_defs";
+
+/// A coordinate reference system this crate can convert between, all on the WGS84 datum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Crs {
+    /// Longitude and latitude in degrees (EPSG:4326).
+    Geographic,
+    /// Spherical web Mercator in meters (EPSG:3857), used by slippy maps.
+    WebMercator,
+    /// A Universal Transverse Mercator zone in meters (EPSG:326xx north, EPSG:327xx south).
+    Utm { zone: u8, north: bool },
+}
+
+impl Crs {
+    /// The EPSG code of the system.
+    pub fn epsg(&self) -> u32 {
+        match *self {
+            Crs::Geographic => 4326,
+            Crs::WebMercator => 3857,
+            Crs::Utm { zone, north: true } => 32600 + zone as u32,
+            Crs::Utm { zone, north: false } => 32700 + zone as u32,
+        }
+    }
+
+    /// The EPSG name of the system, e.g. `WGS 84 / UTM zone 33N`.
+    pub fn name(&self) -> String {
+        match *self {
+            Crs::Geographic => "WGS 84".to_string(),
+            Crs::WebMercator => "WGS 84 / Pseudo-Mercator".to_string(),
+            Crs::Utm { zone, north } => format!("WGS 84 / UTM zone {}{}", zone, if north { 'N' } else { 'S' }),
+        }
+    }
+
+    /// The definition of the system as OGC well-known text (WKT 1), as GDAL writes it.
+    ///
+    /// Web Mercator projects the ellipsoid's coordinates as if they were on a sphere, which
+    /// plain WKT 1 can't say, so it carries a PROJ.4 extension; without it readers would place
+    /// it on the ellipsoid like EPSG:3395.
+    pub fn wkt(&self) -> String {
+        let (zone, north) = match *self {
+            Crs::Geographic => return GEOGRAPHIC_WKT.to_string(),
+            Crs::WebMercator => {
+                return format!(
+                    concat!(
+                        r#"PROJCS["WGS 84 / Pseudo-Mercator",{},PROJECTION["Mercator_1SP"],"#,
+                        r#"PARAMETER["central_meridian",0],PARAMETER["scale_factor",1],PARAMETER["false_easting",0],"#,
+                        r#"PARAMETER["false_northing",0],UNIT["metre",1,AUTHORITY["EPSG","9001"]],"#,
+                        r#"AXIS["Easting",EAST],AXIS["Northing",NORTH],EXTENSION["PROJ4","{}"],AUTHORITY["EPSG","3857"]]"#,
+                    ),
+                    GEOGRAPHIC_WKT,
+                    WEB_MERCATOR_PROJ4
+                );
+            }
+            Crs::Utm { zone, north } => (zone, north),
+        };
+        let false_northing = if north { 0.0 } else { UTM_FALSE_NORTHING_SOUTH };
+        format!(
+            concat!(
+                r#"PROJCS["{}",{},PROJECTION["Transverse_Mercator"],PARAMETER["latitude_of_origin",0],"#,
+                r#"PARAMETER["central_meridian",{}],"#,
+                r#"PARAMETER["scale_factor",{}],PARAMETER["false_easting",{}],PARAMETER["false_northing",{}],"#,
+                r#"UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],AXIS["Northing",NORTH],"#,
+                r#"AUTHORITY["EPSG","{}"]]"#,
+            ),
+            self.name(),
+            GEOGRAPHIC_WKT,
+            zone as f64 * 6.0 - 183.0,
+            UTM_K0,
+            UTM_FALSE_EASTING,
+            false_northing,
+            self.epsg()
+        )
+    }
+
+    /// The definition of the system in the ESRI dialect of well-known text that `.prj` files
+    /// use. ESRI readers go by the parameters alone, so web Mercator names its auxiliary sphere.
+    pub fn esri_wkt(&self) -> String {
+        let (name, projection, parameters) = match *self {
+            Crs::Geographic => return ESRI_GEOGRAPHIC_WKT.to_string(),
+            Crs::WebMercator => (
+                "WGS_1984_Web_Mercator_Auxiliary_Sphere".to_string(),
+                "Mercator_Auxiliary_Sphere",
+                vec![
+                    ("False_Easting", 0.0),
+                    ("False_Northing", 0.0),
+                    ("Central_Meridian", 0.0),
+                    ("Standard_Parallel_1", 0.0),
+                    ("Auxiliary_Sphere_Type", 0.0),
+                ],
+            ),
+            Crs::Utm { zone, north } => (
+                format!("WGS_1984_UTM_Zone_{}{}", zone, if north { 'N' } else { 'S' }),
+                "Transverse_Mercator",
+                vec![
+                    ("False_Easting", UTM_FALSE_EASTING),
+                    ("False_Northing", if north { 0.0 } else { UTM_FALSE_NORTHING_SOUTH }),
+                    ("Central_Meridian", zone as f64 * 6.0 - 183.0),
+                    ("Scale_Factor", UTM_K0),
+                    ("Latitude_Of_Origin", 0.0),
+                ],
+            ),
+        };
+        let parameters: String =
+            parameters.iter().map(|(key, value)| format!(r#"PARAMETER["{}",{:?}],"#, key, value)).collect();
+        format!(r#"PROJCS["{}",{},PROJECTION["{}"],{}UNIT["Meter",1.0]]"#, name, ESRI_GEOGRAPHIC_WKT, projection, parameters)
+    }
+
+    /// Converts coordinates in this system to longitude and latitude in degrees.
+    pub fn to_lon_lat(&self, x: f64, y: f64) -> (f64, f64) {
+        match *self {
+            Crs::Geographic => (x, y),
+            Crs::WebMercator => {
+                let lat = 2.0 * (y / WGS84_A).exp().atan() - PI / 2.0;
+                ((x / WGS84_A).to_degrees(), lat.to_degrees())
+            }
+            Crs::Utm { zone, north } => utm_inverse(zone, north, x, y),
+        }
+    }
+
+    /// Converts longitude and latitude in degrees to coordinates in this system.
+    pub fn from_lon_lat(&self, lon: f64, lat: f64) -> (f64, f64) {
+        match *self {
+            Crs::Geographic => (lon, lat),
+            Crs::WebMercator => {
+                let y = (FRAC_PI_4 + lat.to_radians() / 2.0).tan().ln();
+                (WGS84_A * lon.to_radians(), WGS84_A * y)
+            }
+            Crs::Utm { zone, north } => utm_forward(zone, north, lon, lat),
+        }
+    }
+
+    /// Converts coordinates in this system to coordinates in `target`.
+    pub fn transform(&self, target: &Crs, x: f64, y: f64) -> (f64, f64) {
+        if self == target {
+            return (x, y);
+        }
+        let (lon, lat) = self.to_lon_lat(x, y);
+        target.from_lon_lat(lon, lat)
+    }
+}
+
+impl FromStr for Crs {
+    type Err = anyhow::Error;
+
+    /// Parses an `EPSG:xxxx` code of a supported system.
+    fn from_str(s: &str) -> Result<Self> {
+        let code = s
+            .strip_prefix("EPSG:")
+            .or_else(|| s.strip_prefix("epsg:"))
+            .with_context(|| format!("expected a code like EPSG:3857, found '{}'", s))?;
+        let code: u32 = code.parse().with_context(|| format!("invalid EPSG code '{}'", s))?;
+        Ok(match code {
+            4326 => Crs::Geographic,
+            3857 => Crs::WebMercator,
+            32601..=32660 => Crs::Utm { zone: (code - 32600) as u8, north: true },
+            32701..=32760 => Crs::Utm { zone: (code - 32700) as u8, north: false },
+            _ => bail!("EPSG:{} is not supported (use 4326, 3857 or a WGS84 UTM zone)", code),
+        })
+    }
+}
+
+impl fmt::Display for Crs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EPSG:{}", self.epsg())
+    }
+}
+
+/// Squared eccentricity of the WGS84 ellipsoid.
+fn eccentricity_squared() -> f64 {
+    WGS84_F * (2.0 - WGS84_F)
+}
+
+/// Longitude of the central meridian of a UTM zone in radians.
+fn central_meridian(zone: u8) -> f64 {
+    (zone as f64 * 6.0 - 183.0).to_radians()
+}
+
+/// Projects longitude and latitude onto a UTM zone, following Snyder's series.
+fn utm_forward(zone: u8, north: bool, lon: f64, lat: f64) -> (f64, f64) {
+    let e2 = eccentricity_squared();
+    let ep2 = e2 / (1.0 - e2);
+    let phi = lat.to_radians();
+    let (sin, cos, tan) = (phi.sin(), phi.cos(), phi.tan());
+
+    let n = WGS84_A / (1.0 - e2 * sin * sin).sqrt();
+    let t = tan * tan;
+    let c = ep2 * cos * cos;
+    let a = (lon.to_radians() - central_meridian(zone)) * cos;
+    let m = meridian_arc(phi);
+
+    let x = UTM_K0
+        * n
+        * (a + (1.0 - t + c) * a.powi(3) / 6.0 + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0);
+    let y = UTM_K0
+        * (m + n
+            * tan
+            * (a * a / 2.0
+                + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+                + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6) / 720.0));
+    let false_northing = if north { 0.0 } else { UTM_FALSE_NORTHING_SOUTH };
+    (x + UTM_FALSE_EASTING, y + false_northing)
+}
+
+/// Converts UTM coordinates back to longitude and latitude, following Snyder's series.
+fn utm_inverse(zone: u8, north: bool, x: f64, y: f64) -> (f64, f64) {
+    let e2 = eccentricity_squared();
+    let ep2 = e2 / (1.0 - e2);
+    let false_northing = if north { 0.0 } else { UTM_FALSE_NORTHING_SOUTH };
+
+    // Footpoint latitude: the latitude whose meridian arc equals the northing.
+    let m = (y - false_northing) / UTM_K0;
+    let mu = m / (WGS84_A * (1.0 - e2 / 4.0 - 3.0 * e2 * e2 / 64.0 - 5.0 * e2.powi(3) / 256.0));
+    let e1 = (1.0 - (1.0 - e2).sqrt()) / (1.0 + (1.0 - e2).sqrt());
+    let phi1 = mu
+        + (3.0 * e1 / 2.0 - 27.0 * e1.powi(3) / 32.0) * (2.0 * mu).sin()
+        + (21.0 * e1 * e1 / 16.0 - 55.0 * e1.powi(4) / 32.0) * (4.0 * mu).sin()
+        + (151.0 * e1.powi(3) / 96.0) * (6.0 * mu).sin()
+        + (1097.0 * e1.powi(4) / 512.0) * (8.0 * mu).sin();
+
+    let (sin, cos, tan) = (phi1.sin(), phi1.cos(), phi1.tan());
+    let c1 = ep2 * cos * cos;
+    let t1 = tan * tan;
+    let n1 = WGS84_A / (1.0 - e2 * sin * sin).sqrt();
+    let r1 = WGS84_A * (1.0 - e2) / (1.0 - e2 * sin * sin).powf(1.5);
+    let d = (x - UTM_FALSE_EASTING) / (n1 * UTM_K0);
+
+    let lat = phi1
+        - (n1 * tan / r1)
+            * (d * d / 2.0 - (5.0 + 3.0 * t1 + 10.0 * c1 - 4.0 * c1 * c1 - 9.0 * ep2) * d.powi(4) / 24.0
+                + (61.0 + 90.0 * t1 + 298.0 * c1 + 45.0 * t1 * t1 - 252.0 * ep2 - 3.0 * c1 * c1) * d.powi(6) / 720.0);
+    let lon = central_meridian(zone)
+        + (d - (1.0 + 2.0 * t1 + c1) * d.powi(3) / 6.0
+            + (5.0 - 2.0 * c1 + 28.0 * t1 - 3.0 * c1 * c1 + 8.0 * ep2 + 24.0 * t1 * t1) * d.powi(5) / 120.0)
+            / cos;
+    (lon.to_degrees(), lat.to_degrees())
+}
+
+/// Length of the meridian from the equator to latitude `phi` in radians.
+fn meridian_arc(phi: f64) -> f64 {
+    let e2 = eccentricity_squared();
+    let (e4, e6) = (e2 * e2, e2.powi(3));
+    WGS84_A
+        * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * phi
+            - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * phi).sin()
+            + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * phi).sin()
+            - (35.0 * e6 / 3072.0) * (6.0 * phi).sin())
+}
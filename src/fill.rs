@@ -0,0 +1,85 @@
+use crate::grid::Grid;
+use crate::processor::ElevationGrid;
+use std::collections::VecDeque;
+
+/// Fills NoData regions of at most `max_size` cells from their valid surroundings and
+/// returns the number of cells filled.
+///
+/// Regions are 8-connected. Each region is filled from its edge inwards: every pass gives
+/// the cells next to known values the mean of those values, so the fill blends smoothly
+/// between the heights around the hole. Larger regions, such as sea or the area outside
+/// the survey, are left as NoData.
+pub fn fill_nodata(grid: &mut ElevationGrid, max_size: usize) -> usize {
+    let (rows, cols) = (grid.rows(), grid.cols());
+    let mut visited = Grid::new(rows, cols, false);
+    let mut filled = 0;
+
+    for row in 0..rows {
+        for col in 0..cols {
+            if visited[row][col] || !grid.is_nodata(grid.values[row][col]) {
+                continue;
+            }
+            let region = nodata_region(grid, &mut visited, row, col);
+            if region.len() <= max_size {
+                filled += region.len();
+                fill_region(grid, region);
+            }
+        }
+    }
+
+    filled
+}
+
+/// Collects the NoData cells 8-connected to `(row, col)`, marking them as visited.
+fn nodata_region(grid: &ElevationGrid, visited: &mut Grid<bool>, row: usize, col: usize) -> Vec<(usize, usize)> {
+    let mut region = Vec::new();
+    let mut queue = VecDeque::from([(row, col)]);
+    visited[row][col] = true;
+
+    while let Some((r, c)) = queue.pop_front() {
+        region.push((r, c));
+        for (nr, nc) in surrounding(grid, r, c) {
+            if !visited[nr][nc] && grid.is_nodata(grid.values[nr][nc]) {
+                visited[nr][nc] = true;
+                queue.push_back((nr, nc));
+            }
+        }
+    }
+
+    region
+}
+
+/// Fills a region from the outside in, one ring of cells per pass.
+fn fill_region(grid: &mut ElevationGrid, mut remaining: Vec<(usize, usize)>) {
+    while !remaining.is_empty() {
+        // Values of this pass only see cells known before it, so each ring is filled evenly.
+        let mut ring = Vec::new();
+        remaining.retain(|&(r, c)| {
+            let (sum, count) = surrounding(grid, r, c)
+                .map(|(nr, nc)| grid.values[nr][nc])
+                .filter(|&v| !grid.is_nodata(v))
+                .fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+            if count == 0 {
+                return true;
+            }
+            ring.push((r, c, sum / count as f64));
+            false
+        });
+
+        // A region without any valid neighbors covers the whole grid and cannot be filled.
+        if ring.is_empty() {
+            return;
+        }
+        for (r, c, value) in ring {
+            grid.values[r][c] = value;
+        }
+    }
+}
+
+/// Returns the positions of the up to eight cells around a cell.
+fn surrounding(grid: &ElevationGrid, row: usize, col: usize) -> impl Iterator<Item = (usize, usize)> {
+    let rows = row.saturating_sub(1)..(row + 2).min(grid.rows());
+    let cols = col.saturating_sub(1)..(col + 2).min(grid.cols());
+    rows.flat_map(move |r| cols.clone().map(move |c| (r, c)))
+        .filter(move |&pos| pos != (row, col))
+}
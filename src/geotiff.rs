@@ -0,0 +1,228 @@
+use crate::crs::Crs;
+use anyhow::{bail, Context, Result};
+use image::RgbaImage;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Seek, Write};
+use std::path::Path;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::encoder::{colortype, DirectoryEncoder, TiffEncoder, TiffKind};
+use tiff::tags::Tag;
+use tiff::ColorType;
+
+/// GeoKey telling projected from geographic coordinates.
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+/// GeoKey marking pixel values as covering the whole cell area.
+const GT_RASTER_TYPE_GEO_KEY: u16 = 1025;
+/// GeoKey holding the EPSG code of a geographic coordinate system.
+const GEOGRAPHIC_TYPE_GEO_KEY: u16 = 2048;
+/// GeoKey holding the EPSG code of a projected coordinate system.
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+/// `GTRasterTypeGeoKey` value for `RasterPixelIsArea`.
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+/// `NewSubfileType` value marking a reduced-resolution copy of the main image.
+const REDUCED_RESOLUTION: u32 = 1;
+/// `ExtraSamples` value for a band of alpha not premultiplied into the colors.
+const UNASSOCIATED_ALPHA: u16 = 2;
+/// Overviews are added until the smallest fits within this many pixels on a side.
+const OVERVIEW_MIN_SIZE: u32 = 256;
+/// `GTModelTypeGeoKey` values for projected and geographic coordinates.
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+
+/// Affine transform in GDAL order: `[x_origin, pixel_width, row_rotation, y_origin, column_rotation, pixel_height]`.
+///
+/// Map coordinates of the top-left corner of pixel `(col, row)` are
+/// `x = t[0] + col * t[1] + row * t[2]` and `y = t[3] + col * t[4] + row * t[5]`.
+pub type GeoTransform = [f64; 6];
+
+/// Returns the map coordinates of a fractional pixel position.
+pub fn pixel_to_map(transform: &GeoTransform, x: f64, y: f64) -> (f64, f64) {
+    let [x0, a, b, y0, d, e] = *transform;
+    (x0 + x * a + y * b, y0 + x * d + y * e)
+}
+
+/// Returns the factors internal overviews of a `width` x `height` image are reduced by:
+/// 2, 4, 8, ... up to the first that fits within 256 pixels on a side. Small images get none.
+pub fn overview_factors(width: u32, height: u32) -> Vec<u32> {
+    let mut factors = Vec::new();
+    let mut factor = 1;
+    while width.max(height).div_ceil(factor) > OVERVIEW_MIN_SIZE {
+        factor *= 2;
+        factors.push(factor);
+    }
+    factors
+}
+
+/// Writes single-band float values as a georeferenced TIFF, in `crs` if known.
+///
+/// `levels` holds the `(width, height, values)` of the full image first, followed by any
+/// overviews, which are stored after it as reduced-resolution images.
+pub fn write_gray_f32(
+    path: &Path,
+    levels: &[(u32, u32, Vec<f32>)],
+    transform: &GeoTransform,
+    nodata: Option<f64>,
+    crs: Option<Crs>,
+) -> Result<()> {
+    let mut tiff = TiffEncoder::new(BufWriter::new(File::create(path)?))?;
+    for (i, (width, height, data)) in levels.iter().enumerate() {
+        let mut image = tiff.new_image::<colortype::Gray32Float>(*width, *height)?;
+        if i == 0 {
+            write_georeferencing(image.encoder(), transform, nodata, crs)?;
+        } else {
+            write_overview_tags(image.encoder(), nodata)?;
+        }
+        image.write_data(data)?;
+    }
+    Ok(())
+}
+
+/// Writes an RGBA image as a georeferenced TIFF, in `crs` if known, followed by its
+/// `overviews` as reduced-resolution images.
+///
+/// The fourth band is marked as unassociated alpha, so GIS software shows transparent
+/// pixels, such as NoData cells, as gaps.
+pub fn write_rgba8(
+    path: &Path,
+    rgba: &RgbaImage,
+    overviews: &[RgbaImage],
+    transform: &GeoTransform,
+    crs: Option<Crs>,
+) -> Result<()> {
+    let mut tiff = TiffEncoder::new(BufWriter::new(File::create(path)?))?;
+    for (i, rgba) in std::iter::once(rgba).chain(overviews).enumerate() {
+        let mut image = tiff.new_image::<colortype::RGBA8>(rgba.width(), rgba.height())?;
+        image.encoder().write_tag(Tag::ExtraSamples, UNASSOCIATED_ALPHA)?;
+        if i == 0 {
+            write_georeferencing(image.encoder(), transform, None, crs)?;
+        } else {
+            write_overview_tags(image.encoder(), None)?;
+        }
+        image.write_data(rgba.as_raw())?;
+    }
+    Ok(())
+}
+
+/// Marks an image directory as an overview of the first, with the same NoData value.
+fn write_overview_tags<W: Write + Seek, K: TiffKind>(dir: &mut DirectoryEncoder<W, K>, nodata: Option<f64>) -> Result<()> {
+    dir.write_tag(Tag::NewSubfileType, REDUCED_RESOLUTION)?;
+    if let Some(nodata) = nodata {
+        dir.write_tag(Tag::GdalNodata, nodata.to_string().as_str())?;
+    }
+    Ok(())
+}
+
+/// Writes the transformation, GeoKey directory and optional NoData tags of an image directory.
+fn write_georeferencing<W: Write + Seek, K: TiffKind>(
+    dir: &mut DirectoryEncoder<W, K>,
+    transform: &GeoTransform,
+    nodata: Option<f64>,
+    crs: Option<Crs>,
+) -> Result<()> {
+    let [x0, a, b, y0, d, e] = *transform;
+    let matrix = [
+        a, b, 0.0, x0, //
+        d, e, 0.0, y0, //
+        0.0, 0.0, 0.0, 0.0, //
+        0.0, 0.0, 0.0, 1.0,
+    ];
+    dir.write_tag(Tag::ModelTransformationTag, &matrix[..])?;
+
+    // Header (version 1.1.0 and the key count) followed by the key entries, sorted by key.
+    let mut keys = Vec::new();
+    if let Some(crs) = crs {
+        let model = if crs == Crs::Geographic { MODEL_TYPE_GEOGRAPHIC } else { MODEL_TYPE_PROJECTED };
+        keys.push([GT_MODEL_TYPE_GEO_KEY, 0, 1, model]);
+    }
+    keys.push([GT_RASTER_TYPE_GEO_KEY, 0, 1, RASTER_PIXEL_IS_AREA]);
+    match crs {
+        Some(Crs::Geographic) => keys.push([GEOGRAPHIC_TYPE_GEO_KEY, 0, 1, 4326]),
+        Some(crs) => keys.push([PROJECTED_CS_TYPE_GEO_KEY, 0, 1, crs.epsg() as u16]),
+        None => {}
+    }
+    let mut geo_keys = vec![1, 1, 0, keys.len() as u16];
+    geo_keys.extend(keys.iter().flatten());
+    dir.write_tag(Tag::GeoKeyDirectoryTag, &geo_keys[..])?;
+
+    if let Some(nodata) = nodata {
+        dir.write_tag(Tag::GdalNodata, nodata.to_string().as_str())?;
+    }
+
+    Ok(())
+}
+
+/// A single-band raster read from a GeoTIFF, stored row by row from the first image row.
+#[derive(Debug, Clone)]
+pub struct Raster {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<f64>,
+    pub transform: GeoTransform,
+    pub nodata: Option<f64>,
+}
+
+/// Reads the width and height of a single-band GeoTIFF without decoding its pixels.
+pub fn read_dimensions(path: &Path) -> Result<(u32, u32)> {
+    Ok(open_single_band(path)?.dimensions()?)
+}
+
+/// Opens a GeoTIFF, checking that its first image has a single band.
+fn open_single_band(path: &Path) -> Result<Decoder<BufReader<File>>> {
+    let mut decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+    match decoder.colortype()? {
+        ColorType::Gray(_) => Ok(decoder),
+        other => bail!("only single-band GeoTIFFs are supported, found {:?}", other),
+    }
+}
+
+/// Reads the first band of a single-band GeoTIFF along with its georeferencing and NoData value.
+///
+/// The transform comes from `ModelTransformationTag`, or from `ModelTiepointTag` and
+/// `ModelPixelScaleTag`. Files without either are placed at the origin with unit cells.
+pub fn read_single_band(path: &Path) -> Result<Raster> {
+    let mut decoder = open_single_band(path)?;
+    let (width, height) = decoder.dimensions()?;
+
+    let transform = if let Ok(m) = decoder.get_tag_f64_vec(Tag::ModelTransformationTag) {
+        if m.len() < 8 {
+            bail!("malformed ModelTransformationTag");
+        }
+        [m[3], m[0], m[1], m[7], m[4], m[5]]
+    } else if let (Ok(tie), Ok(scale)) = (
+        decoder.get_tag_f64_vec(Tag::ModelTiepointTag),
+        decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag),
+    ) {
+        if tie.len() < 6 || scale.len() < 2 {
+            bail!("malformed ModelTiepointTag or ModelPixelScaleTag");
+        }
+        // The tiepoint links raster point (i, j) to model point (x, y).
+        let (i, j, x, y) = (tie[0], tie[1], tie[3], tie[4]);
+        [x - i * scale[0], scale[0], 0.0, y + j * scale[1], 0.0, -scale[1]]
+    } else {
+        [0.0, 1.0, 0.0, 0.0, 0.0, -1.0]
+    };
+
+    let nodata = match decoder.get_tag_ascii_string(Tag::GdalNodata) {
+        Ok(text) => Some(
+            text.trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                .parse()
+                .with_context(|| format!("invalid GDAL_NODATA value '{}'", text))?,
+        ),
+        Err(_) => None,
+    };
+
+    let data: Vec<f64> = match decoder.read_image()? {
+        DecodingResult::U8(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U16(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::U64(v) => v.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::I8(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::I16(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::I32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::I64(v) => v.into_iter().map(|x| x as f64).collect(),
+        DecodingResult::F32(v) => v.into_iter().map(f64::from).collect(),
+        DecodingResult::F64(v) => v,
+    };
+
+    Ok(Raster { width, height, data, transform, nodata })
+}
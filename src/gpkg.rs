@@ -0,0 +1,191 @@
+use crate::crs::Crs;
+use crate::geotiff::GeoTransform;
+use anyhow::{bail, Context, Result};
+use image::{imageops, ColorType, DynamicImage, ImageFormat};
+use rusqlite::{params, Connection};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Width and height of the tiles images are cut into.
+const TILE_SIZE: u32 = 256;
+/// `application_id` marking an SQLite file as a GeoPackage ("GPKG").
+const APPLICATION_ID: i32 = 0x4750_4B47;
+/// `user_version` of GeoPackage 1.3.0.
+const USER_VERSION: i32 = 10300;
+/// Tables every GeoPackage holding tiles needs, with the system rows the standard requires.
+const SCHEMA: &str = r#"
+CREATE TABLE gpkg_spatial_ref_sys (
+    srs_name TEXT NOT NULL,
+    srs_id INTEGER PRIMARY KEY,
+    organization TEXT NOT NULL,
+    organization_coordsys_id INTEGER NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT
+);
+CREATE TABLE gpkg_contents (
+    table_name TEXT NOT NULL PRIMARY KEY,
+    data_type TEXT NOT NULL,
+    identifier TEXT UNIQUE,
+    description TEXT DEFAULT '',
+    last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    min_x DOUBLE,
+    min_y DOUBLE,
+    max_x DOUBLE,
+    max_y DOUBLE,
+    srs_id INTEGER,
+    CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+);
+CREATE TABLE gpkg_tile_matrix_set (
+    table_name TEXT NOT NULL PRIMARY KEY,
+    srs_id INTEGER NOT NULL,
+    min_x DOUBLE NOT NULL,
+    min_y DOUBLE NOT NULL,
+    max_x DOUBLE NOT NULL,
+    max_y DOUBLE NOT NULL,
+    CONSTRAINT fk_gtms_table_name FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+    CONSTRAINT fk_gtms_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+);
+CREATE TABLE gpkg_tile_matrix (
+    table_name TEXT NOT NULL,
+    zoom_level INTEGER NOT NULL,
+    matrix_width INTEGER NOT NULL,
+    matrix_height INTEGER NOT NULL,
+    tile_width INTEGER NOT NULL,
+    tile_height INTEGER NOT NULL,
+    pixel_x_size DOUBLE NOT NULL,
+    pixel_y_size DOUBLE NOT NULL,
+    CONSTRAINT pk_ttm PRIMARY KEY (table_name, zoom_level),
+    CONSTRAINT fk_tmm_table_name FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name)
+);
+INSERT INTO gpkg_spatial_ref_sys VALUES
+    ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', 'undefined cartesian coordinate reference system'),
+    ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', 'undefined geographic coordinate reference system');
+"#;
+
+/// Writes an image as a GeoPackage holding a single tile pyramid level at its native
+/// resolution, placed by the pixel-corner `transform` in `crs`, replacing any existing file.
+///
+/// Tiles are stored as PNG in a table named after the file; fully transparent tiles are
+/// left out. Without a CRS the tiles are placed in the standard's undefined cartesian system.
+pub fn write_gpkg(path: &Path, image: &DynamicImage, transform: &GeoTransform, crs: Option<Crs>) -> Result<()> {
+    let [x0, pixel_width, row_rotation, top, column_rotation, pixel_height] = *transform;
+    if row_rotation != 0.0 || column_rotation != 0.0 {
+        bail!("GeoPackage tiles can't be rotated");
+    }
+    // Tile rows run north to south, like the rows of a north-up image.
+    if pixel_width <= 0.0 || pixel_height >= 0.0 {
+        bail!("GeoPackage tiles need a north-up image");
+    }
+    let pixel_height = -pixel_height;
+    let (bottom, right) = (top - image.height() as f64 * pixel_height, x0 + image.width() as f64 * pixel_width);
+    let matrix_width = image.width().div_ceil(TILE_SIZE);
+    let matrix_height = image.height().div_ceil(TILE_SIZE);
+
+    if path.exists() {
+        fs::remove_file(path).with_context(|| format!("replacing {:?}", path))?;
+    }
+    let mut db = Connection::open(path).with_context(|| format!("creating {:?}", path))?;
+    db.pragma_update(None, "application_id", APPLICATION_ID)?;
+    db.pragma_update(None, "user_version", USER_VERSION)?;
+    let tx = db.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    let srs_id = match crs {
+        Some(crs) => {
+            tx.execute(
+                "INSERT OR IGNORE INTO gpkg_spatial_ref_sys VALUES (?1, ?2, 'EPSG', ?2, ?3, NULL)",
+                params![crs.name(), crs.epsg(), crs.wkt()],
+            )?;
+            crs.epsg() as i64
+        }
+        None => -1,
+    };
+    let table = table_name(path);
+    tx.execute(
+        "INSERT INTO gpkg_contents (table_name, data_type, identifier, min_x, min_y, max_x, max_y, srs_id)
+         VALUES (?1, 'tiles', ?1, ?2, ?3, ?4, ?5, ?6)",
+        params![table, x0, bottom, right, top, srs_id],
+    )?;
+    // The matrix covers whole tiles, so it reaches past the right and bottom edges.
+    tx.execute(
+        "INSERT INTO gpkg_tile_matrix_set VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            table,
+            srs_id,
+            x0,
+            top - (matrix_height * TILE_SIZE) as f64 * pixel_height,
+            x0 + (matrix_width * TILE_SIZE) as f64 * pixel_width,
+            top
+        ],
+    )?;
+    tx.execute(
+        "INSERT INTO gpkg_tile_matrix VALUES (?1, 0, ?2, ?3, ?4, ?4, ?5, ?6)",
+        params![table, matrix_width, matrix_height, TILE_SIZE, pixel_width, pixel_height],
+    )?;
+    tx.execute_batch(&format!(
+        "CREATE TABLE \"{}\" (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            zoom_level INTEGER NOT NULL,
+            tile_column INTEGER NOT NULL,
+            tile_row INTEGER NOT NULL,
+            tile_data BLOB NOT NULL,
+            UNIQUE (zoom_level, tile_column, tile_row)
+        )",
+        table
+    ))?;
+
+    let mut insert = tx.prepare(&format!(
+        "INSERT INTO \"{}\" (zoom_level, tile_column, tile_row, tile_data) VALUES (0, ?1, ?2, ?3)",
+        table
+    ))?;
+    // Edge tiles are padded with transparency, so every image gets an alpha channel.
+    let image: DynamicImage = match image.color() {
+        ColorType::L8 | ColorType::La8 => image.to_luma_alpha8().into(),
+        _ => image.to_rgba8().into(),
+    };
+    for row in 0..matrix_height {
+        for col in 0..matrix_width {
+            let (x, y) = (col * TILE_SIZE, row * TILE_SIZE);
+            let part = image.crop_imm(x, y, TILE_SIZE.min(image.width() - x), TILE_SIZE.min(image.height() - y));
+            let mut tile = DynamicImage::new(TILE_SIZE, TILE_SIZE, image.color());
+            imageops::replace(&mut tile, &part, 0, 0);
+            if let Some(data) = encode_tile(tile)? {
+                insert.execute(params![col, row, data])?;
+            }
+        }
+    }
+    drop(insert);
+    tx.commit()?;
+    Ok(())
+}
+
+/// Encodes a tile as PNG, dropping the alpha channel of opaque tiles, or returns `None`
+/// for a fully transparent tile.
+fn encode_tile(tile: DynamicImage) -> Result<Option<Vec<u8>>> {
+    let rgba = tile.to_rgba8();
+    let (lowest, highest) = rgba.pixels().fold((255, 0), |(low, high), p| (p[3].min(low), p[3].max(high)));
+    if highest == 0 {
+        return Ok(None);
+    }
+    let tile = match tile {
+        DynamicImage::ImageLumaA8(_) if lowest == 255 => tile.into_luma8().into(),
+        DynamicImage::ImageRgba8(_) if lowest == 255 => tile.into_rgb8().into(),
+        tile => tile,
+    };
+    let mut data = Vec::new();
+    tile.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+    Ok(Some(data))
+}
+
+/// Returns the tile table name for a GeoPackage file: its stem, with anything but ASCII
+/// letters, digits and underscores replaced by underscores.
+fn table_name(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name: String = stem.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    // Tables whose names start like the standard's own would be taken for them.
+    if name.is_empty() || name.starts_with("gpkg") || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("tiles_{}", name)
+    } else {
+        name
+    }
+}
@@ -0,0 +1,101 @@
+use rayon::prelude::*;
+use std::ops::{Index, IndexMut};
+
+/// A rectangular table of cells stored row by row in a single `Vec`, so the cells of a row
+/// and of neighboring rows sit close together in memory.
+///
+/// Indexing with a row number gives that row as a slice, so `grid[row][col]` is a cell.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Grid<T> {
+    cells: Vec<T>,
+    rows: usize,
+    cols: usize,
+}
+
+impl<T> Grid<T> {
+    /// Creates a grid from its cells in row-major order, the cell at `(row, col)` being at
+    /// `row * cols + col`.
+    ///
+    /// # Panics
+    /// Panics if there aren't exactly `rows * cols` cells.
+    pub fn from_vec(rows: usize, cols: usize, cells: Vec<T>) -> Self {
+        assert_eq!(cells.len(), rows * cols, "a {}x{} grid needs {} cells", cols, rows, rows * cols);
+        Self { cells, rows, cols }
+    }
+
+    /// Creates a grid whose cells are computed from their `(row, col)` position.
+    pub fn from_fn(rows: usize, cols: usize, mut f: impl FnMut(usize, usize) -> T) -> Self {
+        let cells = (0..rows * cols).map(|i| f(i / cols, i % cols)).collect();
+        Self { cells, rows, cols }
+    }
+
+    /// Like [`from_fn`](Self::from_fn), but computes rows in parallel on the current thread pool.
+    pub fn par_from_fn(rows: usize, cols: usize, f: impl Fn(usize, usize) -> T + Sync) -> Self
+    where
+        T: Send,
+    {
+        let cells = (0..rows * cols).into_par_iter().map(|i| f(i / cols, i % cols)).collect();
+        Self { cells, rows, cols }
+    }
+
+    /// Number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of columns.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Returns the cell at `(row, col)`, or `None` outside the grid.
+    pub fn get(&self, row: usize, col: usize) -> Option<&T> {
+        (row < self.rows && col < self.cols).then(|| &self.cells[row * self.cols + col])
+    }
+
+    /// Returns the cells in row-major order.
+    pub fn as_slice(&self) -> &[T] {
+        &self.cells
+    }
+
+    /// Iterates over every cell in row-major order.
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.cells.iter()
+    }
+
+    /// Iterates mutably over every cell in row-major order.
+    pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, T> {
+        self.cells.iter_mut()
+    }
+
+    /// Iterates over the rows from the first to the last.
+    pub fn iter_rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.rows).map(|row| &self[row])
+    }
+
+    /// Iterates mutably over the rows from the first to the last.
+    pub fn iter_rows_mut(&mut self) -> std::slice::ChunksMut<'_, T> {
+        self.cells.chunks_mut(self.cols.max(1))
+    }
+}
+
+impl<T: Clone> Grid<T> {
+    /// Creates a grid with every cell set to `value`.
+    pub fn new(rows: usize, cols: usize, value: T) -> Self {
+        Self { cells: vec![value; rows * cols], rows, cols }
+    }
+}
+
+impl<T> Index<usize> for Grid<T> {
+    type Output = [T];
+
+    fn index(&self, row: usize) -> &[T] {
+        &self.cells[row * self.cols..(row + 1) * self.cols]
+    }
+}
+
+impl<T> IndexMut<usize> for Grid<T> {
+    fn index_mut(&mut self, row: usize) -> &mut [T] {
+        &mut self.cells[row * self.cols..(row + 1) * self.cols]
+    }
+}
@@ -0,0 +1,154 @@
+use crate::grid::Grid;
+use crate::processor::ElevationGrid;
+use std::collections::VecDeque;
+
+/// Offsets of the eight neighbors of a cell as `(row, col)` steps.
+const NEIGHBORS: [(isize, isize); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+/// Computes D8 flow accumulation: the number of cells upstream of every cell.
+///
+/// Each cell drains to the neighbor with the steepest descent. Single-cell pits are raised
+/// to their lowest neighbor first so they don't swallow the flow of a whole slope; cells
+/// with no lower neighbor, such as flats and grid edges, end their flow path. NoData cells
+/// stay NoData and neither give nor take flow.
+pub fn compute_flow_accumulation(grid: &ElevationGrid) -> ElevationGrid {
+    let (rows, cols) = (grid.rows(), grid.cols());
+    let heights = fill_single_cell_pits(grid);
+    let directions = flow_directions(grid, &heights);
+
+    // Visit cells after everything draining into them, passing each count downstream.
+    let mut inflows = vec![0usize; rows * cols];
+    for &target in directions.iter().flatten() {
+        inflows[target] += 1;
+    }
+    let mut queue: VecDeque<usize> = (0..rows * cols).filter(|&i| inflows[i] == 0).collect();
+    let mut upstream = vec![0.0; rows * cols];
+    while let Some(cell) = queue.pop_front() {
+        if let Some(target) = directions[cell] {
+            upstream[target] += upstream[cell] + 1.0;
+            inflows[target] -= 1;
+            if inflows[target] == 0 {
+                queue.push_back(target);
+            }
+        }
+    }
+
+    grid.map_cells(|row, col| (!grid.is_nodata(grid.values[row][col])).then(|| upstream[row * cols + col]))
+}
+
+/// Fills sinks with the Planchon-Darboux algorithm and returns the number of cells raised.
+///
+/// Water is first assumed to stand infinitely high everywhere except at the outlets, the
+/// cells on the grid edge or next to NoData, and is then drained away until every cell
+/// can flow to an outlet. Each filled cell ends up at least `tolerance` above the
+/// neighbor it drains to, so a positive tolerance leaves no flats for flow routing.
+pub fn fill_sinks(grid: &mut ElevationGrid, tolerance: f64) -> usize {
+    let (rows, cols) = (grid.rows(), grid.cols());
+    let is_outlet = |row: usize, col: usize| {
+        row == 0
+            || col == 0
+            || row == rows - 1
+            || col == cols - 1
+            || neighbors(grid, row, col).any(|(r, c, _)| grid.is_nodata(grid.values[r][c]))
+    };
+    let mut water = Grid::from_fn(rows, cols, |row, col| {
+        let value = grid.values[row][col];
+        if grid.is_nodata(value) || is_outlet(row, col) { value } else { f64::INFINITY }
+    });
+
+    // Alternate the scan direction so drainage spreads quickly both ways across the grid.
+    let mut forward = true;
+    loop {
+        let mut changed = false;
+        for i in 0..rows * cols {
+            let index = if forward { i } else { rows * cols - 1 - i };
+            let (row, col) = (index / cols, index % cols);
+            let value = grid.values[row][col];
+            if grid.is_nodata(value) || water[row][col] <= value {
+                continue;
+            }
+            for (r, c, _) in neighbors(grid, row, col) {
+                let drain = water[r][c] + tolerance;
+                if grid.is_nodata(grid.values[r][c]) || drain >= water[row][col] {
+                    continue;
+                }
+                water[row][col] = drain.max(value);
+                changed = true;
+                if water[row][col] == value {
+                    break;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+        forward = !forward;
+    }
+
+    let mut raised = 0;
+    for (value, &level) in grid.values.iter_mut().zip(water.iter()) {
+        if level > *value {
+            *value = level;
+            raised += 1;
+        }
+    }
+    raised
+}
+
+/// Returns the elevations with every cell lower than all of its valid neighbors raised to
+/// the lowest of them.
+fn fill_single_cell_pits(grid: &ElevationGrid) -> Grid<f64> {
+    let filled = grid.map_cells(|row, col| {
+        let value = grid.values[row][col];
+        if grid.is_nodata(value) {
+            return None;
+        }
+        let lowest = neighbors(grid, row, col)
+            .map(|(r, c, _)| grid.values[r][c])
+            .filter(|&v| !grid.is_nodata(v))
+            .fold(f64::INFINITY, f64::min);
+        Some(if lowest.is_finite() { value.max(lowest) } else { value })
+    });
+    filled.values
+}
+
+/// Returns the flat index of the cell each cell drains to, or `None` where flow stops.
+fn flow_directions(grid: &ElevationGrid, heights: &Grid<f64>) -> Vec<Option<usize>> {
+    let cols = grid.cols();
+    let diagonal = grid.cell_width.hypot(grid.cell_height);
+    let mut directions = vec![None; grid.rows() * cols];
+    for row in 0..grid.rows() {
+        for col in 0..cols {
+            if grid.is_nodata(grid.values[row][col]) {
+                continue;
+            }
+            let mut steepest = 0.0;
+            for (r, c, (dr, dc)) in neighbors(grid, row, col) {
+                if grid.is_nodata(grid.values[r][c]) {
+                    continue;
+                }
+                let distance = match (dr, dc) {
+                    (0, _) => grid.cell_width,
+                    (_, 0) => grid.cell_height,
+                    _ => diagonal,
+                };
+                let drop = (heights[row][col] - heights[r][c]) / distance;
+                if drop > steepest {
+                    steepest = drop;
+                    directions[row * cols + col] = Some(r * cols + c);
+                }
+            }
+        }
+    }
+    directions
+}
+
+/// Returns the positions of the up to eight cells around a cell with their offsets.
+fn neighbors(grid: &ElevationGrid, row: usize, col: usize) -> impl Iterator<Item = (usize, usize, (isize, isize))> {
+    let (rows, cols) = (grid.rows(), grid.cols());
+    NEIGHBORS.into_iter().filter_map(move |(dr, dc)| {
+        let r = row.checked_add_signed(dr).filter(|&r| r < rows)?;
+        let c = col.checked_add_signed(dc).filter(|&c| c < cols)?;
+        Some((r, c, (dr, dc)))
+    })
+}
@@ -0,0 +1,149 @@
+use image::{Rgb, RgbImage};
+
+/// Height of the color bar in pixels.
+const BAR_HEIGHT: u32 = 256;
+/// Width of the color bar in pixels.
+const BAR_WIDTH: u32 = 24;
+/// Blank space around the bar and labels.
+const MARGIN: u32 = 10;
+/// Number of labeled ticks, including both ends.
+const TICKS: u32 = 5;
+/// Width and height of a class swatch, and the gap between swatches.
+const SWATCH_SIZE: u32 = 24;
+const SWATCH_GAP: u32 = 4;
+/// Length of the tick marks next to the bar.
+const TICK_LENGTH: u32 = 5;
+/// Size in pixels of one font dot.
+const FONT_SCALE: u32 = 2;
+/// Width and height of a glyph in font dots, and the gap between glyphs.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const GLYPH_GAP: u32 = 1;
+
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+
+/// Returns the rows of a 5x7 bitmap glyph, most significant bit on the left, or `None` for
+/// characters the legend never prints.
+fn glyph(c: char) -> Option<[u8; 7]> {
+    Some(match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        _ => return None,
+    })
+}
+
+/// Width in pixels of `text` drawn with the bitmap font.
+fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * (GLYPH_WIDTH + GLYPH_GAP) * FONT_SCALE
+}
+
+/// Draws `text` with its top-left corner at `(x, y)`.
+fn draw_text(image: &mut RgbImage, text: &str, x: u32, y: u32, color: Rgb<u8>) {
+    for (i, rows) in text.chars().filter_map(glyph).enumerate() {
+        let left = x + i as u32 * (GLYPH_WIDTH + GLYPH_GAP) * FONT_SCALE;
+        for (gy, bits) in rows.iter().enumerate() {
+            for gx in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - gx)) == 0 {
+                    continue;
+                }
+                for dy in 0..FONT_SCALE {
+                    for dx in 0..FONT_SCALE {
+                        let (px, py) = (left + gx * FONT_SCALE + dx, y + gy as u32 * FONT_SCALE + dy);
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Formats a tick value with as many decimals as the span of the legend needs.
+fn format_tick(value: f64, span: f64) -> String {
+    let decimals = if span >= 50.0 {
+        0
+    } else if span >= 5.0 {
+        1
+    } else {
+        2
+    };
+    // Rounding first and adding zero keeps values that round to zero from printing as "-0".
+    let factor = 10f64.powi(decimals as i32);
+    format!("{:.*}", decimals, (value * factor).round() / factor + 0.0)
+}
+
+/// Renders a vertical color bar for values from `min` at the bottom to `max` at the top,
+/// colored by `color`, with evenly spaced value labels on its right.
+pub fn render_legend(min: f64, max: f64, color: impl Fn(f64) -> Rgb<u8>) -> RgbImage {
+    let span = max - min;
+    let value_at = |t: f64| min + span * t;
+    let labels: Vec<String> = (0..TICKS)
+        .map(|i| format_tick(value_at(i as f64 / (TICKS - 1) as f64), span.abs()))
+        .collect();
+    let label_width = labels.iter().map(|label| text_width(label)).max().unwrap_or(0);
+    let text_height = GLYPH_HEIGHT * FONT_SCALE;
+
+    let width = MARGIN + BAR_WIDTH + TICK_LENGTH + MARGIN / 2 + label_width + MARGIN;
+    let height = MARGIN + text_height / 2 + BAR_HEIGHT + text_height / 2 + MARGIN;
+    let mut legend = RgbImage::from_pixel(width, height, WHITE);
+    let bar_top = MARGIN + text_height / 2;
+
+    // The bar runs from the maximum at the top to the minimum at the bottom.
+    for y in 0..BAR_HEIGHT {
+        let t = 1.0 - y as f64 / (BAR_HEIGHT - 1) as f64;
+        let c = color(value_at(t));
+        for x in 0..BAR_WIDTH {
+            legend.put_pixel(MARGIN + x, bar_top + y, c);
+        }
+    }
+
+    // Label the ticks from the bottom up, centering each label on its tick.
+    for (i, label) in labels.iter().enumerate() {
+        let y = bar_top + (BAR_HEIGHT - 1) - i as u32 * (BAR_HEIGHT - 1) / (TICKS - 1);
+        for x in 0..TICK_LENGTH {
+            legend.put_pixel(MARGIN + BAR_WIDTH + x, y, BLACK);
+        }
+        let label_x = MARGIN + BAR_WIDTH + TICK_LENGTH + MARGIN / 2;
+        draw_text(&mut legend, label, label_x, y - text_height / 2, BLACK);
+    }
+
+    legend
+}
+
+/// Renders a legend of categories, one color swatch per class stacked from the first class
+/// at the bottom to the last at the top, each labeled on its right.
+pub fn render_class_legend(labels: &[String], colors: &[Rgb<u8>]) -> RgbImage {
+    let classes = labels.len().min(colors.len()) as u32;
+    let label_width = labels.iter().map(|label| text_width(label)).max().unwrap_or(0);
+    let text_height = GLYPH_HEIGHT * FONT_SCALE;
+
+    let width = MARGIN + SWATCH_SIZE + MARGIN / 2 + label_width + MARGIN;
+    let height = 2 * MARGIN + classes * SWATCH_SIZE + classes.saturating_sub(1) * SWATCH_GAP;
+    let mut legend = RgbImage::from_pixel(width, height, WHITE);
+
+    for (i, (label, &color)) in labels.iter().zip(colors).enumerate() {
+        let top = height - MARGIN - (i as u32 + 1) * SWATCH_SIZE - i as u32 * SWATCH_GAP;
+        for y in 0..SWATCH_SIZE {
+            for x in 0..SWATCH_SIZE {
+                legend.put_pixel(MARGIN + x, top + y, color);
+            }
+        }
+        let label_x = MARGIN + SWATCH_SIZE + MARGIN / 2;
+        draw_text(&mut legend, label, label_x, top + (SWATCH_SIZE - text_height) / 2, BLACK);
+    }
+
+    legend
+}
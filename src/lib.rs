@@ -0,0 +1,62 @@
+//! Convert ESRI ASCII grid and GeoTIFF DEMs to grayscale, hillshaded and terrain-analysis images.
+//!
+//! The binary is a thin wrapper over this library; the conversion steps are
+//! exposed individually so they can be embedded in other programs.
+
+pub mod asc_header;
+pub mod asc_text;
+pub mod cli;
+pub mod colormap_file;
+pub mod colormaps;
+pub mod compare;
+pub mod config;
+pub mod contours;
+pub mod crs;
+pub mod fill;
+pub mod geotiff;
+pub mod gpkg;
+pub mod grid;
+pub mod hydro;
+pub mod legend;
+pub mod mask;
+pub mod mosaic;
+pub mod multi_tiff;
+pub mod processor;
+pub mod report;
+pub mod reproject;
+pub mod resample;
+pub mod stats;
+pub mod tiles;
+pub mod world_file;
+
+pub use asc_header::{override_cell_size, validate_ascii_header};
+pub use asc_text::AsciiText;
+pub use colormap_file::ColorRelief;
+pub use colormaps::Colormap;
+pub use compare::{compare_images, ImageDiff};
+pub use contours::{draw_contours, join_segments, trace_contours, write_geojson, ContourLine, ContourSegment};
+pub use crs::Crs;
+pub use fill::fill_nodata;
+pub use gpkg::write_gpkg;
+pub use grid::Grid;
+pub use hydro::{compute_flow_accumulation, fill_sinks};
+pub use legend::{render_class_legend, render_legend};
+pub use mask::PolygonMask;
+pub use mosaic::merge_grids;
+pub use multi_tiff::MultiPageTiff;
+pub use processor::{
+    apply_aerial_perspective, apply_colored_hillshade, apply_gamma, apply_hillshade,
+    apply_multidirectional_hillshade, ascii_to_image, aspect_to_image, blend_colormap_with_hillshade,
+    classes_to_image, classify_slope, compute_aspect, compute_curvature, compute_occlusion, compute_sky_view_factor,
+    compute_slope, compute_tpi, compute_tri, elevation_to_colormap, equalize_grid, geotiff_to_image,
+    grayscale_to_colormap, grid_to_image, is_dem_file, is_geotiff, is_gzipped, letterbox, log_normalize_grid,
+    mask_nodata, normalize_grid, normalize_grid_16, paint_water, process_asc_file, process_asc_file_with,
+    process_source, read_ascii_grid, read_ascii_grid_from, read_ascii_grid_sampled_from, read_geotiff_grid,
+    read_grid, read_grid_size, read_grid_with_cell_size, slope_shade, stream_ascii_to_image, trim_nodata_edges,
+    DemSource, ElevationGrid, ProcessOptions, Processed, Timings, FLAT_ASPECT,
+};
+pub use report::write_report;
+pub use reproject::reproject_grid;
+pub use resample::{image_filter, sample_grid};
+pub use stats::GridStats;
+pub use tiles::write_tiles;
@@ -1,33 +1,262 @@
-mod cli;
-mod processor;
-
-use clap::Parser;
+use anyhow::{bail, Context};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info, warn, LevelFilter};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
 use walkdir::WalkDir;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use std::fs;
-use crate::cli::Args;
-use crate::processor::process_asc_file;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use dem_processor::cli::Args;
+use dem_processor::{compare_images, is_dem_file, process_source, write_report, DemSource, ProcessOptions, Processed, Timings};
 
 fn main()-> anyhow::Result<()>{
 
-    println!("🍕 Starting DEM Processor...");
+    let args = Args::parse_with_preset();
+    // RUST_LOG still takes precedence over the -v and -q flags.
+    env_logger::Builder::new().filter_level(args.log_level()).parse_default_env().init();
+
+    info!("🍕 Starting DEM Processor...");
+
+    if let Some([a, b]) = args.compare.as_deref() {
+        return compare(a, b, &args.output_dir);
+    }
     
-    let args = Args::parse();
+    let options = ProcessOptions::try_from(&args)?;
+    if options.format == "jpeg" && options.nodata_color.is_none() {
+        warn!("⚠️ JPEG can't store transparency, NoData cells will be black (pick another with --nodata-color)");
+    }
 
-    if !args.output_dir.exists() {
+    if !args.dry_run && !args.output_dir.exists() {
         fs::create_dir_all(&args.output_dir)?;
     }
 
-    for entry in WalkDir::new(&args.input_dir)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.path().extension().map(|ext| ext == "asc").unwrap_or(false))
-    {
-        let path = entry.path();
-        println!("Processing: {:?}", path);
-        process_asc_file(path, &args)?;
+    // A list names the files to process; otherwise a single file is processed directly and
+    // a directory is searched for DEMs.
+    let (paths, origin): (Vec<PathBuf>, PathBuf) = match (&args.from_file, &args.input_dir) {
+        (Some(list), _) => (read_path_list(list)?, list.clone()),
+        (None, Some(input)) if input.is_file() => {
+            if !is_dem_file(input) {
+                bail!("{:?} is not an .asc, .asc.gz or GeoTIFF file", input);
+            }
+            (vec![input.clone()], input.clone())
+        }
+        (None, Some(input)) => {
+            let mut walker = WalkDir::new(input);
+            if let Some(depth) = args.walk_depth() {
+                walker = walker.max_depth(depth);
+            }
+            let include = glob_set(&args.include)?;
+            let exclude = glob_set(&args.exclude)?;
+            let mut paths: Vec<PathBuf> = walker
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|e| is_dem_file(e.path()))
+                .filter(|e| {
+                    let matches = |globs: &GlobSet| {
+                        globs.is_match(e.file_name()) || e.path().strip_prefix(input).is_ok_and(|rel| globs.is_match(rel))
+                    };
+                    (args.include.is_empty() || matches(&include)) && !matches(&exclude)
+                })
+                .map(|e| e.into_path())
+                .collect();
+            // Directory listings come in whatever order the filesystem keeps.
+            sort_paths(&mut paths, &args.sort);
+            (paths, input.clone())
+        }
+        (None, None) => unreachable!("clap requires --input-dir or --from-file"),
+    };
+
+    // Merging renders every tile into a single output instead of one per file.
+    let sources: Vec<DemSource> = if args.merge {
+        if paths.is_empty() {
+            bail!("no tiles found in {:?}", origin);
+        }
+        vec![DemSource::Mosaic { name: "merged".to_string(), tiles: paths }]
+    } else {
+        paths.into_iter().map(DemSource::File).collect()
+    };
+
+    // Show progress over the whole batch unless every file is reported individually or
+    // the output is quieted.
+    let progress = if args.log_level() != LevelFilter::Info {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(sources.len() as u64)
+    };
+    progress.set_style(ProgressStyle::with_template(
+        "{spinner} [{elapsed_precise}] {bar:40} {pos}/{len} (ETA {eta}) {msg}",
+    )?);
+
+    // Process files in parallel; a failing file is reported without stopping the others.
+    // With --threads-per-file, every batch thread hands its files to a pool of its own so
+    // the work within a file doesn't compete with the other files for the batch threads.
+    let pool = ThreadPoolBuilder::new().num_threads(args.jobs.unwrap_or(0)).build()?;
+    let file_pools = match args.threads_per_file {
+        Some(threads) => (0..pool.current_num_threads())
+            .map(|_| ThreadPoolBuilder::new().num_threads(threads as usize).build())
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    let results: Vec<(&DemSource, Result<Processed, String>, Duration)> = pool.install(|| {
+        sources
+            .par_iter()
+            .map(|source| {
+                debug!("Processing: {}", source);
+                if let DemSource::File(path) = source {
+                    progress.set_message(path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+                }
+                let started = Instant::now();
+                let result = match rayon::current_thread_index().and_then(|i| file_pools.get(i)) {
+                    Some(file_pool) => file_pool.install(|| process_source(source, &options)),
+                    None => process_source(source, &options),
+                };
+                let result = result.map_err(|e| format!("{:#}", e));
+                progress.inc(1);
+                if let Err(reason) = &result {
+                    progress.suspend(|| error!("💔 Failed to process {}: {}", source, reason));
+                }
+                (source, result, started.elapsed())
+            })
+            .collect()
+    });
+    progress.finish_and_clear();
+
+    if args.timing {
+        report_timings(&results);
+    }
+    if let Some(pages) = &options.pages {
+        match pages.page_count() {
+            0 => warn!("⚠️ No images were rendered into {:?}", pages.path()),
+            count => info!("🗂️ Wrote {} pages to {:?}", count, pages.path()),
+        }
     }
+    if let Some(report) = &args.report {
+        write_report(report, &options.modes, &results)?;
+        debug!("📋 Saved the batch report to {:?}", report);
+    }
+    if let Some(limit) = args.preview {
+        preview(&results, limit, args.quiet > 0);
+    }
+    let mut failures: Vec<(String, &String)> = results
+        .iter()
+        .filter_map(|(source, result, _)| Some((source.to_string(), result.as_ref().err()?)))
+        .collect();
 
-    println!("All done! Good job! You deserved a beer! 🍺"); // I'm telling myself
+    // Summarize the batch, repeating every failure since they may have scrolled away.
+    info!("{} succeeded, {} failed", sources.len() - failures.len(), failures.len());
+    if !failures.is_empty() {
+        failures.sort();
+        for (source, reason) in &failures {
+            error!("  {}: {}", source, reason);
+        }
+        bail!("{} of {} files failed", failures.len(), sources.len());
+    }
 
+    info!("All done! Good job! You deserved a beer! 🍺"); // I'm telling myself
+
+    Ok(())
+}
+
+/// Logs how two images differ and writes the difference image next to the other outputs.
+fn compare(a: &Path, b: &Path, output_dir: &Path) -> anyhow::Result<()> {
+    let open = |path: &Path| image::open(path).with_context(|| format!("opening {:?}", path));
+    let (diff, image) = compare_images(&open(a)?, &open(b)?)?;
+    let stem = |path: &Path| path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let diff_path = output_dir.join(format!("{}_vs_{}_diff.png", stem(a), stem(b)));
+    fs::create_dir_all(output_dir)?;
+    image.save(&diff_path)?;
+
+    if diff.differing == 0 {
+        info!("🟰 {:?} and {:?} are identical", a, b);
+    } else {
+        info!(
+            "🔬 {} of {} pixels differ: RMS {:.3}, max {:.3} levels",
+            diff.differing, diff.pixels, diff.rms, diff.max
+        );
+    }
+    debug!("🔬 Saved the difference image to {:?}", diff_path);
     Ok(())
 }
+
+/// Sorts paths by `key`: name, size or mtime, breaking ties by name.
+fn sort_paths(paths: &mut [PathBuf], key: &str) {
+    match key {
+        "size" => paths.sort_by_cached_key(|path| (fs::metadata(path).map(|m| m.len()).ok(), path.clone())),
+        "mtime" => paths.sort_by_cached_key(|path| (fs::metadata(path).and_then(|m| m.modified()).ok(), path.clone())),
+        _ => paths.sort(),
+    }
+}
+
+/// Compiles glob patterns given on the command line into a single matcher.
+fn glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).with_context(|| format!("invalid glob '{}'", pattern))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Reads the paths listed one per line in `list`, or on standard input when it is `-`.
+///
+/// Blank lines are ignored. Paths that don't exist are reported and left out.
+fn read_path_list(list: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let text = if list == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(list).with_context(|| format!("reading file list {:?}", list))?
+    };
+
+    let mut paths = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let path = PathBuf::from(line);
+        if path.is_file() {
+            paths.push(path);
+        } else {
+            warn!("⚠️ Skipping {:?} from {:?}: no such file", path, list);
+        }
+    }
+    Ok(paths)
+}
+
+/// Opens every output of a small batch in the system's default viewer.
+///
+/// Large batches and quiet runs are left alone so a directory of tiles doesn't open a window per tile.
+fn preview(results: &[(&DemSource, Result<Processed, String>, Duration)], limit: usize, quiet: bool) {
+    if quiet {
+        return;
+    }
+    if results.len() > limit {
+        warn!("⚠️ Not previewing {} files; pass --preview {} to open them all", results.len(), results.len());
+        return;
+    }
+    for (_, path) in results.iter().filter_map(|(_, result, _)| result.as_ref().ok()).flat_map(|p| &p.outputs) {
+        if let Err(e) = open::that(path) {
+            warn!("⚠️ Couldn't open {:?} for preview: {}", path, e);
+        }
+    }
+}
+
+/// Logs a table of the time each successful source spent reading, rendering and writing,
+/// followed by the totals.
+fn report_timings(results: &[(&DemSource, Result<Processed, String>, Duration)]) {
+    let ms = |d: Duration| format!("{:.1} ms", d.as_secs_f64() * 1000.0);
+    let row = |name: &str, t: &Timings| {
+        let total = t.read + t.render + t.write;
+        format!("{:<40} {:>12} {:>12} {:>12} {:>12}", name, ms(t.read), ms(t.render), ms(t.write), ms(total))
+    };
+
+    info!("⏱️ {:<40} {:>12} {:>12} {:>12} {:>12}", "file", "read", "render", "write", "total");
+    let mut sum = Timings::default();
+    for (source, result, _) in results {
+        if let Ok(Processed { timings: t, .. }) = result {
+            info!("⏱️ {}", row(&source.to_string(), t));
+            sum.read += t.read;
+            sum.render += t.render;
+            sum.write += t.write;
+        }
+    }
+    info!("⏱️ {}", row("total", &sum));
+}
@@ -0,0 +1,120 @@
+use crate::processor::ElevationGrid;
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// A ring of a polygon as `(x, y)` vertices in map coordinates.
+type Ring = Vec<(f64, f64)>;
+
+/// Polygons that cells are kept inside of, such as a study area or a coastline.
+///
+/// Each polygon is an outer ring followed by its holes. A cell is inside when its center
+/// is inside any of the polygons.
+#[derive(Debug, Clone, Default)]
+pub struct PolygonMask {
+    polygons: Vec<Vec<Ring>>,
+}
+
+impl PolygonMask {
+    /// Reads the polygons of a GeoJSON file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("reading mask file {:?}", path))?;
+        Self::parse(&text).with_context(|| format!("parsing mask file {:?}", path))
+    }
+
+    /// Parses the Polygon and MultiPolygon geometries of a GeoJSON geometry, feature or
+    /// feature collection; other geometries are ignored.
+    pub fn parse(text: &str) -> Result<Self> {
+        let geojson: Value = serde_json::from_str(text)?;
+        let mut mask = Self::default();
+        mask.collect(&geojson)?;
+        if mask.polygons.is_empty() {
+            bail!("no Polygon or MultiPolygon geometries found");
+        }
+        Ok(mask)
+    }
+
+    /// Adds the polygons of a GeoJSON object.
+    fn collect(&mut self, object: &Value) -> Result<()> {
+        match object["type"].as_str() {
+            Some("FeatureCollection") => {
+                for feature in object["features"].as_array().context("a FeatureCollection needs features")? {
+                    self.collect(feature)?;
+                }
+            }
+            Some("Feature") => self.collect(&object["geometry"])?,
+            Some("GeometryCollection") => {
+                for geometry in object["geometries"].as_array().context("a GeometryCollection needs geometries")? {
+                    self.collect(geometry)?;
+                }
+            }
+            Some("Polygon") => self.polygons.push(parse_polygon(&object["coordinates"])?),
+            Some("MultiPolygon") => {
+                for polygon in object["coordinates"].as_array().context("a MultiPolygon needs coordinates")? {
+                    self.polygons.push(parse_polygon(polygon)?);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Turns the cells of a grid whose centers fall outside every polygon into NoData,
+    /// returning how many valid cells were masked.
+    ///
+    /// Each row is rasterized along a scanline through its cell centers, filling between
+    /// pairs of ring crossings so holes stay empty.
+    pub fn clip(&self, grid: &mut ElevationGrid) -> usize {
+        let y_max = grid.y_min + grid.rows() as f64 * grid.cell_height;
+        let cols = grid.cols();
+        let mut inside = vec![false; cols];
+        let mut crossings = Vec::new();
+        let mut masked = 0;
+        for row in 0..grid.rows() {
+            let y = y_max - (row as f64 + 0.5) * grid.cell_height;
+            inside.fill(false);
+            for polygon in &self.polygons {
+                crossings.clear();
+                for ring in polygon {
+                    for (&(x0, y0), &(x1, y1)) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+                        if (y0 > y) != (y1 > y) {
+                            crossings.push(x0 + (y - y0) * (x1 - x0) / (y1 - y0));
+                        }
+                    }
+                }
+                crossings.sort_by(f64::total_cmp);
+                // Cells whose centers lie between a pair of crossings are inside.
+                let center_col = |x: f64| ((x - grid.x_min) / grid.cell_width - 0.5).ceil().clamp(0.0, cols as f64) as usize;
+                for span in crossings.chunks_exact(2) {
+                    inside[center_col(span[0])..center_col(span[1])].fill(true);
+                }
+            }
+            for (col, &keep) in inside.iter().enumerate() {
+                if !keep && !grid.is_nodata(grid.values[row][col]) {
+                    grid.values[row][col] = grid.nodata;
+                    masked += 1;
+                }
+            }
+        }
+        masked
+    }
+}
+
+/// Parses the rings of a GeoJSON Polygon's coordinates.
+fn parse_polygon(coordinates: &Value) -> Result<Vec<Ring>> {
+    let rings = coordinates.as_array().context("a Polygon needs an array of rings")?;
+    rings
+        .iter()
+        .map(|ring| {
+            let positions = ring.as_array().context("a ring needs an array of positions")?;
+            positions
+                .iter()
+                .map(|position| match (position[0].as_f64(), position[1].as_f64()) {
+                    (Some(x), Some(y)) => Ok((x, y)),
+                    _ => bail!("invalid position {}", position),
+                })
+                .collect()
+        })
+        .collect()
+}
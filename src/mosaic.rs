@@ -0,0 +1,68 @@
+use crate::grid::Grid;
+use crate::processor::ElevationGrid;
+use anyhow::{bail, Result};
+
+/// Relative difference allowed between the cell sizes of merged tiles.
+const CELL_SIZE_TOLERANCE: f64 = 1e-6;
+
+/// Places tiles into one grid covering all of them, using their corners and cell size.
+///
+/// Gaps between tiles are NoData. Where tiles overlap, the first tile with a valid value
+/// wins. NoData cells take the first tile's NoData value. All tiles must share a cell size.
+pub fn merge_grids(tiles: &[ElevationGrid]) -> Result<ElevationGrid> {
+    let Some(first) = tiles.first() else {
+        bail!("no tiles to merge");
+    };
+    let (cell_width, cell_height) = (first.cell_width, first.cell_height);
+    let differs = |a: f64, b: f64| (a - b).abs() > CELL_SIZE_TOLERANCE * b;
+    for tile in tiles {
+        if differs(tile.cell_width, cell_width) || differs(tile.cell_height, cell_height) {
+            bail!(
+                "tiles have different cell sizes ({}x{} and {}x{})",
+                cell_width,
+                cell_height,
+                tile.cell_width,
+                tile.cell_height
+            );
+        }
+    }
+
+    // The mosaic extent covers every tile.
+    let x_max_of = |t: &ElevationGrid| t.x_min + t.cols() as f64 * cell_width;
+    let y_max_of = |t: &ElevationGrid| t.y_min + t.rows() as f64 * cell_height;
+    let x_min = tiles.iter().map(|t| t.x_min).fold(f64::INFINITY, f64::min);
+    let y_min = tiles.iter().map(|t| t.y_min).fold(f64::INFINITY, f64::min);
+    let x_max = tiles.iter().map(x_max_of).fold(f64::NEG_INFINITY, f64::max);
+    let y_max = tiles.iter().map(y_max_of).fold(f64::NEG_INFINITY, f64::max);
+    let cols = ((x_max - x_min) / cell_width).round() as usize;
+    let rows = ((y_max - y_min) / cell_height).round() as usize;
+
+    let mut merged = ElevationGrid {
+        values: Grid::new(rows, cols, first.nodata),
+        nodata: first.nodata,
+        x_min,
+        y_min,
+        cell_width,
+        cell_height,
+    };
+
+    // Rows run southward from the northern edge, so offsets are measured from the top left.
+    for tile in tiles {
+        let row_offset = ((y_max - y_max_of(tile)) / cell_height).round() as usize;
+        let col_offset = ((tile.x_min - x_min) / cell_width).round() as usize;
+        for (r, row) in tile.values.iter_rows().enumerate() {
+            for (c, &value) in row.iter().enumerate() {
+                let (target_row, target_col) = (row_offset + r, col_offset + c);
+                if target_row >= rows || target_col >= cols {
+                    continue;
+                }
+                let target = &mut merged.values[target_row][target_col];
+                if !tile.is_nodata(value) && first.is_nodata(*target) {
+                    *target = value;
+                }
+            }
+        }
+    }
+
+    Ok(merged)
+}
@@ -0,0 +1,97 @@
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use std::fmt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tiff::encoder::{colortype, TiffEncoder};
+use tiff::tags::Tag;
+
+/// TIFF tag holding the name of a page.
+const PAGE_NAME: u16 = 285;
+
+/// A TIFF file that rendered images are appended to as pages, from any thread.
+///
+/// Pages are written as soon as they are appended, so a batch never holds more than the
+/// images being rendered. Each page is named after the output it replaces.
+pub struct MultiPageTiff {
+    path: PathBuf,
+    state: Mutex<Pages>,
+}
+
+/// The encoder and the number of pages written so far.
+struct Pages {
+    encoder: TiffEncoder<File>,
+    count: usize,
+}
+
+impl MultiPageTiff {
+    /// Creates the file, replacing any existing one.
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("creating {:?}", path))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(Pages { encoder: TiffEncoder::new(file)?, count: 0 }),
+        })
+    }
+
+    /// Appends an image as the next page. Gray images with alpha are stored as RGBA, since
+    /// TIFF has no gray and alpha color type.
+    pub fn append(&self, name: &str, image: &DynamicImage) -> Result<()> {
+        let mut pages = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (width, height) = (image.width(), image.height());
+        let encoder = &mut pages.encoder;
+        match image {
+            DynamicImage::ImageLuma8(gray) => {
+                let mut page = encoder.new_image::<colortype::Gray8>(width, height)?;
+                page.encoder().write_tag(Tag::Unknown(PAGE_NAME), name)?;
+                page.write_data(gray.as_raw())?;
+            }
+            DynamicImage::ImageLuma16(gray) => {
+                let mut page = encoder.new_image::<colortype::Gray16>(width, height)?;
+                page.encoder().write_tag(Tag::Unknown(PAGE_NAME), name)?;
+                page.write_data(gray.as_raw())?;
+            }
+            DynamicImage::ImageRgb8(rgb) => {
+                let mut page = encoder.new_image::<colortype::RGB8>(width, height)?;
+                page.encoder().write_tag(Tag::Unknown(PAGE_NAME), name)?;
+                page.write_data(rgb.as_raw())?;
+            }
+            DynamicImage::ImageRgb16(rgb) => {
+                let mut page = encoder.new_image::<colortype::RGB16>(width, height)?;
+                page.encoder().write_tag(Tag::Unknown(PAGE_NAME), name)?;
+                page.write_data(rgb.as_raw())?;
+            }
+            DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgba16(_) => {
+                let rgba = image.to_rgba16();
+                let mut page = encoder.new_image::<colortype::RGBA16>(width, height)?;
+                page.encoder().write_tag(Tag::Unknown(PAGE_NAME), name)?;
+                page.write_data(rgba.as_raw())?;
+            }
+            _ => {
+                let rgba = image.to_rgba8();
+                let mut page = encoder.new_image::<colortype::RGBA8>(width, height)?;
+                page.encoder().write_tag(Tag::Unknown(PAGE_NAME), name)?;
+                page.write_data(rgba.as_raw())?;
+            }
+        }
+        pages.count += 1;
+        Ok(())
+    }
+
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of pages appended so far.
+    pub fn page_count(&self) -> usize {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).count
+    }
+}
+
+impl fmt::Debug for MultiPageTiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiPageTiff").field("path", &self.path).finish_non_exhaustive()
+    }
+}
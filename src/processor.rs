@@ -1,181 +1,2960 @@
+use crate::asc_header::{override_cell_size, validate_ascii_header};
+use crate::asc_text::AsciiText;
 use crate::cli::Args;
-use anyhow::Result;
+use crate::colormap_file::ColorRelief;
+use crate::colormaps::Colormap;
+use crate::crs::Crs;
+use crate::contours::{draw_contours, join_segments, trace_contours, write_geojson};
+use crate::fill::fill_nodata;
+use crate::geotiff::{self, GeoTransform};
+use crate::gpkg::write_gpkg;
+use crate::grid::Grid;
+use crate::hydro::{compute_flow_accumulation, fill_sinks};
+use crate::legend::{render_class_legend, render_legend};
+use crate::mask::PolygonMask;
+use crate::mosaic::merge_grids;
+use crate::multi_tiff::MultiPageTiff;
+use crate::reproject::reproject_grid;
+use crate::resample::{image_filter, sample_grid};
+use crate::stats::GridStats;
+use crate::tiles::{self, write_tiles};
+use crate::world_file::write_world_file;
+use anyhow::{bail, Context, Result};
 use esri_ascii_grid::ascii_file::EsriASCIIReader;
-use image::{GrayImage, Luma, RgbImage, Rgb};
-use std::fs::File;
-use std::path::Path;
-use std::f32::consts::PI;
+use esri_ascii_grid::header::EsriASCIIRasterHeader;
+use flate2::read::GzDecoder;
+use image::{ColorType, DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, Luma, LumaA, Pixel, Primitive, RgbImage, Rgb, Rgba};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use log::{debug, info, warn};
+use rayon::prelude::*;
+use std::cell::OnceCell;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::f64::consts::PI;
 
-/// Processes an ASCII grid file and generates output based on the specified mode (grayscale or hillshade).
+/// Options controlling how a single DEM file is rendered.
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    /// Directory where the rendered images are written.
+    pub output_dir: PathBuf,
+    /// Input directory whose subdirectory layout is recreated under `output_dir`, if any.
+    pub preserve_tree: Option<PathBuf>,
+    /// Rendering modes, each written to its own output: grayscale, hillshade, slope, aspect,
+    /// color-relief, tri, tpi, curvature, svf or occlusion.
+    pub modes: Vec<String>,
+    /// Output format: png, jpeg, webp, geotiff, multi-tiff or gpkg.
+    pub format: String,
+    /// Whether GeoTIFFs get internal overviews for quick display when zoomed out.
+    pub overviews: bool,
+    /// The file images are appended to as pages with the multi-tiff format.
+    pub pages: Option<Arc<MultiPageTiff>>,
+    /// Bits per channel of grayscale output, 8 or 16. 16-bit output needs PNG.
+    pub bit_depth: u8,
+    /// JPEG quality from 1 to 100.
+    pub quality: u8,
+    /// PNG compression: fast, default or best.
+    pub png_compression: String,
+    /// Output file name without extension, with `{stem}`, `{mode}`, `{azimuth}` and
+    /// `{altitude}` tokens. Each mode has its own default.
+    pub name_template: Option<String>,
+    /// Write a world file next to non-GeoTIFF outputs.
+    pub world_file: bool,
+    /// Size of the box thumbnails are scaled to fit, if they are written.
+    pub thumbnail: Option<u32>,
+    /// Width and height every image is letterboxed into, if set.
+    pub canvas: Option<[u32; 2]>,
+    /// Color of the letterbox padding; it is transparent when unset.
+    pub pad_color: Option<Rgb<u8>>,
+    /// Record the source file and rendering parameters in PNG text chunks.
+    pub metadata: bool,
+    /// Vertical unit of the input elevations: meters or feet.
+    pub input_units: String,
+    /// Vertical unit elevations are converted to before rendering: meters or feet.
+    pub output_units: String,
+    /// Map area `[min_x, min_y, max_x, max_y]` the grid is cropped to before rendering, if set.
+    pub bbox: Option<[f64; 4]>,
+    /// Pixels `[x, y, width, height]` of the full-resolution image the grid is cropped to, if set.
+    pub crop_pixels: Option<[u32; 4]>,
+    /// Polygons outside which cells become NoData, if set.
+    pub mask: Option<PolygonMask>,
+    /// Average blocks of this many cells on a side before rendering; 1 keeps full resolution.
+    pub downsample: usize,
+    /// Read only every this many rows and columns; 1 reads every cell.
+    pub sample_every: usize,
+    /// How grids and images are resized: nearest, bilinear or bicubic.
+    pub resample: String,
+    /// Largest NoData region, in cells, to fill from its surroundings, or `None` to keep holes.
+    pub fill_nodata: Option<usize>,
+    /// Whether grayscale images are streamed from the file instead of reading the grid.
+    pub low_memory: bool,
+    /// Cell size replacing the one in the file headers, if set.
+    pub cell_size: Option<f64>,
+    /// Tolerance of the sink fill in elevation units, or `None` to keep sinks.
+    pub fill_sinks: Option<f64>,
+    /// Coordinate system of the input coordinates, if known.
+    pub source_crs: Option<Crs>,
+    /// Coordinate system the grid is warped into before rendering, if any.
+    pub reproject: Option<Crs>,
+    /// Whether images are cut into a web map tile pyramid instead of written whole.
+    pub tiles: bool,
+    /// Shallowest and deepest tile zoom levels; unset levels follow from the grid.
+    pub min_zoom: Option<u8>,
+    pub max_zoom: Option<u8>,
+    /// Tile row numbering, xyz or tms.
+    pub tile_scheme: String,
+    /// Vertical exaggeration applied to elevations before hillshading.
+    pub z_factor: f64,
+    /// Opaque color for NoData cells; they are transparent when unset.
+    pub nodata_color: Option<Rgb<u8>>,
+    /// How NoData cells are handled: transparent, black, skip-file or mean-fill.
+    pub nodata_policy: String,
+    /// Cells within this distance of NoData are masked like NoData; 0 disables.
+    pub edge_trim: usize,
+    /// Gamma correction applied to the final image; 1 leaves it unchanged.
+    pub gamma: f64,
+    /// Elevation at or below which cells are painted as water, if set.
+    pub water_level: Option<f64>,
+    /// Color of water cells.
+    pub water_color: Rgb<u8>,
+    /// Elevation spacing of contour lines drawn over the image, if set.
+    pub contour_interval: Option<f64>,
+    /// Where contours go: drawn on the `image`, or written to a `geojson` file.
+    pub contour_format: String,
+    /// Every this many contours is drawn thicker; 0 draws them all alike.
+    pub index_contour: u32,
+    /// Color of contour lines.
+    pub contour_color: Rgb<u8>,
+    /// Color ramp blended with the hillshade.
+    pub colormap: Colormap,
+    /// Color table keyed to elevation, used instead of `colormap` when set.
+    pub color_relief: Option<ColorRelief>,
+    /// Whether the colormap and color-relief file run the other way.
+    pub invert_colormap: bool,
+    /// Name of the colormap or path of the color file, recorded in PNG metadata.
+    pub colormap_name: String,
+    /// Compass direction of the light source in degrees.
+    pub azimuth: f64,
+    /// Light azimuths hillshade and shade modes are rendered at, one frame each, instead of
+    /// `azimuth`; empty for a single image.
+    pub azimuth_sweep: Vec<f64>,
+    /// Height of the light source above the horizon in degrees.
+    pub altitude: f64,
+    /// Combine several light directions instead of a single azimuth.
+    pub multidirectional: bool,
+    /// Azimuths and colors of tinted lights shading the relief instead, if any.
+    pub colored_lights: Vec<(f64, Rgb<u8>)>,
+    /// Multiply a slope shade into the hillshade so steep cells are darker.
+    pub slope_blend: bool,
+    /// Radius in cells of the Gaussian blur applied to the elevations before shading, or 0.
+    pub smooth: usize,
+    /// How the hillshade combines with the colors: multiply, overlay, soft-light or hsv-value.
+    pub blend_mode: String,
+    /// Mix between the plain colors (0) and the fully shaded blend (1).
+    pub hillshade_opacity: f64,
+    /// Haze given to the most distant terrain of hillshade and color-relief images, if any.
+    pub aerial_perspective: Option<f64>,
+    /// Color distant terrain fades toward.
+    pub haze_color: Rgb<u8>,
+    /// What makes terrain distant: low `elevation`, or `distance` north of the southern edge.
+    pub haze_by: String,
+    /// Window radius in cells for the topographic position index.
+    pub tpi_radius: usize,
+    /// Curvature computed in curvature mode: profile, plan or total.
+    pub curvature_type: String,
+    /// Ascending slopes in degrees that split the classes of slope-class mode.
+    pub slope_breaks: Vec<f64>,
+    /// Color of each slope class, one more than there are breaks.
+    pub slope_colors: Vec<Rgb<u8>>,
+    /// Horizon search distance in cells for the sky-view factor.
+    pub svf_radius: usize,
+    /// Number of azimuths sampled for the sky-view factor.
+    pub svf_directions: usize,
+    /// Horizon search distance in cells for ambient occlusion.
+    pub ao_radius: usize,
+    /// How strongly sheltering terrain darkens cells in ambient occlusion.
+    pub ao_strength: f64,
+    /// Overwrite outputs that already exist instead of skipping them.
+    pub force: bool,
+    /// Report the planned outputs after checking each header, without rendering anything.
+    pub dry_run: bool,
+    /// Log summary statistics of every grid.
+    pub stats: bool,
+    /// Write summary statistics to a JSON file next to the outputs.
+    pub stats_json: bool,
+    /// Write a color legend for the elevation range to `<stem>_legend.png`.
+    pub legend: bool,
+    /// Percentage of valid cells clipped at each end of the range before normalizing.
+    pub clip_percent: f64,
+    /// How elevations are stretched to gray levels: linear or log.
+    pub normalize: String,
+    /// Elevation mapped to black, instead of the file's minimum.
+    pub min_elev: Option<f64>,
+    /// Elevation mapped to white, instead of the file's maximum.
+    pub max_elev: Option<f64>,
+    /// Gray level, out of 255, given to every valid cell of a flat grid.
+    pub flat_level: u8,
+}
+
+impl ProcessOptions {
+    /// Creates options that write grayscale images to `output_dir`.
+    pub fn new(output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            preserve_tree: None,
+            modes: vec!["grayscale".to_string()],
+            format: "png".to_string(),
+            overviews: true,
+            pages: None,
+            bit_depth: 8,
+            quality: 90,
+            png_compression: "fast".to_string(),
+            name_template: None,
+            world_file: false,
+            thumbnail: None,
+            canvas: None,
+            pad_color: None,
+            metadata: true,
+            input_units: "meters".to_string(),
+            output_units: "meters".to_string(),
+            bbox: None,
+            crop_pixels: None,
+            mask: None,
+            downsample: 1,
+            sample_every: 1,
+            resample: "bilinear".to_string(),
+            fill_nodata: None,
+            low_memory: false,
+            cell_size: None,
+            fill_sinks: None,
+            source_crs: None,
+            reproject: None,
+            tiles: false,
+            min_zoom: None,
+            max_zoom: None,
+            tile_scheme: "xyz".to_string(),
+            z_factor: 1.0,
+            nodata_color: None,
+            nodata_policy: "transparent".to_string(),
+            edge_trim: 0,
+            gamma: 1.0,
+            water_level: None,
+            water_color: Rgb([120, 170, 210]),
+            contour_interval: None,
+            contour_format: "image".to_string(),
+            index_contour: 5,
+            contour_color: Rgb([120, 80, 40]),
+            colormap: Colormap::default(),
+            colormap_name: "classic".to_string(),
+            color_relief: None,
+            invert_colormap: false,
+            azimuth: 315.0,
+            azimuth_sweep: Vec::new(),
+            altitude: 45.0,
+            multidirectional: false,
+            colored_lights: Vec::new(),
+            slope_blend: false,
+            smooth: 0,
+            blend_mode: "multiply".to_string(),
+            hillshade_opacity: 1.0,
+            aerial_perspective: None,
+            haze_color: Rgb([200, 215, 230]),
+            haze_by: "elevation".to_string(),
+            tpi_radius: 3,
+            curvature_type: "total".to_string(),
+            slope_breaks: vec![5.0, 15.0, 30.0],
+            slope_colors: vec![Rgb([26, 152, 80]), Rgb([166, 217, 106]), Rgb([253, 174, 97]), Rgb([215, 48, 39])],
+            svf_radius: 10,
+            svf_directions: 16,
+            ao_radius: 5,
+            ao_strength: 1.0,
+            force: false,
+            dry_run: false,
+            stats: false,
+            stats_json: false,
+            legend: false,
+            clip_percent: 0.0,
+            normalize: "linear".to_string(),
+            min_elev: None,
+            max_elev: None,
+            flat_level: 128,
+        }
+    }
+
+    /// The colormap applied to normalized elevations, reversed if asked.
+    fn gray_colormap(&self) -> Colormap {
+        if self.invert_colormap { self.colormap.reversed() } else { self.colormap.clone() }
+    }
+
+    /// Position of flat grids on the `0..=1` scale of a stretch.
+    fn flat_position(&self) -> f64 {
+        self.flat_level as f64 / 255.0
+    }
+
+    /// The coordinate system of the rendered grid, if the input's is known.
+    fn output_crs(&self) -> Option<Crs> {
+        self.source_crs.map(|crs| self.reproject.unwrap_or(crs))
+    }
+
+    /// The colormap applied to raw elevations in `min_val..=max_val`: the color-relief file
+    /// if one is set or the hypsometric ramp otherwise, reversed if asked.
+    fn elevation_colormap(&self, min_val: f64, max_val: f64) -> Colormap {
+        let colormap = match &self.color_relief {
+            Some(relief) => relief.to_colormap(min_val, max_val),
+            None => Colormap::hypsometric(),
+        };
+        if self.invert_colormap { colormap.reversed() } else { colormap }
+    }
+}
+
+/// Returns true if the arguments ask for anything beyond a plain grayscale stretch, which
+/// needs the whole grid in memory.
+fn needs_whole_grid(args: &Args) -> bool {
+    args.mode.iter().any(|mode| mode != "grayscale")
+        || args.merge
+        || args.format == "geotiff"
+        || args.bit_depth == 16
+        || args.normalize != "linear"
+        || args.clip_percent > 0.0
+        || args.input_units != args.output_units
+        || args.downsample > 1
+        || args.sample_every > 1
+        || args.bbox.is_some()
+        || args.crop_pixels.is_some()
+        || args.mask_file.is_some()
+        || args.fill_nodata
+        || args.fill_sinks
+        || args.cell_size.is_some()
+        || args.reproject.is_some()
+        || args.tiles
+        || args.stats
+        || args.stats_json
+        || args.legend
+        || args.contours.is_some()
+        || args.water_level.is_some()
+        || args.nodata_color.is_some()
+        || args.nodata_policy != "transparent"
+        || args.edge_trim > 0
+}
+
+/// Returns the azimuths from `start` up to, but not including, `end` in `step` degrees.
+fn sweep_azimuths(start: f64, end: f64, step: f64) -> Vec<f64> {
+    (0..).map(|i| start + i as f64 * step).take_while(|&azimuth| azimuth < end).collect()
+}
+
+impl TryFrom<&Args> for ProcessOptions {
+    type Error = anyhow::Error;
+
+    fn try_from(args: &Args) -> Result<Self> {
+        if args.bit_depth == 16 && !matches!(args.format.as_str(), "png" | "geotiff" | "multi-tiff") {
+            bail!("16-bit output is only supported for PNG and TIFF, not {}", args.format);
+        }
+        if args.low_memory && needs_whole_grid(args) {
+            bail!("--low-memory only renders plain grayscale PNG, JPEG or WebP images with a linear stretch");
+        }
+        if args.tiles {
+            if matches!(args.format.as_str(), "geotiff" | "multi-tiff" | "gpkg") || args.bit_depth == 16 {
+                bail!("tiles are written as 8-bit PNG, JPEG or WebP images");
+            }
+            if args.reproject.is_some_and(|crs| crs != Crs::WebMercator) {
+                bail!("tiles are cut in EPSG:3857; drop --reproject or use it with EPSG:3857");
+            }
+        }
+        if args.azimuth_sweep.is_some() && !args.mode.iter().any(|mode| matches!(mode.as_str(), "hillshade" | "shade")) {
+            warn!("🎞️ --azimuth-sweep only renders frames in hillshade and shade modes");
+        }
+        if args.aerial_perspective.is_some() && !args.mode.iter().any(|mode| matches!(mode.as_str(), "hillshade" | "color-relief")) {
+            warn!("🌫️ --aerial-perspective only hazes hillshade and color-relief images");
+        }
+        if args.canvas.is_some() && (matches!(args.format.as_str(), "geotiff" | "gpkg") || args.world_file || args.tiles) {
+            bail!("letterboxed images no longer line up with the map; drop --canvas for GeoTIFF, GeoPackage, world file or tile output");
+        }
+        if args.thumbnail.is_some() && args.format == "geotiff" {
+            bail!("thumbnails are made from rendered images; use --thumbnail with PNG, JPEG or WebP output");
+        }
+        if [args.min_elev, args.max_elev].iter().flatten().any(|bound| !bound.is_finite()) {
+            bail!("--min-elev and --max-elev must be finite");
+        }
+        if let (Some(min_elev), Some(max_elev)) = (args.min_elev, args.max_elev)
+            && min_elev >= max_elev
+        {
+            bail!("--min-elev {} must be below --max-elev {}", min_elev, max_elev);
+        }
+        if !args.slope_breaks.is_sorted_by(|a, b| a < b) {
+            bail!("--slope-breaks must be in ascending order");
+        }
+        if args.slope_colors.len() != args.slope_breaks.len() + 1 {
+            bail!(
+                "{} slope breaks make {} classes, but --slope-colors gives {} colors",
+                args.slope_breaks.len(),
+                args.slope_breaks.len() + 1,
+                args.slope_colors.len()
+            );
+        }
+        // Pages are appended as files finish, so the file is opened before any is processed.
+        let pages = match (&args.output_file, args.format.as_str()) {
+            (Some(path), "multi-tiff") if !args.dry_run => {
+                if path.exists() && !args.force {
+                    bail!("{:?} already exists (use --force to overwrite)", path);
+                }
+                Some(Arc::new(MultiPageTiff::create(path)?))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            output_dir: args.output_dir.clone(),
+            preserve_tree: args.input_dir.clone().filter(|_| args.preserve_tree),
+            modes: args.mode.clone(),
+            format: args.format.clone(),
+            overviews: !args.no_overviews,
+            pages,
+            bit_depth: args.bit_depth,
+            quality: args.quality,
+            png_compression: args.png_compression.clone(),
+            name_template: args.name_template.clone(),
+            world_file: args.world_file,
+            thumbnail: args.thumbnail,
+            canvas: args.canvas,
+            pad_color: args.pad_color,
+            metadata: !args.no_metadata,
+            input_units: args.input_units.clone(),
+            output_units: args.output_units.clone(),
+            bbox: args.bbox,
+            crop_pixels: args.crop_pixels,
+            mask: args.mask_file.as_deref().map(PolygonMask::from_file).transpose()?,
+            downsample: args.downsample as usize,
+            sample_every: args.sample_every as usize,
+            resample: args.resample.clone(),
+            fill_nodata: args.fill_nodata.then_some(args.fill_max_size),
+            low_memory: args.low_memory,
+            cell_size: args.cell_size,
+            fill_sinks: args.fill_sinks.then_some(args.fill_tolerance),
+            source_crs: args.source_crs,
+            // Tiles are cut from web Mercator images.
+            reproject: if args.tiles { Some(Crs::WebMercator) } else { args.reproject },
+            tiles: args.tiles,
+            min_zoom: args.min_zoom,
+            max_zoom: args.max_zoom,
+            tile_scheme: args.tile_scheme.clone(),
+            z_factor: args.z_factor,
+            nodata_color: args.nodata_color,
+            nodata_policy: args.nodata_policy.clone(),
+            edge_trim: args.edge_trim as usize,
+            gamma: args.gamma,
+            water_level: args.water_level,
+            water_color: args.water_color,
+            contour_interval: args.contours,
+            contour_format: args.contour_format.clone(),
+            index_contour: args.index_contour,
+            contour_color: args.contour_color,
+            colormap: Colormap::by_name(&args.colormap).expect("colormap name is validated by clap"),
+            color_relief: args.colormap_file.as_deref().map(ColorRelief::from_file).transpose()?,
+            invert_colormap: args.invert_colormap,
+            colormap_name: match &args.colormap_file {
+                Some(path) => path.display().to_string(),
+                None => args.colormap.clone(),
+            },
+            azimuth: args.azimuth,
+            azimuth_sweep: args.azimuth_sweep.map(|[start, end, step]| sweep_azimuths(start, end, step)).unwrap_or_default(),
+            altitude: args.altitude,
+            multidirectional: args.multidirectional,
+            colored_lights: args.colored_lights.clone(),
+            slope_blend: args.slope_blend,
+            smooth: args.smooth as usize,
+            blend_mode: args.blend_mode.clone(),
+            hillshade_opacity: args.hillshade_opacity,
+            aerial_perspective: args.aerial_perspective,
+            haze_color: args.haze_color,
+            haze_by: args.haze_by.clone(),
+            tpi_radius: args.tpi_radius as usize,
+            curvature_type: args.curvature_type.clone(),
+            slope_breaks: args.slope_breaks.clone(),
+            slope_colors: args.slope_colors.clone(),
+            svf_radius: args.svf_radius as usize,
+            svf_directions: args.svf_directions as usize,
+            ao_radius: args.ao_radius as usize,
+            ao_strength: args.ao_strength,
+            force: args.force,
+            dry_run: args.dry_run,
+            stats: args.stats,
+            stats_json: args.stats_json,
+            legend: args.legend,
+            clip_percent: args.clip_percent,
+            normalize: args.normalize.clone(),
+            min_elev: args.min_elev,
+            max_elev: args.max_elev,
+            flat_level: args.flat_level,
+        })
+    }
+}
+
+/// Processes an ASCII grid or GeoTIFF DEM using the options given on the command line.
 pub fn process_asc_file(path: &Path, args: &Args) -> Result<()> {
-    // Convert the ASCII grid file to a grayscale image.
-    let image = ascii_to_image(path)?;
-
-    // Handle different processing modes based on user input.
-    match args.mode.as_str() {
-        "grayscale" => {
-            // Save the grayscale image to the output directory.
-            let output_path = args.output_dir.join(format!("{}.png", path.file_stem().unwrap().to_string_lossy()));
-            image.save(&output_path)?;
-            println!("🍤 Saved grayscale image to {:?}", output_path);
-        }
-        "hillshade" => {
-            // Generate a colormap from the grayscale image.
-            let color_img = grayscale_to_colormap(&image);
-            // Apply hillshading to the grayscale image.
-            let hillshade = apply_hillshade(&image, 30.0, 315.0, 45.0);
-            // Blend the colormap with the hillshade.
-            let blended = blend_colormap_with_hillshade(&color_img, &hillshade);
-            // Save the hillshaded image to the output directory.
-            let output_path = args.output_dir.join(format!("{}_hillshade.png", path.file_stem().unwrap().to_string_lossy()));
-            blended.save(&output_path)?;
-            println!("🧋 Saved hillshaded image to {:?}", output_path);
-        }
-        _ => println!("💔 Unsupported mode: {}", args.mode),
+    process_asc_file_with(path, &ProcessOptions::try_from(args)?)
+}
+
+/// Processes an ASCII grid or GeoTIFF DEM and generates output based on the specified mode.
+pub fn process_asc_file_with(path: &Path, options: &ProcessOptions) -> Result<()> {
+    process_source(&DemSource::File(path.to_path_buf()), options).map(|_| ())
+}
+
+/// What processing one source wrote, and how long it took.
+#[derive(Debug, Clone, Default)]
+pub struct Processed {
+    /// Mode and path of every output written; outputs skipped as existing are left out.
+    pub outputs: Vec<(String, PathBuf)>,
+    /// Minimum and maximum of the valid cells once the grid is prepared, if it was read.
+    pub elevation_range: Option<(f64, f64)>,
+    /// Time spent in each phase.
+    pub timings: Timings,
+}
+
+/// Time spent in each phase of processing one source. Phases that were skipped take no time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    /// Reading and parsing the elevations.
+    pub read: Duration,
+    /// Terrain math and coloring, from preparing the grid to the finished image.
+    pub render: Duration,
+    /// Encoding and writing outputs and sidecar files.
+    pub write: Duration,
+}
+
+/// Where the elevations rendered into one output come from.
+#[derive(Debug, Clone)]
+pub enum DemSource {
+    /// A single ASCII grid or GeoTIFF file.
+    File(PathBuf),
+    /// Adjacent tiles merged into one grid, written under `name`.
+    Mosaic { name: String, tiles: Vec<PathBuf> },
+}
+
+impl DemSource {
+    /// The name outputs are derived from.
+    fn stem(&self) -> String {
+        match self {
+            DemSource::File(path) => {
+                // Drop both extensions of gzipped grids such as `tile.asc.gz`.
+                let stem = Path::new(path.file_stem().unwrap());
+                let stem = if is_gzipped(path) { Path::new(stem.file_stem().unwrap()) } else { stem };
+                stem.to_string_lossy().into_owned()
+            }
+            DemSource::Mosaic { name, .. } => name.clone(),
+        }
+    }
+
+    /// The directory outputs are written to, mirroring the file's place in the input tree
+    /// when the tree is preserved.
+    fn output_dir(&self, options: &ProcessOptions) -> PathBuf {
+        let relative = match (self, &options.preserve_tree) {
+            (DemSource::File(path), Some(root)) => path.parent().and_then(|dir| dir.strip_prefix(root).ok()),
+            _ => None,
+        };
+        relative.map_or_else(|| options.output_dir.clone(), |dir| options.output_dir.join(dir))
+    }
+
+    /// Checks the headers without reading the cells and describes the source's size.
+    fn check_headers(&self, cell_size: Option<f64>) -> Result<String> {
+        match self {
+            DemSource::File(path) => {
+                let (rows, cols) = read_grid_size_with_cell_size(path, cell_size)?;
+                Ok(format!("{}x{}", cols, rows))
+            }
+            DemSource::Mosaic { tiles, .. } => {
+                for tile in tiles {
+                    read_grid_size_with_cell_size(tile, cell_size).with_context(|| format!("reading {:?}", tile))?;
+                }
+                Ok(format!("{} tiles", tiles.len()))
+            }
+        }
+    }
+
+    /// Reads the elevations, merging tiles into one grid, keeping every `sample_every`th
+    /// row and column.
+    ///
+    /// Mosaics are sampled once merged, so the kept cells of neighboring tiles line up.
+    fn read(&self, cell_size: Option<f64>, sample_every: usize) -> Result<ElevationGrid> {
+        let grid = match self {
+            DemSource::File(path) if sample_every > 1 && !is_geotiff(path) => {
+                let text: Box<dyn Read> = match cell_size {
+                    Some(cell_size) => Box::new(ascii_with_cell_size(path, cell_size)?),
+                    None => Box::new(open_ascii_text(path)?),
+                };
+                return read_ascii_grid_sampled_from(text, sample_every);
+            }
+            DemSource::File(path) => read_grid_with_cell_size(path, cell_size)?,
+            DemSource::Mosaic { tiles, .. } => {
+                let grids = tiles
+                    .iter()
+                    .map(|tile| read_grid_with_cell_size(tile, cell_size).with_context(|| format!("reading {:?}", tile)))
+                    .collect::<Result<Vec<_>>>()?;
+                merge_grids(&grids)?
+            }
+        };
+        Ok(if sample_every > 1 { grid.subsample(sample_every) } else { grid })
+    }
+}
+
+impl fmt::Display for DemSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DemSource::File(path) => write!(f, "{:?}", path),
+            DemSource::Mosaic { name, tiles } => write!(f, "mosaic {:?} of {} tiles", name, tiles.len()),
+        }
+    }
+}
+
+/// Renders the elevations of a file or mosaic based on the specified mode.
+pub fn process_source(source: &DemSource, options: &ProcessOptions) -> Result<Processed> {
+    let extension = match options.format.as_str() {
+        "png" => "png",
+        "geotiff" | "multi-tiff" => "tif",
+        "jpeg" => "jpg",
+        "webp" => "webp",
+        "gpkg" => "gpkg",
+        other => bail!("Unsupported format: {}", other),
+    };
+    // Every requested mode is rendered from a single read of the elevations.
+    let stem = source.stem();
+    let output_dir = source.output_dir(options);
+    let mut outputs: Vec<Output> = Vec::new();
+    for mode in &options.modes {
+        let Some(default_template) = default_name_template(mode) else {
+            warn!("💔 Unsupported mode: {}", mode);
+            continue;
+        };
+        // Modes lit from one direction render a frame per azimuth of a sweep.
+        let frames: Vec<Option<f64>> = match default_frame_template(mode) {
+            Some(_) if !options.azimuth_sweep.is_empty() => options.azimuth_sweep.iter().copied().map(Some).collect(),
+            _ => vec![None],
+        };
+        for frame in frames {
+            let template = match (&options.name_template, frame) {
+                (Some(template), _) => template.as_str(),
+                (None, Some(_)) => default_frame_template(mode).unwrap_or(default_template),
+                (None, None) => default_template,
+            };
+            if frame.is_some() && !template.contains("{azimuth}") {
+                bail!("azimuth sweep frames need {{azimuth}} in the name template '{}'", template);
+            }
+            let name = render_name(template, &stem, mode, frame, options)?;
+            // Tiles go into a directory named like the image.
+            let output_path = if options.tiles {
+                output_dir.join(&name)
+            } else {
+                output_dir.join(format!("{}.{}", name, extension))
+            };
+            if outputs.iter().any(|(_, _, path, _)| *path == output_path) {
+                bail!("several modes would write {:?}; add {{mode}} to the name template", output_path);
+            }
+            outputs.push((mode, name, output_path, frame));
+        }
+    }
+
+    // A dry run only checks that the header can be read and reports what would be written.
+    if options.dry_run {
+        let size = source.check_headers(options.cell_size)?;
+        for (mode, _, output_path, _) in &outputs {
+            let action = if output_path.exists() && !options.force { "would skip existing" } else { "would write" };
+            info!("🔍 {} ({}): {} {:?} in {} mode", source, size, action, output_path, mode);
+        }
+        return Ok(Processed::default());
+    }
+
+    // Leave existing outputs alone unless asked to overwrite them. Statistics are still reported.
+    let wants_stats = options.stats || options.stats_json;
+    outputs.retain(|(_, _, output_path, _)| {
+        let skip = output_path.exists() && !options.force;
+        if skip {
+            info!("⏭️ Skipping {}: {:?} already exists (use --force to overwrite)", source, output_path);
+        }
+        !skip
+    });
+    let skip = outputs.is_empty();
+    if skip && !wants_stats {
+        return Ok(Processed::default());
+    }
+
+    // Subdirectories of a preserved input tree are created as they are needed.
+    fs::create_dir_all(&output_dir)?;
+    if options.low_memory {
+        return stream_grayscale(source, outputs, options);
+    }
+
+    // Read the elevations and convert them to a grayscale image.
+    // Fixed bounds override the file's own range so adjacent tiles share one stretch.
+    // Percentile clipping ignores outlier spikes and pits when stretching.
+    let mut timings = Timings::default();
+    let reading = Instant::now();
+    let mut grid = source.read(options.cell_size, options.sample_every)?;
+    timings.read = reading.elapsed();
+    if grid.is_integer() {
+        debug!("🔢 {} holds integer values", source);
+    }
+    let rendering = Instant::now();
+    if options.input_units != options.output_units {
+        grid.scale_values(meters_per_unit(&options.input_units)? / meters_per_unit(&options.output_units)?);
+    }
+    if let Some(max_size) = options.fill_nodata {
+        let filled = fill_nodata(&mut grid, max_size);
+        debug!("🩹 Filled {} NoData cells in {}", filled, source);
+    }
+    apply_nodata_policy(&mut grid, &options.nodata_policy)?;
+    if let Some(tolerance) = options.fill_sinks {
+        let raised = fill_sinks(&mut grid, tolerance);
+        debug!("🕳️ Raised {} cells filling sinks in {}", raised, source);
+    }
+    if let (Some(from), Some(to)) = (options.source_crs, options.reproject) {
+        grid = reproject_grid(&grid, from, to, &options.resample)?;
+        debug!("🌐 Reprojected {} from {} to {}: {}x{} cells", source, from, to, grid.cols(), grid.rows());
+    }
+    if let Some(bbox) = options.bbox {
+        grid = grid.crop_to_bbox(bbox);
+        debug!("✂️ Cropped {} to {}x{} cells", source, grid.cols(), grid.rows());
+    } else if let Some(pixels) = options.crop_pixels {
+        grid = grid.crop_to_pixels(pixels).with_context(|| format!("cropping {}", source))?;
+        debug!("✂️ Cropped {} to {}x{} cells", source, grid.cols(), grid.rows());
+    }
+    if let Some(mask) = &options.mask {
+        let masked = mask.clip(&mut grid);
+        debug!("🎭 Masked {} cells of {} outside the mask polygons", masked, source);
+    }
+    if options.downsample > 1 {
+        grid = grid.downsample(options.downsample, &options.resample);
+    }
+    if wants_stats {
+        report_stats(source, &grid, &output_dir.join(format!("{}_stats.json", stem)), options)?;
+    }
+    let (low, high) = grid.value_range();
+    let mut processed = Processed { elevation_range: (low <= high).then_some((low, high)), ..Processed::default() };
+    if skip {
+        return Ok(processed);
+    }
+    // Tiles of nothing but NoData, such as open sea, have no range to stretch. Every mode
+    // renders them fully transparent, or in the NoData color, and they get no legend.
+    if processed.elevation_range.is_none() {
+        warn!("⚠️ {} has no valid cells; its images will be empty", source);
+    }
+    let (min_val, max_val) = if options.clip_percent > 0.0 {
+        grid.percentile_range(options.clip_percent)
+    } else {
+        grid.value_range()
+    };
+    let (min_val, max_val, flat) = stretch_range(source, options, min_val, max_val);
+    let scale = value_scale(&grid, &options.normalize, min_val, max_val, flat);
+    let image = grid_to_levels(&grid, scale, |level| (level * 255.0).round() as u8);
+
+    // Sweep frames share the colors and only shade the relief again.
+    let colors = OnceCell::new();
+    for (mode, name, output_path, frame) in outputs {
+        // Each sweep frame is lit from its own azimuth.
+        let frame_options;
+        let options = match frame {
+            Some(azimuth) => {
+                frame_options = ProcessOptions { azimuth, ..options.clone() };
+                &frame_options
+            }
+            None => options,
+        };
+
+        // The legend shows the colors of elevation-colored modes over the stretched range.
+        // Sweep frames share one legend, written with the first frame.
+        let first_frame = frame.is_none_or(|azimuth| options.azimuth_sweep.first() == Some(&azimuth));
+        // Slope classes get a swatch for each class instead.
+        if options.legend && processed.elevation_range.is_some() && first_frame {
+            let legend = match mode {
+                "slope-class" => Some(render_class_legend(&slope_class_labels(&options.slope_breaks), &options.slope_colors)),
+                _ => legend_colors(mode, &grid, options, min_val, max_val, flat).map(|color| render_legend(min_val, max_val, color)),
+            };
+            match legend {
+                Some(legend) => {
+                    let legend_path = output_dir.join(format!("{}_legend.png", name));
+                    timed(&mut timings.write, || legend.save(&legend_path))?;
+                    debug!("🏷️ Saved legend to {:?}", legend_path);
+                }
+                None => warn!("🏷️ Legends are not supported in {} mode", mode),
+            }
+        }
+
+        // Record where each image came from in its PNG text chunks.
+        let text = if options.metadata { png_text(source, mode, options) } else { Vec::new() };
+
+        // Handle different processing modes based on user input.
+        match mode {
+            "grayscale" => {
+                // Save the grayscale image to the output directory. GeoTIFFs keep the raw elevations.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &grid, options))?;
+                } else {
+                    let gray: DynamicImage = match options.water_level {
+                        Some(level) => {
+                            let mut rgb = DynamicImage::ImageLuma8(image.clone()).into_rgb8();
+                            paint_water(&mut rgb, &grid, level, options.water_color);
+                            rgb.into()
+                        }
+                        None if options.bit_depth == 16 => {
+                            normalize_grid_16(&grid, min_val, max_val, &options.normalize, flat).into()
+                        }
+                        None => image.clone().into(),
+                    };
+                    timed(&mut timings.write, || {
+                        save_image(gray, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("🍤 Saved grayscale image to {:?}", output_path);
+            }
+            "hillshade" => {
+                // Generate a colormap from the grayscale image, or from the raw elevations for color files.
+                let color_img = colors.get_or_init(|| match &options.color_relief {
+                    Some(_) => elevation_to_colormap(&grid, &options.elevation_colormap(min_val, max_val)),
+                    None => grayscale_to_colormap(&image, &options.gray_colormap()),
+                });
+                let hillshade = shaded_relief(&grid, options)?;
+                // Blend the colormap with the hillshade, then paint water flat over it.
+                let mut blended = blend_colormap_with_hillshade(
+                    color_img,
+                    &hillshade,
+                    &options.blend_mode,
+                    options.hillshade_opacity,
+                )?;
+                // Below full opacity the voids would keep some of the colormap.
+                clear_nodata(&mut blended, &grid);
+                if let Some(level) = options.water_level {
+                    paint_water(&mut blended, &grid, level, options.water_color);
+                }
+                if let Some(strength) = options.aerial_perspective {
+                    apply_aerial_perspective(&mut blended, &grid, strength, options.haze_color, &options.haze_by, min_val, max_val);
+                }
+                // Save the hillshaded image to the output directory.
+                if options.format == "geotiff" {
+                    apply_gamma(&mut blended, options.gamma);
+                    timed(&mut timings.write, || write_rgb_geotiff(&output_path, &blended, &grid, options))?;
+                } else {
+                    timed(&mut timings.write, || {
+                        save_image(blended, &grid, relief_nodata_color(options), &output_path, &text, options)
+                    })?;
+                }
+                debug!("🧋 Saved hillshaded image to {:?}", output_path);
+            }
+            "shade" => {
+                // The shade only depends on the local slope and aspect, never on the elevation
+                // range of the file, so adjacent tiles shade alike.
+                let shade = shaded_relief(&grid, options)?;
+                if options.format == "geotiff" {
+                    let mut shade = shade;
+                    apply_gamma(&mut shade, options.gamma);
+                    timed(&mut timings.write, || write_rgb_geotiff(&output_path, &shade, &grid, options))?;
+                } else {
+                    // Tinted lights make a colored relief; a plain shade is stored as gray.
+                    let shade = if options.colored_lights.is_empty() {
+                        DynamicImage::ImageRgb8(shade).into_luma8().into()
+                    } else {
+                        DynamicImage::ImageRgb8(shade)
+                    };
+                    timed(&mut timings.write, || {
+                        save_image(shade, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("🌗 Saved shaded relief to {:?}", output_path);
+            }
+            "slope" => {
+                // Compute the slope in degrees from the raw elevations.
+                let slope = compute_slope(&grid, options.z_factor);
+                // Save the slope map to the output directory. GeoTIFFs keep the slope in degrees.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &slope, options))?;
+                } else {
+                    let slope_img = normalize_grid(&slope, 0.0, 90.0);
+                    timed(&mut timings.write, || {
+                        save_image(slope_img, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("⛰️ Saved slope map to {:?}", output_path);
+            }
+            "slope-class" => {
+                // Bin the slope in degrees into the classes split at the breaks.
+                let classes = classify_slope(&compute_slope(&grid, options.z_factor), &options.slope_breaks);
+                // Save the class map to the output directory. GeoTIFFs keep the class numbers.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &classes, options))?;
+                } else {
+                    let class_img = classes_to_image(&classes, &options.slope_colors);
+                    timed(&mut timings.write, || {
+                        save_image(class_img, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("🚦 Saved slope classes to {:?}", output_path);
+            }
+            "aspect" => {
+                // Compute the aspect in compass degrees from the raw elevations.
+                let aspect = compute_aspect(&grid);
+                // Save the aspect map to the output directory. GeoTIFFs keep the aspect in degrees.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &aspect, options))?;
+                } else {
+                    let aspect_img = aspect_to_image(&aspect);
+                    timed(&mut timings.write, || {
+                        save_image(aspect_img, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("🧭 Saved aspect map to {:?}", output_path);
+            }
+            "tri" => {
+                // Compute the ruggedness from the raw elevations.
+                let tri = compute_tri(&grid);
+                // Save the ruggedness map to the output directory. GeoTIFFs keep the index in elevation units.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &tri, options))?;
+                } else {
+                    let (min_tri, max_tri) = tri.value_range();
+                    let tri_img = normalize_grid(&tri, min_tri, max_tri);
+                    timed(&mut timings.write, || {
+                        save_image(tri_img, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("🪨 Saved ruggedness map to {:?}", output_path);
+            }
+            "occlusion" => {
+                // Darken every cell by how much of its surroundings rise above it.
+                let occlusion = compute_occlusion(&grid, options.z_factor, options.ao_radius, options.ao_strength);
+                // Save the occlusion shade to the output directory. GeoTIFFs keep the 0-1 shade.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &occlusion, options))?;
+                } else {
+                    let occlusion_img = normalize_grid(&occlusion, 0.0, 1.0);
+                    timed(&mut timings.write, || {
+                        save_image(occlusion_img, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("🌑 Saved ambient occlusion map to {:?}", output_path);
+            }
+            "curvature" => {
+                // Fit a surface to every neighborhood and take its second derivatives.
+                let curvature = compute_curvature(&grid, options.z_factor, &options.curvature_type)?;
+                // Save the curvature map to the output directory. GeoTIFFs keep the curvature values.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &curvature, options))?;
+                } else {
+                    // Center the diverging colormap on zero, ignoring the sharpest 2% at either end.
+                    let (low, high) = curvature.percentile_range(2.0);
+                    let colormap = Colormap::diverging(low.abs().max(high.abs()));
+                    let curvature_img = elevation_to_colormap(&curvature, &colormap);
+                    timed(&mut timings.write, || {
+                        save_image(curvature_img, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("〰️ Saved curvature map to {:?}", output_path);
+            }
+            "svf" => {
+                // Trace the horizon around every cell.
+                let svf = compute_sky_view_factor(&grid, options.z_factor, options.svf_radius, options.svf_directions);
+                // Save the sky-view map to the output directory. GeoTIFFs keep the 0-1 factor.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &svf, options))?;
+                } else {
+                    let (min_svf, max_svf) = svf.value_range();
+                    let svf_img = normalize_grid(&svf, min_svf, max_svf);
+                    timed(&mut timings.write, || {
+                        save_image(svf_img, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("🌌 Saved sky-view factor map to {:?}", output_path);
+            }
+            "flow-accum" => {
+                // Route every cell downhill and count the cells draining through it.
+                let accumulation = compute_flow_accumulation(&grid);
+                // Save the drainage map to the output directory. GeoTIFFs keep the upstream cell counts.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &accumulation, options))?;
+                } else {
+                    // A log scale makes the few large streams and the many small rills both visible.
+                    let (_, max_accumulation) = accumulation.value_range();
+                    let accumulation_img = log_normalize_grid(&accumulation, 0.0, max_accumulation);
+                    timed(&mut timings.write, || {
+                        save_image(accumulation_img, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("💧 Saved flow accumulation map to {:?}", output_path);
+            }
+            "tpi" => {
+                // Compare every cell with the mean of its surroundings.
+                let tpi = compute_tpi(&grid, options.tpi_radius);
+                // Save the position map to the output directory. GeoTIFFs keep the index in elevation units.
+                if options.format == "geotiff" {
+                    timed(&mut timings.write, || write_grid_geotiff(&output_path, &tpi, options))?;
+                } else {
+                    // Center the diverging colormap on zero so ridges and valleys get equal weight.
+                    let (min_tpi, max_tpi) = tpi.value_range();
+                    let colormap = Colormap::diverging(min_tpi.abs().max(max_tpi.abs()));
+                    let tpi_img = elevation_to_colormap(&tpi, &colormap);
+                    timed(&mut timings.write, || {
+                        save_image(tpi_img, &grid, options.nodata_color, &output_path, &text, options)
+                    })?;
+                }
+                debug!("🏔️ Saved topographic position map to {:?}", output_path);
+            }
+            "color-relief" => {
+                // Color the raw elevations so the same elevation always gets the same color.
+                let mut relief = elevation_to_colormap(&grid, &options.elevation_colormap(min_val, max_val));
+                if let Some(level) = options.water_level {
+                    paint_water(&mut relief, &grid, level, options.water_color);
+                }
+                if let Some(strength) = options.aerial_perspective {
+                    apply_aerial_perspective(&mut relief, &grid, strength, options.haze_color, &options.haze_by, min_val, max_val);
+                }
+                // Save the color relief to the output directory.
+                if options.format == "geotiff" {
+                    apply_gamma(&mut relief, options.gamma);
+                    timed(&mut timings.write, || write_rgb_geotiff(&output_path, &relief, &grid, options))?;
+                } else {
+                    timed(&mut timings.write, || {
+                        save_image(relief, &grid, relief_nodata_color(options), &output_path, &text, options)
+                    })?;
+                }
+                debug!("🎨 Saved color relief to {:?}", output_path);
+            }
+            _ => unreachable!("mode suffix was checked above"),
+        }
+
+        // GeoTIFFs and GeoPackages carry their own georeferencing; other images get a sidecar on request.
+        if options.world_file && !matches!(options.format.as_str(), "geotiff" | "multi-tiff" | "gpkg") && !options.tiles {
+            timed(&mut timings.write, || write_world_file(&output_path, &grid.geotransform(), options.output_crs()))?;
+        }
+        processed.outputs.push((mode.to_string(), output_path));
+    }
+
+    // Contours can also be written as vectors instead of being drawn.
+    if let (Some(interval), "geojson") = (options.contour_interval, options.contour_format.as_str()) {
+        let contours_path = output_dir.join(format!("{}_contours.geojson", stem));
+        let lines = join_segments(&trace_contours(&grid, interval));
+        timed(&mut timings.write, || write_geojson(&contours_path, &grid, &lines, options.output_crs()))?;
+        debug!("🗺️ Saved {} contour lines to {:?}", lines.len(), contours_path);
+    }
+
+    timings.render = rendering.elapsed().saturating_sub(timings.write);
+    processed.timings = timings;
+    Ok(processed)
+}
+
+/// Shades the raw elevations with the configured light, using the grid's own cell size.
+///
+/// Smoothing only feeds the shading, so colors blended over it keep every detail.
+fn shaded_relief(grid: &ElevationGrid, options: &ProcessOptions) -> Result<RgbImage> {
+    let smoothed;
+    let relief = if options.smooth > 0 {
+        smoothed = grid.smooth(options.smooth);
+        &smoothed
+    } else {
+        grid
+    };
+    let hillshade = if !options.colored_lights.is_empty() {
+        apply_colored_hillshade(relief, options.z_factor, &options.colored_lights, options.altitude)
+    } else if options.multidirectional {
+        apply_multidirectional_hillshade(relief, options.z_factor, options.altitude)
+    } else {
+        apply_hillshade(relief, options.z_factor, options.azimuth, options.altitude)
+    };
+    // Darken steep cells regardless of the light direction.
+    if options.slope_blend {
+        let slope_shade = DynamicImage::ImageLuma8(slope_shade(&compute_slope(relief, options.z_factor)));
+        blend_colormap_with_hillshade(&hillshade, &slope_shade.into_rgb8(), "multiply", 1.0)
+    } else {
+        Ok(hillshade)
+    }
+}
+
+/// Runs `f`, adding the time it takes to `total`.
+fn timed<T>(total: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = f();
+    *total += started.elapsed();
+    result
+}
+
+/// Returns the length of a vertical unit in meters.
+fn meters_per_unit(unit: &str) -> Result<f64> {
+    match unit {
+        "meters" => Ok(1.0),
+        "feet" => Ok(0.3048),
+        other => bail!("Unsupported unit: {}", other),
+    }
+}
+
+/// Applies the NoData policies that change the grid itself: `skip-file` fails on any NoData
+/// cell and `mean-fill` gives NoData cells the mean of the valid ones. The other policies
+/// only affect how NoData is drawn.
+fn apply_nodata_policy(grid: &mut ElevationGrid, policy: &str) -> Result<()> {
+    match policy {
+        "transparent" | "black" => {}
+        "skip-file" => {
+            let count = grid.values.iter().filter(|&&v| grid.is_nodata(v)).count();
+            if count > 0 {
+                bail!("{} NoData cells found (--nodata-policy skip-file)", count);
+            }
+        }
+        "mean-fill" => {
+            if let Some(stats) = GridStats::of(grid) {
+                let mut values = std::mem::take(&mut grid.values);
+                for value in values.iter_mut().filter(|v| grid.is_nodata(**v)) {
+                    *value = stats.mean;
+                }
+                grid.values = values;
+            }
+        }
+        other => bail!("Unsupported NoData policy: {}", other),
+    }
+    Ok(())
+}
+
+/// Logs the statistics of a grid and writes them to `stats_path` if requested.
+fn report_stats(source: &DemSource, grid: &ElevationGrid, stats_path: &Path, options: &ProcessOptions) -> Result<()> {
+    let Some(stats) = GridStats::of(grid) else {
+        warn!("📊 {} has no valid cells", source);
+        return Ok(());
+    };
+    info!("📊 {}: {}", source, stats);
+    if options.stats_json {
+        fs::write(stats_path, stats.to_json())?;
+        debug!("📊 Saved statistics to {:?}", stats_path);
+    }
+    Ok(())
+}
+
+/// An output of a file: its mode, name, path and, for sweep frames, the azimuth of the light.
+type Output<'a> = (&'a str, String, PathBuf, Option<f64>);
+
+/// Renders grayscale outputs by streaming the file instead of reading the whole grid.
+fn stream_grayscale(
+    source: &DemSource,
+    outputs: Vec<Output>,
+    options: &ProcessOptions,
+) -> Result<Processed> {
+    let path = match source {
+        DemSource::File(path) if !is_geotiff(path) => path,
+        _ => bail!("--low-memory only streams single ASCII grids"),
+    };
+    let mut timings = Timings::default();
+    let reading = Instant::now();
+    let (mut image, outline, elevation_range) = stream_ascii_levels(path, options.min_elev, options.max_elev, options.flat_position())?;
+    timings.read = reading.elapsed();
+    let mut processed = Processed { elevation_range, ..Processed::default() };
+    match elevation_range {
+        None => warn!("⚠️ {} has no valid cells; its images will be empty", source),
+        // The streamed levels were stretched the same way; this only warns.
+        Some((low, high)) => {
+            stretch_range(source, options, low, high);
+        }
+    }
+    apply_gamma(&mut image, options.gamma);
+    let image: DynamicImage = match options.canvas {
+        Some([width, height]) => letterbox(&image.into(), width, height, options.pad_color, image_filter(&options.resample)),
+        None => image.into(),
+    };
+
+    for (mode, _, output_path, _) in outputs {
+        let text = if options.metadata { png_text(source, mode, options) } else { Vec::new() };
+        if let Some(size) = options.thumbnail {
+            timed(&mut timings.write, || write_thumbnail(&image, size, &output_path, options))?;
+        }
+        if options.format == "gpkg" {
+            timed(&mut timings.write, || write_gpkg(&output_path, &image, &outline.geotransform(), options.output_crs()))?;
+        } else {
+            timed(&mut timings.write, || write_image(image.clone(), &output_path, &text, options))?;
+        }
+        if options.world_file && options.format != "gpkg" {
+            timed(&mut timings.write, || write_world_file(&output_path, &outline.geotransform(), options.output_crs()))?;
+        }
+        debug!("🍤 Saved grayscale image to {:?}", output_path);
+        processed.outputs.push((mode.to_string(), output_path));
+    }
+    processed.timings = timings;
+    Ok(processed)
+}
+
+/// Returns the output name template used for a mode, or `None` for unknown modes.
+fn default_name_template(mode: &str) -> Option<&'static str> {
+    match mode {
+        "grayscale" => Some("{stem}"),
+        "hillshade" => Some("{stem}_hillshade"),
+        "shade" => Some("{stem}_shade"),
+        "slope" => Some("{stem}_slope"),
+        "aspect" => Some("{stem}_aspect"),
+        "color-relief" => Some("{stem}_color_relief"),
+        "tri" => Some("{stem}_tri"),
+        "tpi" => Some("{stem}_tpi"),
+        "curvature" => Some("{stem}_curvature"),
+        "svf" => Some("{stem}_svf"),
+        "slope-class" => Some("{stem}_slope_class"),
+        "occlusion" => Some("{stem}_occlusion"),
+        "flow-accum" => Some("{stem}_flow_accum"),
+        _ => None,
+    }
+}
+
+/// Returns the default output name template of a sweep frame, for modes lit from one direction.
+fn default_frame_template(mode: &str) -> Option<&'static str> {
+    match mode {
+        "hillshade" => Some("{stem}_az{azimuth}"),
+        "shade" => Some("{stem}_shade_az{azimuth}"),
+        _ => None,
+    }
+}
+
+/// Fills in the `{stem}`, `{mode}`, `{azimuth}` and `{altitude}` tokens of an output name template.
+///
+/// Sweep frames pad their azimuth to three digits, so the frames sort in order.
+fn render_name(template: &str, stem: &str, mode: &str, frame: Option<f64>, options: &ProcessOptions) -> Result<String> {
+    let azimuth = match frame {
+        Some(azimuth) => format!("{:03}", azimuth),
+        None => options.azimuth.to_string(),
+    };
+    let name = template
+        .replace("{stem}", stem)
+        .replace("{mode}", mode)
+        .replace("{azimuth}", &azimuth)
+        .replace("{altitude}", &options.altitude.to_string());
+    if let Some(start) = name.find('{') {
+        let token = &name[start..name[start..].find('}').map_or(name.len(), |end| start + end + 1)];
+        bail!("unknown token {} in name template '{}'", token, template);
+    }
+    Ok(name)
+}
+
+/// Returns the color given to an elevation by the mode's output, for modes colored by elevation.
+fn legend_colors<'a>(
+    mode: &str,
+    grid: &ElevationGrid,
+    options: &'a ProcessOptions,
+    min_val: f64,
+    max_val: f64,
+    flat: f64,
+) -> Option<Box<dyn Fn(f64) -> Rgb<u8> + 'a>> {
+    let scale = value_scale(grid, &options.normalize, min_val, max_val, flat);
+    let gray = move |value: f64| scale(value).clamp(0.0, 1.0);
+    match (mode, &options.color_relief) {
+        ("grayscale", _) => Some(Box::new(move |value| Rgb([(gray(value) * 255.0).round() as u8; 3]))),
+        ("hillshade", None) => {
+            let colormap = options.gray_colormap();
+            Some(Box::new(move |value| colormap.sample(gray(value))))
+        }
+        ("hillshade" | "color-relief", _) => {
+            let colormap = options.elevation_colormap(min_val, max_val);
+            Some(Box::new(move |value| colormap.sample(value)))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the legend labels of the slope classes split at `breaks`, such as `0-5`, `5-15`
+/// and `15+`.
+fn slope_class_labels(breaks: &[f64]) -> Vec<String> {
+    let lower = std::iter::once(0.0).chain(breaks.iter().copied());
+    lower
+        .zip(breaks.iter().map(Some).chain([None]))
+        .map(|(low, high)| match high {
+            Some(high) => format!("{}-{}", low, high),
+            None => format!("{}+", low),
+        })
+        .collect()
+}
+
+/// Returns the PNG text chunks describing the source and parameters of an output.
+fn png_text(source: &DemSource, mode: &str, options: &ProcessOptions) -> Vec<(&'static str, String)> {
+    vec![
+        ("Software", format!("dem_processor {}", env!("CARGO_PKG_VERSION"))),
+        ("Source", match source {
+            DemSource::File(path) => path.display().to_string(),
+            mosaic => mosaic.to_string(),
+        }),
+        ("Mode", mode.to_string()),
+        ("Azimuth", options.azimuth.to_string()),
+        ("Altitude", options.altitude.to_string()),
+        ("Z-Factor", options.z_factor.to_string()),
+        ("Colormap", options.colormap_name.clone()),
+    ]
+}
+
+/// Returns the NoData fill for outputs colored by the color file.
+///
+/// An `nv` entry colors NoData unless `--nodata-color` overrides it; a transparent one keeps the default.
+fn relief_nodata_color(options: &ProcessOptions) -> Option<Rgb<u8>> {
+    options.nodata_color.or_else(|| {
+        let nv = options.color_relief.as_ref()?.nodata_color()?;
+        (nv[3] > 0).then(|| nv.to_rgb())
+    })
+}
+
+/// Largest magnitude up to which float32 represents every integer exactly.
+const F32_EXACT_INTEGERS: f64 = 16_777_216.0;
+
+/// Relative tolerance used when comparing cell values against the NoData value.
+const NODATA_TOLERANCE: f64 = 1e-6;
+
+/// Raw elevation values read from a DEM, or a raster derived from them.
+/// Row 0 is the northernmost row, and images are rendered with their rows in grid order,
+/// so north is up.
+#[derive(Debug, Clone)]
+pub struct ElevationGrid {
+    /// Elevation values in row-major order, read as `values[row][col]`.
+    pub values: Grid<f64>,
+    /// The value marking cells without data.
+    pub nodata: f64,
+    /// X coordinate of the lower-left corner of the grid.
+    pub x_min: f64,
+    /// Y coordinate of the lower-left corner of the grid.
+    pub y_min: f64,
+    /// Width of a cell in map units.
+    pub cell_width: f64,
+    /// Height of a cell in map units, which equals the width for square cells.
+    pub cell_height: f64,
+}
+
+impl ElevationGrid {
+    /// Number of rows in the grid.
+    pub fn rows(&self) -> usize {
+        self.values.rows()
+    }
+
+    /// Number of columns in the grid.
+    pub fn cols(&self) -> usize {
+        self.values.cols()
+    }
+
+    /// Returns true if `value` marks a cell without data.
+    ///
+    /// NaN is always NoData, and other values match within a small relative tolerance so a
+    /// NoData value that went through float32 or text formatting is still recognized.
+    pub fn is_nodata(&self, value: f64) -> bool {
+        value.is_nan() || (value - self.nodata).abs() <= NODATA_TOLERANCE * self.nodata.abs().max(1.0)
+    }
+
+    /// Returns the minimum and maximum of the valid cells.
+    pub fn value_range(&self) -> (f64, f64) {
+        let mut min_val = f64::MAX;
+        let mut max_val = f64::MIN;
+
+        // Track the min and max values for normalization.
+        for &value in self.values.iter() {
+            if !self.is_nodata(value) {
+                min_val = min_val.min(value);
+                max_val = max_val.max(value);
+            }
+        }
+
+        (min_val, max_val)
+    }
+
+    /// Returns true if every valid cell holds a whole number, as in integer DEMs and class rasters.
+    pub fn is_integer(&self) -> bool {
+        self.values.iter().all(|&v| self.is_nodata(v) || v.fract() == 0.0)
+    }
+
+    /// Returns whether the grid holds integer codes too large for float32 to keep exactly,
+    /// which holds every integer up to 2^24. Grids without valid cells hold none.
+    pub fn rounds_in_f32(&self) -> bool {
+        let (min_val, max_val) = self.value_range();
+        min_val <= max_val && min_val.abs().max(max_val.abs()) > F32_EXACT_INTEGERS && self.is_integer()
+    }
+
+    /// Multiplies every valid cell by `factor`, e.g. to convert between units.
+    pub fn scale_values(&mut self, factor: f64) {
+        let mut values = std::mem::take(&mut self.values);
+        for value in values.iter_mut() {
+            if !self.is_nodata(*value) {
+                *value *= factor;
+            }
+        }
+        self.values = values;
+    }
+
+    /// Returns the `percent` and `100 - percent` percentiles of the valid cells.
+    pub fn percentile_range(&self, percent: f64) -> (f64, f64) {
+        let mut valid: Vec<f64> = self
+            .values
+            .iter()
+            .copied()
+            .filter(|&v| !self.is_nodata(v))
+            .collect();
+        if valid.is_empty() {
+            return self.value_range();
+        }
+        valid.sort_by(f64::total_cmp);
+
+        let percentile = |p: f64| valid[((p / 100.0) * (valid.len() - 1) as f64).round() as usize];
+        (percentile(percent), percentile(100.0 - percent))
+    }
+
+    /// Returns the 3x3 neighborhood around a cell in row-major order, scaled by `z_factor`.
+    ///
+    /// Neighbors outside the grid are clamped to the edge and NoData neighbors take the
+    /// center value. Returns `None` if the cell itself is NoData.
+    pub fn window(&self, row: usize, col: usize, z_factor: f64) -> Option<[f64; 9]> {
+        let center = self.values[row][col];
+        if self.is_nodata(center) {
+            return None;
+        }
+
+        let mut window = [0.0; 9];
+        for (i, z) in window.iter_mut().enumerate() {
+            let r = (row + i / 3).saturating_sub(1).min(self.rows() - 1);
+            let c = (col + i % 3).saturating_sub(1).min(self.cols() - 1);
+            let value = self.values[r][c];
+            *z = if self.is_nodata(value) { center } else { value } * z_factor;
+        }
+        Some(window)
+    }
+
+    /// Like [`window`](Self::window), but returns `None` if any cell of the neighborhood is NoData.
+    pub fn valid_window(&self, row: usize, col: usize, z_factor: f64) -> Option<[f64; 9]> {
+        let window = self.window(row, col, z_factor)?;
+        let rows = row.saturating_sub(1)..(row + 2).min(self.rows());
+        let cols = col.saturating_sub(1)..(col + 2).min(self.cols());
+        let mut cells = rows.flat_map(|r| cols.clone().map(move |c| (r, c)));
+        cells.all(|(r, c)| !self.is_nodata(self.values[r][c])).then_some(window)
+    }
+
+    /// Returns the valid values within `radius` cells of a cell, excluding the cell itself.
+    ///
+    /// Neighbors outside the grid and NoData neighbors are skipped.
+    pub fn neighbors(&self, row: usize, col: usize, radius: usize) -> impl Iterator<Item = f64> + '_ {
+        let rows = row.saturating_sub(radius)..(row + radius + 1).min(self.rows());
+        let cols = col.saturating_sub(radius)..(col + radius + 1).min(self.cols());
+        rows.flat_map(move |r| cols.clone().map(move |c| (r, c)))
+            .filter(move |&(r, c)| (r, c) != (row, col))
+            .map(|(r, c)| self.values[r][c])
+            .filter(|&value| !self.is_nodata(value))
+    }
+
+    /// Returns a grid with the same extent whose values are computed cell by cell.
+    /// Cells for which `f` returns `None` become NoData.
+    pub fn map_cells(&self, f: impl Fn(usize, usize) -> Option<f64>) -> ElevationGrid {
+        let values = Grid::from_fn(self.rows(), self.cols(), |row, col| f(row, col).unwrap_or(self.nodata));
+        ElevationGrid {
+            values,
+            nodata: self.nodata,
+            x_min: self.x_min,
+            y_min: self.y_min,
+            cell_width: self.cell_width,
+            cell_height: self.cell_height,
+        }
+    }
+
+    /// Like [`map_cells`](Self::map_cells), but computes rows in parallel on the current thread pool.
+    pub fn par_map_cells(&self, f: impl Fn(usize, usize) -> Option<f64> + Sync) -> ElevationGrid {
+        let values = Grid::par_from_fn(self.rows(), self.cols(), |row, col| f(row, col).unwrap_or(self.nodata));
+        ElevationGrid {
+            values,
+            nodata: self.nodata,
+            x_min: self.x_min,
+            y_min: self.y_min,
+            cell_width: self.cell_width,
+            cell_height: self.cell_height,
+        }
+    }
+
+    /// Returns a grid blurred with a Gaussian kernel reaching `radius` cells, with a standard
+    /// deviation of half the radius.
+    ///
+    /// The kernel is applied along rows and then columns. NoData cells stay NoData and are
+    /// left out of their neighbors' averages, so holes don't bleed into the surface.
+    pub fn smooth(&self, radius: usize) -> ElevationGrid {
+        let sigma = (radius as f64 / 2.0).max(0.5);
+        let kernel: Vec<f64> = (0..=2 * radius)
+            .map(|i| (-((i as f64 - radius as f64).powi(2)) / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let kernel = kernel.as_slice();
+        let (rows, cols) = (self.rows(), self.cols());
+        let taps = |center: usize, len: usize| {
+            let first = center.saturating_sub(radius);
+            (first..(center + radius + 1).min(len)).map(move |i| (i, kernel[i + radius - center]))
+        };
+
+        // Weighted sums and weights of the valid cells along each row.
+        let mut along_rows = Grid::new(rows, cols, (0.0, 0.0));
+        for (row, sums) in along_rows.iter_rows_mut().enumerate() {
+            for (col, sum) in sums.iter_mut().enumerate() {
+                for (c, weight) in taps(col, cols) {
+                    let value = self.values[row][c];
+                    if !self.is_nodata(value) {
+                        *sum = (sum.0 + weight * value, sum.1 + weight);
+                    }
+                }
+            }
+        }
+
+        // Combine those down each column, normalizing by the weight of the valid cells.
+        self.map_cells(|row, col| {
+            if self.is_nodata(self.values[row][col]) {
+                return None;
+            }
+            let (sum, weight) = taps(row, rows)
+                .map(|(r, w)| (along_rows[r][col].0 * w, along_rows[r][col].1 * w))
+                .fold((0.0, 0.0), |(s, t), (a, b)| (s + a, t + b));
+            Some(sum / weight)
+        })
+    }
+
+    /// Returns a grid `factor` times coarser, resampled by the `method`. With bilinear, each
+    /// cell is the mean of the valid cells in a `factor` x `factor` block, and blocks without
+    /// valid cells are NoData. With nearest, each cell is the cell at the center of its block,
+    /// and with bicubic it is interpolated there; see [`sample_grid`].
+    ///
+    /// Partial blocks along the east and south edges extend the grid past its original extent.
+    pub fn downsample(&self, factor: usize, method: &str) -> ElevationGrid {
+        let rows = self.rows().div_ceil(factor);
+        let cols = self.cols().div_ceil(factor);
+        let values = Grid::from_fn(rows, cols, |row, col| {
+            let block_rows = row * factor..((row + 1) * factor).min(self.rows());
+            let block_cols = col * factor..((col + 1) * factor).min(self.cols());
+            if method != "bilinear" {
+                let center = |block: &std::ops::Range<usize>| (block.start + block.end - 1) as f64 / 2.0;
+                return sample_grid(self, center(&block_rows), center(&block_cols), method).unwrap_or(self.nodata);
+            }
+            let (sum, count) = block_rows
+                .flat_map(|r| &self.values[r][block_cols.clone()])
+                .filter(|&&v| !self.is_nodata(v))
+                .fold((0.0, 0), |(sum, count), &v| (sum + v, count + 1));
+            if count == 0 { self.nodata } else { sum / count as f64 }
+        });
+
+        // The grid is anchored at its lower-left corner, so keep the northern edge in place.
+        let cell_width = self.cell_width * factor as f64;
+        let cell_height = self.cell_height * factor as f64;
+        let y_max = self.y_min + self.rows() as f64 * self.cell_height;
+        ElevationGrid {
+            values,
+            nodata: self.nodata,
+            x_min: self.x_min,
+            y_min: y_max - rows as f64 * cell_height,
+            cell_width,
+            cell_height,
+        }
+    }
+
+    /// Keeps every `every`th row and column, starting with the first, without averaging;
+    /// each kept cell stands for the block of cells around it.
+    pub fn subsample(&self, every: usize) -> ElevationGrid {
+        let values = Grid::from_fn(self.rows().div_ceil(every), self.cols().div_ceil(every), |row, col| {
+            self.values[row * every][col * every]
+        });
+        let y_max = self.y_min + self.rows() as f64 * self.cell_height;
+        sampled_grid(values, self.nodata, [self.x_min, y_max], self.cell_width, self.cell_height, every)
+    }
+
+    /// Cuts out `rows` x `cols` cells starting at `(first_row, first_col)`, which may lie
+    /// outside the grid: cells beyond its extent are NoData.
+    pub fn crop(&self, first_row: i64, first_col: i64, rows: usize, cols: usize) -> ElevationGrid {
+        let values = Grid::from_fn(rows, cols, |row, col| {
+            let (r, c) = (first_row + row as i64, first_col + col as i64);
+            if r < 0 || c < 0 {
+                return self.nodata;
+            }
+            self.values.get(r as usize, c as usize).copied().unwrap_or(self.nodata)
+        });
+        let y_max = self.y_min + self.rows() as f64 * self.cell_height - first_row as f64 * self.cell_height;
+        ElevationGrid {
+            values,
+            nodata: self.nodata,
+            x_min: self.x_min + first_col as f64 * self.cell_width,
+            y_min: y_max - rows as f64 * self.cell_height,
+            cell_width: self.cell_width,
+            cell_height: self.cell_height,
+        }
+    }
+
+    /// Cuts out the cells overlapping `[min_x, min_y, max_x, max_y]` in map coordinates.
+    /// Parts of the box beyond the grid are NoData.
+    pub fn crop_to_bbox(&self, [min_x, min_y, max_x, max_y]: [f64; 4]) -> ElevationGrid {
+        let y_max = self.y_min + self.rows() as f64 * self.cell_height;
+        let first_col = ((min_x - self.x_min) / self.cell_width).floor() as i64;
+        let last_col = ((max_x - self.x_min) / self.cell_width).ceil() as i64;
+        let first_row = ((y_max - max_y) / self.cell_height).floor() as i64;
+        let last_row = ((y_max - min_y) / self.cell_height).ceil() as i64;
+        let rows = (last_row - first_row).max(0) as usize;
+        let cols = (last_col - first_col).max(0) as usize;
+        self.crop(first_row, first_col, rows, cols)
+    }
+
+    /// Cuts out the `width` x `height` pixels at `(x, y)` of the image rendered from the grid.
+    pub fn crop_to_pixels(&self, [x, y, width, height]: [u32; 4]) -> Result<ElevationGrid> {
+        let (x, y, width, height) = (x as usize, y as usize, width as usize, height as usize);
+        if x + width > self.cols() || y + height > self.rows() {
+            bail!(
+                "the {}x{} pixels at {},{} run past the {}x{} image",
+                width,
+                height,
+                x,
+                y,
+                self.cols(),
+                self.rows()
+            );
+        }
+        Ok(self.crop(y as i64, x as i64, height, width))
+    }
+
+    /// Returns where a fractional `(row, col)` grid position, with whole numbers at cell
+    /// centers, lands in the output image in pixels.
+    pub fn image_position(&self, row: f64, col: f64) -> (f64, f64) {
+        (col + 0.5, row + 0.5)
+    }
+
+    /// Returns the transform from output image pixels to map coordinates.
+    ///
+    /// The image starts at the upper-left corner since rows are rendered north-up.
+    pub fn geotransform(&self) -> GeoTransform {
+        let y_max = self.y_min + self.rows() as f64 * self.cell_height;
+        [self.x_min, self.cell_width, 0.0, y_max, 0.0, -self.cell_height]
+    }
+}
+
+/// Reads the elevation values of an ASCII grid file.
+pub fn read_ascii_grid(path: &Path) -> Result<ElevationGrid> {
+    read_ascii_grid_from(open_ascii_text(path)?)
+}
+
+/// Reads an ESRI ASCII grid from any reader, such as a file or an in-memory buffer.
+///
+/// Values are read as `f64` whether the file holds integers or decimals, which keeps every
+/// integer elevation or class code below 2^53 exact; see [`ElevationGrid::is_integer`].
+/// CRLF line endings, stray whitespace and blank lines are tolerated; see [`AsciiText`].
+pub fn read_ascii_grid_from<R: Read>(source: R) -> Result<ElevationGrid> {
+    read_ascii_grid_sampled_from(source, 1)
+}
+
+/// Reads every `every`th row and column of an ESRI ASCII grid, starting with the first,
+/// like [`ElevationGrid::subsample`] but without holding or even parsing the skipped cells.
+pub fn read_ascii_grid_sampled_from<R: Read>(source: R, every: usize) -> Result<ElevationGrid> {
+    let mut text = AsciiText::new(BufReader::new(source));
+    let (header, header_text) = read_ascii_header(&mut text)?;
+    let nodata = header.no_data_value().unwrap_or(f64::NAN);
+    let rows = header.num_rows();
+    let cols = header.num_cols();
+
+    // Without a NODATA_value line, the lines read for the header end with the first row.
+    // Rows are listed from the north, so the first value is the upper-left cell.
+    let mut values = Grid::new(rows.div_ceil(every), cols.div_ceil(every), nodata);
+    let sampled = |index: usize| {
+        index < rows * cols && (index / cols).is_multiple_of(every) && (index % cols).is_multiple_of(every)
+    };
+    let text = Cursor::new(header_text).chain(text);
+    let count = for_each_sampled_ascii_value(text, header_lines(&header), sampled, |index, value| {
+        values[index / cols / every][index % cols / every] = value;
+    })?;
+    if count != rows * cols {
+        bail!("expected {} cells ({}x{}), found {}", rows * cols, cols, rows, count);
+    }
+
+    // The header already shifts `xllcenter`/`yllcenter` to the corner.
+    let y_max = header.min_y() + rows as f64 * header.cell_size();
+    Ok(sampled_grid(values, nodata, [header.min_x(), y_max], header.cell_size(), header.cell_size(), every))
+}
+
+/// Builds the grid of every `every`th cell of a grid whose upper-left corner is `corner`,
+/// with cells `every` times as large, centered on the cells they were sampled from.
+fn sampled_grid(
+    values: Grid<f64>,
+    nodata: f64,
+    [x_min, y_max]: [f64; 2],
+    cell_width: f64,
+    cell_height: f64,
+    every: usize,
+) -> ElevationGrid {
+    // Sampled cells sit in the upper-left corner of their blocks, so the blocks shift by half
+    // a block less one cell.
+    let shift = (every - 1) as f64 / 2.0;
+    let span = every as f64;
+    ElevationGrid {
+        x_min: x_min - shift * cell_width,
+        y_min: y_max + shift * cell_height - values.rows() as f64 * span * cell_height,
+        values,
+        nodata,
+        cell_width: cell_width * span,
+        cell_height: cell_height * span,
+    }
+}
+
+/// Reads the first band of a single-band GeoTIFF DEM.
+pub fn read_geotiff_grid(path: &Path) -> Result<ElevationGrid> {
+    let raster = geotiff::read_single_band(path)?;
+    let [x0, pixel_width, row_rotation, y0, col_rotation, pixel_height] = raster.transform;
+    if row_rotation != 0.0 || col_rotation != 0.0 {
+        bail!("rotated GeoTIFFs are not supported");
+    }
+
+    // Grid rows run from north to south, so south-up rasters are flipped.
+    let (rows, cols) = (raster.height as usize, raster.width as usize);
+    let (values, y_top) = if pixel_height > 0.0 {
+        let flipped = Grid::from_fn(rows, cols, |row, col| raster.data[(rows - 1 - row) * cols + col]);
+        (flipped, y0 + pixel_height * raster.height as f64)
+    } else {
+        (Grid::from_vec(rows, cols, raster.data), y0)
+    };
+
+    Ok(ElevationGrid {
+        values,
+        nodata: raster.nodata.unwrap_or(f64::NAN),
+        x_min: x0,
+        y_min: y_top - pixel_height.abs() * raster.height as f64,
+        cell_width: pixel_width,
+        cell_height: pixel_height.abs(),
+    })
+}
+
+/// Reads a DEM, choosing the GeoTIFF or ASCII grid reader from the file extension.
+pub fn read_grid(path: &Path) -> Result<ElevationGrid> {
+    if is_geotiff(path) {
+        read_geotiff_grid(path)
+    } else {
+        read_ascii_grid(path)
+    }
+}
+
+/// Reads a DEM like [`read_grid`], with `cell_size`, if set, replacing the cell size of
+/// the file. ASCII grids without a usable `cellsize` line can be read this way.
+pub fn read_grid_with_cell_size(path: &Path, cell_size: Option<f64>) -> Result<ElevationGrid> {
+    let Some(cell_size) = cell_size else {
+        return read_grid(path);
+    };
+    if is_geotiff(path) {
+        let mut grid = read_geotiff_grid(path)?;
+        if grid.cell_width != cell_size || grid.cell_height != cell_size {
+            warn!("⚠️ Overriding the {}x{} cell size of {:?} with {}", grid.cell_width, grid.cell_height, path, cell_size);
+        }
+        grid.cell_width = cell_size;
+        grid.cell_height = cell_size;
+        Ok(grid)
+    } else {
+        read_ascii_grid_from(ascii_with_cell_size(path, cell_size)?)
+    }
+}
+
+/// Reads the number of rows and columns of a DEM like [`read_grid_size`], accepting ASCII
+/// grids without a usable `cellsize` line when `cell_size` is set.
+fn read_grid_size_with_cell_size(path: &Path, cell_size: Option<f64>) -> Result<(usize, usize)> {
+    match cell_size {
+        Some(cell_size) if !is_geotiff(path) => {
+            let (header, _) = read_ascii_header(&mut ascii_with_cell_size(path, cell_size)?)?;
+            Ok((header.num_rows(), header.num_cols()))
+        }
+        _ => read_grid_size(path),
+    }
+}
+
+/// Loads an ASCII grid into memory with its header rewritten to use `cell_size`.
+fn ascii_with_cell_size(path: &Path, cell_size: f64) -> Result<Cursor<Vec<u8>>> {
+    let mut data = Vec::new();
+    open_ascii_text(path)?.read_to_end(&mut data).with_context(|| format!("reading {:?}", path))?;
+    let (data, original) = override_cell_size(&data, cell_size);
+    match original {
+        Some(original) if original == cell_size => {}
+        Some(original) => warn!("⚠️ Overriding the cell size {} of {:?} with {}", original, path, cell_size),
+        None => warn!("⚠️ {:?} has no usable cellsize; using {}", path, cell_size),
+    }
+    Ok(Cursor::new(data))
+}
+
+/// Reads the number of rows and columns of a DEM from its header, without reading the cells.
+pub fn read_grid_size(path: &Path) -> Result<(usize, usize)> {
+    if is_geotiff(path) {
+        let (width, height) = geotiff::read_dimensions(path)?;
+        Ok((height as usize, width as usize))
+    } else {
+        let (header, _) = read_ascii_header(&mut open_ascii_text(path)?)?;
+        Ok((header.num_rows(), header.num_cols()))
+    }
+}
+
+/// Reads the header of ASCII grid text after checking it, so malformed headers name the bad
+/// line. Also returns the text of the lines read, which can run into the first row of cells.
+fn read_ascii_header(text: &mut impl BufRead) -> Result<(EsriASCIIRasterHeader<f64, f64>, Vec<u8>)> {
+    let mut lines = Vec::new();
+    for _ in 0..6 {
+        text.read_until(b'\n', &mut lines)?;
+    }
+    validate_ascii_header(lines.as_slice()).context("invalid ASCII grid header")?;
+    let header = EsriASCIIReader::<_, f64, f64>::from_file(Cursor::new(&lines))?.header;
+    Ok((header, lines))
+}
+
+/// Number of lines of an ASCII grid header, which only has a NODATA_value line if it has NoData.
+fn header_lines(header: &EsriASCIIRasterHeader<f64, f64>) -> usize {
+    if header.no_data_value().is_some() { 6 } else { 5 }
+}
+
+/// Returns true if the path has a `.gz` extension.
+pub fn is_gzipped(path: &Path) -> bool {
+    path.extension().map(|ext| ext.eq_ignore_ascii_case("gz")).unwrap_or(false)
+}
+
+/// Returns true if the path has a `.tif` or `.tiff` extension.
+pub fn is_geotiff(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("tif") || ext.eq_ignore_ascii_case("tiff"))
+        .unwrap_or(false)
+}
+
+/// Returns true if the path looks like a DEM this crate can read: an `.asc` grid, possibly
+/// gzipped as `.asc.gz`, or a GeoTIFF.
+pub fn is_dem_file(path: &Path) -> bool {
+    let is_asc = |path: &Path| path.extension().map(|ext| ext == "asc").unwrap_or(false);
+    is_asc(path) || (is_gzipped(path) && is_asc(Path::new(path.file_stem().unwrap()))) || is_geotiff(path)
+}
+
+/// Converts a single-band GeoTIFF DEM to a grayscale image.
+pub fn geotiff_to_image(path: &Path) -> Result<GrayImage> {
+    Ok(grid_to_image(&read_geotiff_grid(path)?))
+}
+
+/// Converts an ASCII grid read from `reader` to a grayscale image.
+///
+/// The grid is buffered in memory first, so the reader can be a decompressor or a pipe.
+pub fn ascii_to_image(mut reader: impl Read) -> Result<GrayImage> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(grid_to_image(&read_ascii_grid_from(Cursor::new(data))?))
+}
+
+/// Converts an ASCII grid file to a grayscale image without loading the grid, reading the
+/// file twice: once for the elevation range and once for the gray levels.
+///
+/// Memory use is one byte per cell instead of the eight of [`ascii_to_image`], for grids
+/// too large to hold. NoData cells are black.
+pub fn stream_ascii_to_image(path: &Path) -> Result<GrayImage> {
+    let (image, _, _) = stream_ascii_levels(path, None, None, MID_GRAY)?;
+    Ok(DynamicImage::ImageLumaA8(image).into_luma8())
+}
+
+/// Gray levels streamed from an ASCII grid, the outline of the grid and the range of its valid cells.
+type StreamedLevels = (GrayAlphaImage, ElevationGrid, Option<(f64, f64)>);
+
+/// Streams an ASCII grid into gray levels stretched over `min_val..=max_val`, or the range
+/// of the file where unset as in [`stretch_bounds`], with NoData cells transparent. A flat
+/// range puts every valid cell at `flat` on the `0..=1` scale.
+///
+/// Also returns an outline of the grid: its position, cell size and one empty row per
+/// grid row, which is enough to place the image without holding the elevations, and the
+/// range of the valid cells.
+fn stream_ascii_levels(
+    path: &Path,
+    min_val: Option<f64>,
+    max_val: Option<f64>,
+    flat: f64,
+) -> Result<StreamedLevels> {
+    let (header, _) = read_ascii_header(&mut open_ascii_text(path)?)?;
+    let (rows, cols) = (header.num_rows(), header.num_cols());
+    let header_lines = header_lines(&header);
+    let outline = ElevationGrid {
+        values: Grid::new(rows, 0, 0.0),
+        nodata: header.no_data_value().unwrap_or(f64::NAN),
+        x_min: header.min_x(),
+        y_min: header.min_y(),
+        cell_width: header.cell_size(),
+        cell_height: header.cell_size(),
+    };
+
+    // The first pass only runs when the range isn't fixed.
+    let (min_val, max_val, flat) = match (min_val, max_val) {
+        (Some(min_val), Some(max_val)) => (min_val, max_val, flat),
+        _ => {
+            let (mut low, mut high) = (f64::INFINITY, f64::NEG_INFINITY);
+            stream_ascii_values(path, header_lines, |_, value| {
+                if !outline.is_nodata(value) {
+                    low = low.min(value);
+                    high = high.max(value);
+                }
+            })?;
+            stretch_bounds(min_val, max_val, low, high, flat)
+        }
+    };
+
+    let scale = linear_scale(min_val, max_val, flat);
+    let mut image = ImageBuffer::new(cols as u32, rows as u32);
+    let (mut low, mut high) = (f64::INFINITY, f64::NEG_INFINITY);
+    let count = stream_ascii_values(path, header_lines, |index, value| {
+        if index >= rows * cols || outline.is_nodata(value) {
+            return;
+        }
+        low = low.min(value);
+        high = high.max(value);
+        // File rows are grid rows, which are image rows.
+        let (row, col) = (index / cols, index % cols);
+        let level = (scale(value).clamp(0.0, 1.0) * 255.0).round() as u8;
+        image.put_pixel(col as u32, row as u32, LumaA([level, 255]));
+    })?;
+    if count != rows * cols {
+        bail!("expected {} cells ({}x{}), found {}", rows * cols, cols, rows, count);
     }
+    Ok((image, outline, (low <= high).then_some((low, high))))
+}
 
-    Ok(())
+/// Calls `f` with the index and value of every cell of an ASCII grid, in file order, and
+/// returns the number of cells.
+fn stream_ascii_values(path: &Path, header_lines: usize, f: impl FnMut(usize, f64)) -> Result<usize> {
+    for_each_ascii_value(open_ascii_text(path)?, header_lines, f)
+}
+
+/// Calls `f` with the index and value of every cell of ASCII grid text after the
+/// `header_lines` lines of its header, and returns the number of cells.
+fn for_each_ascii_value(text: impl BufRead, header_lines: usize, f: impl FnMut(usize, f64)) -> Result<usize> {
+    for_each_sampled_ascii_value(text, header_lines, |_| true, f)
 }
 
-/// Converts an ASCII grid file to a grayscale image.
-fn ascii_to_image(path: &Path) -> Result<GrayImage> {
+/// Like [`for_each_ascii_value`], but only parses the cells whose index `sampled` accepts;
+/// the rest are counted unchecked.
+fn for_each_sampled_ascii_value(
+    mut text: impl BufRead,
+    header_lines: usize,
+    sampled: impl Fn(usize) -> bool,
+    mut f: impl FnMut(usize, f64),
+) -> Result<usize> {
+    let mut line = String::new();
+    for _ in 0..header_lines {
+        text.read_line(&mut line)?;
+    }
+    let mut index = 0;
+    loop {
+        line.clear();
+        if text.read_line(&mut line)? == 0 {
+            return Ok(index);
+        }
+        for token in line.split_whitespace() {
+            if sampled(index) {
+                let value = token.parse().with_context(|| format!("cell {}: '{}' is not a number", index, token))?;
+                f(index, value);
+            }
+            index += 1;
+        }
+    }
+}
+
+/// Opens an ASCII grid as tidied text, decompressing gzipped files on the fly.
+fn open_ascii_text(path: &Path) -> Result<AsciiText<Box<dyn BufRead>>> {
     let file = File::open(path)?;
-    let mut reader: EsriASCIIReader<File, f64, f64> = EsriASCIIReader::from_file(file)?;
+    let text: Box<dyn BufRead> =
+        if is_gzipped(path) { Box::new(BufReader::new(GzDecoder::new(file))) } else { Box::new(BufReader::new(file)) };
+    Ok(AsciiText::new(text))
+}
 
-    let header = reader.header;
-    let nodata = header.no_data_value().unwrap_or(f64::NAN);
-    let rows = header.num_rows();
-    let cols = header.num_cols();
+/// Normalizes an elevation grid to a grayscale image. Grids without valid cells give a black image.
+pub fn grid_to_image(grid: &ElevationGrid) -> GrayImage {
+    let (min_val, max_val) = grid.value_range();
+    if min_val > max_val {
+        warn!("⚠️ The grid has no valid cells; its image is black");
+        return GrayImage::new(grid.cols() as u32, grid.rows() as u32);
+    }
+    normalize_grid(grid, min_val, max_val)
+}
 
-    let mut values = vec![vec![0.0; cols]; rows];
-    let mut min_val = f64::MAX;
-    let mut max_val = f64::MIN;
+/// Maps grid values in `min_val..=max_val` to a grayscale image, clamping values outside
+/// the range. NoData cells are black, and a flat range is mid-gray.
+pub fn normalize_grid(grid: &ElevationGrid, min_val: f64, max_val: f64) -> GrayImage {
+    grid_to_levels(grid, linear_scale(min_val, max_val, MID_GRAY), |level| (level * 255.0).round() as u8)
+}
 
-    // Read the grid values and track the min and max values for normalization.
-    for cell in reader.into_iter() {
-        if let Ok((row, col, value)) = cell {
-            values[row][col] = value;
-            if value != nodata {
-                min_val = min_val.min(value);
-                max_val = max_val.max(value);
-            }
+/// Maps grid values in `min_val..=max_val` to a grayscale image on a logarithmic scale,
+/// which spreads out detail near the minimum. NoData cells are black, and a flat range is
+/// mid-gray.
+///
+/// Values are offset by the minimum first, so negative elevations stay in the log domain.
+pub fn log_normalize_grid(grid: &ElevationGrid, min_val: f64, max_val: f64) -> GrayImage {
+    grid_to_levels(grid, log_scale(min_val, max_val, MID_GRAY), |level| (level * 255.0).round() as u8)
+}
+
+/// Maps grid values in `min_val..=max_val` to a grayscale image by histogram equalization,
+/// so each gray level covers about as many cells. NoData cells are black and left out of
+/// the histogram, and a flat range is mid-gray.
+pub fn equalize_grid(grid: &ElevationGrid, min_val: f64, max_val: f64) -> GrayImage {
+    grid_to_levels(grid, equalized_scale(grid, min_val, max_val, MID_GRAY), |level| (level * 255.0).round() as u8)
+}
+
+/// Like [`normalize_grid`], [`log_normalize_grid`] or [`equalize_grid`] depending on the
+/// `normalize` stretch, but with 16-bit gray levels for downstream tools that re-stretch
+/// the image. A flat range puts every valid cell at `flat` on the `0..=1` scale.
+pub fn normalize_grid_16(
+    grid: &ElevationGrid,
+    min_val: f64,
+    max_val: f64,
+    normalize: &str,
+    flat: f64,
+) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+    grid_to_levels(grid, value_scale(grid, normalize, min_val, max_val, flat), |level| (level * 65535.0).round() as u16)
+}
+
+/// Position on the `0..=1` scale given to flat grids by functions without a flat level.
+const MID_GRAY: f64 = 0.5;
+
+/// Returns the range a grid whose valid cells span `low..=high` is stretched over, the fixed
+/// `min_elev` and `max_elev` where given and the grid's own range elsewhere, along with the
+/// position on the `0..=1` scale of every cell when that range is empty.
+///
+/// A single fixed bound beyond the whole grid, such as a minimum of 100 m for a tile that
+/// tops out at 9 m, would invert the range. The grid is then flat at that bound instead,
+/// its cells at the end of the scale they would take in a tile spanning the bound.
+fn stretch_bounds(min_elev: Option<f64>, max_elev: Option<f64>, low: f64, high: f64, flat: f64) -> (f64, f64, f64) {
+    match (min_elev, max_elev) {
+        (Some(min_val), None) if low <= high && min_val > high => (min_val, min_val, 0.0),
+        (None, Some(max_val)) if low <= high && max_val < low => (max_val, max_val, 1.0),
+        (min_val, max_val) => (min_val.unwrap_or(low), max_val.unwrap_or(high), flat),
+    }
+}
+
+/// Returns the [`stretch_bounds`] of a source's grid, warning when they leave it flat.
+fn stretch_range(source: &DemSource, options: &ProcessOptions, low: f64, high: f64) -> (f64, f64, f64) {
+    let (min_val, max_val, flat) = stretch_bounds(options.min_elev, options.max_elev, low, high, options.flat_position());
+    if low <= high && min_val == max_val {
+        if min_val > high {
+            warn!("⚠️ {} lies below --min-elev {}; its cells get the lowest gray level", source, min_val);
+        } else if max_val < low {
+            warn!("⚠️ {} lies above --max-elev {}; its cells get the highest gray level", source, max_val);
+        } else {
+            // A flat grid has nothing to stretch, so its cells all get the flat level.
+            warn!("⚠️ {} is flat at {}; its cells get gray level {}", source, min_val, options.flat_level);
+        }
+    }
+    (min_val, max_val, flat)
+}
+
+/// Returns the position of a value on the `min_val..=max_val` scale of a stretch, or `flat`
+/// for every value when the range is a single elevation.
+fn value_scale(
+    grid: &ElevationGrid,
+    normalize: &str,
+    min_val: f64,
+    max_val: f64,
+    flat: f64,
+) -> Box<dyn Fn(f64) -> f64> {
+    match normalize {
+        "log" => Box::new(log_scale(min_val, max_val, flat)),
+        "equalize" => Box::new(equalized_scale(grid, min_val, max_val, flat)),
+        _ => Box::new(linear_scale(min_val, max_val, flat)),
+    }
+}
+
+/// Returns the position of a value on a linear `min_val..=max_val` scale.
+fn linear_scale(min_val: f64, max_val: f64, flat: f64) -> impl Fn(f64) -> f64 {
+    // A zero span would put every value at 0/0, which is NaN.
+    move |value| if max_val == min_val { flat } else { (value - min_val) / (max_val - min_val) }
+}
+
+/// Returns the position of a value on a logarithmic `min_val..=max_val` scale.
+fn log_scale(min_val: f64, max_val: f64, flat: f64) -> impl Fn(f64) -> f64 {
+    let span = (max_val - min_val + 1.0).ln();
+    // An inverted range has no log domain, and clamping to it would panic.
+    move |value| if max_val <= min_val { flat } else { (value.clamp(min_val, max_val) - min_val + 1.0).ln() / span }
+}
+
+/// Returns the position of a value in the cumulative histogram of the valid cells within
+/// `min_val..=max_val`, with the minimum at 0.
+fn equalized_scale(grid: &ElevationGrid, min_val: f64, max_val: f64, flat: f64) -> impl Fn(f64) -> f64 + use<> {
+    // An inverted range holds no cells, and clamping to it would panic.
+    let inverted = max_val < min_val;
+    let mut valid: Vec<f64> = grid
+        .values
+        .iter()
+        .filter(|&&v| !inverted && !grid.is_nodata(v))
+        .map(|&v| v.clamp(min_val, max_val))
+        .collect();
+    valid.sort_by(f64::total_cmp);
+    let lowest = valid.partition_point(|&v| v <= min_val);
+    move |value| {
+        if max_val <= min_val {
+            return flat;
         }
+        let below = valid.partition_point(|&v| v <= value.clamp(min_val, max_val));
+        if valid.len() == lowest {
+            return 0.0;
+        }
+        below.saturating_sub(lowest) as f64 / (valid.len() - lowest) as f64
     }
+}
 
-    let mut img = GrayImage::new(cols as u32, rows as u32);
-    // Normalize the values to the range [0, 255] and create the grayscale image.
-    for row in 0..rows {
-        for col in 0..cols {
-            let value = values[row][col];
-            let pixel = if value == nodata {
-                0
+/// Builds a grayscale image from the position of each valid cell on a `0..=1` scale,
+/// converted to a gray level by `to_level`. NoData cells are black.
+fn grid_to_levels<T: Primitive>(
+    grid: &ElevationGrid,
+    scale: impl Fn(f64) -> f64,
+    to_level: impl Fn(f64) -> T,
+) -> ImageBuffer<Luma<T>, Vec<T>> {
+    let rows = grid.rows();
+    let cols = grid.cols();
+
+    let mut img = ImageBuffer::new(cols as u32, rows as u32);
+    // Normalize the values to the range of gray levels and create the grayscale image.
+    for y in 0..rows {
+        for (col, &value) in grid.values[y].iter().enumerate() {
+            let pixel = if grid.is_nodata(value) {
+                to_level(0.0)
             } else {
-                ((value - min_val) / (max_val - min_val) * 255.0).round() as u8
+                to_level(scale(value).clamp(0.0, 1.0))
+            };
+            img.put_pixel(col as u32, y as u32, Luma([pixel]));
+        }
+    }
+
+    img
+}
+
+/// Applies gamma correction, `255 * (v / 255)^(1 / gamma)`, to every channel of an image.
+pub fn apply_gamma<P: Pixel<Subpixel = u8>>(image: &mut ImageBuffer<P, Vec<u8>>, gamma: f64) {
+    if gamma == 1.0 {
+        return;
+    }
+    let lut: Vec<u8> = (0..=255)
+        .map(|v| (255.0 * (v as f64 / 255.0).powf(1.0 / gamma)).round() as u8)
+        .collect();
+    for sample in image.iter_mut() {
+        *sample = lut[*sample as usize];
+    }
+}
+
+/// Applies gamma correction to every channel of a 16-bit image, like [`apply_gamma`].
+fn apply_gamma_16<P: Pixel<Subpixel = u16>>(image: &mut ImageBuffer<P, Vec<u16>>, gamma: f64) {
+    if gamma == 1.0 {
+        return;
+    }
+    for sample in image.iter_mut() {
+        *sample = (65535.0 * (*sample as f64 / 65535.0).powf(1.0 / gamma)).round() as u16;
+    }
+}
+
+/// Paints every valid cell at or below `level` with a flat water color.
+pub fn paint_water(image: &mut RgbImage, grid: &ElevationGrid, level: f64, color: Rgb<u8>) {
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let value = grid.values[y as usize][x as usize];
+        if !grid.is_nodata(value) && value <= level {
+            *pixel = color;
+        }
+    }
+}
+
+/// Blacks out the NoData cells of an image rendered from `grid`, as a full-strength
+/// hillshade leaves them, so no output format shows color in the voids.
+fn clear_nodata(image: &mut RgbImage, grid: &ElevationGrid) {
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        if grid.is_nodata(grid.values[y as usize][x as usize]) {
+            *pixel = Rgb([0, 0, 0]);
+        }
+    }
+}
+
+/// Blends each valid cell toward a `haze` color by up to `strength`, the more the more
+/// distant it is: the lower in `min_val..=max_val` by `elevation`, or the farther north of
+/// the southern edge by `distance`.
+pub fn apply_aerial_perspective(
+    image: &mut RgbImage,
+    grid: &ElevationGrid,
+    strength: f64,
+    haze: Rgb<u8>,
+    by: &str,
+    min_val: f64,
+    max_val: f64,
+) {
+    let range = max_val - min_val;
+    let last_row = grid.rows().saturating_sub(1).max(1) as f64;
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let row = y as usize;
+        let value = grid.values[row][x as usize];
+        if grid.is_nodata(value) {
+            continue;
+        }
+        let distance = match by {
+            "distance" => 1.0 - row as f64 / last_row,
+            _ if range > 0.0 => 1.0 - ((value - min_val) / range).clamp(0.0, 1.0),
+            _ => 0.0,
+        };
+        let amount = strength * distance;
+        for (channel, target) in pixel.0.iter_mut().zip(haze.0) {
+            *channel = (*channel as f64 * (1.0 - amount) + target as f64 * amount).round() as u8;
+        }
+    }
+}
+
+/// Marks the NoData cells of a rendered image.
+///
+/// Without a fill color, grayscale images gain an alpha channel and color images become RGBA,
+/// with NoData cells fully transparent. With a fill color, the image is RGB and NoData cells
+/// are painted with it.
+pub fn mask_nodata(image: impl Into<DynamicImage>, grid: &ElevationGrid, fill: Option<Rgb<u8>>) -> DynamicImage {
+    let image = image.into();
+    let is_nodata = |x: u32, y: u32| grid.is_nodata(grid.values[y as usize][x as usize]);
+
+    match (fill, image) {
+        (None, DynamicImage::ImageLuma8(gray)) => {
+            let (width, height) = gray.dimensions();
+            DynamicImage::ImageLumaA8(image::ImageBuffer::from_fn(width, height, |x, y| {
+                let alpha = if is_nodata(x, y) { 0 } else { 255 };
+                LumaA([gray.get_pixel(x, y)[0], alpha])
+            }))
+        }
+        (None, DynamicImage::ImageLuma16(gray)) => {
+            let (width, height) = gray.dimensions();
+            DynamicImage::ImageLumaA16(image::ImageBuffer::from_fn(width, height, |x, y| {
+                let alpha = if is_nodata(x, y) { 0 } else { u16::MAX };
+                LumaA([gray.get_pixel(x, y)[0], alpha])
+            }))
+        }
+        (Some(Rgb([r, g, b])), DynamicImage::ImageLuma16(gray)) => {
+            let mut rgb = DynamicImage::ImageLuma16(gray).into_rgb16();
+            let color = Rgb([r, g, b].map(|c| c as u16 * 257));
+            for (x, y, pixel) in rgb.enumerate_pixels_mut() {
+                if is_nodata(x, y) {
+                    *pixel = color;
+                }
+            }
+            DynamicImage::ImageRgb16(rgb)
+        }
+        (None, image) => {
+            let mut rgba = image.into_rgba8();
+            for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+                if is_nodata(x, y) {
+                    *pixel = Rgba([0, 0, 0, 0]);
+                }
+            }
+            DynamicImage::ImageRgba8(rgba)
+        }
+        (Some(color), image) => {
+            let mut rgb = image.into_rgb8();
+            for (x, y, pixel) in rgb.enumerate_pixels_mut() {
+                if is_nodata(x, y) {
+                    *pixel = color;
+                }
+            }
+            DynamicImage::ImageRgb8(rgb)
+        }
+    }
+}
+
+/// Returns a copy of the grid with every cell within `cells` cells of NoData, diagonals
+/// included, turned into NoData, eroding the valid area.
+///
+/// The edges of the grid don't count as NoData, so only voids eat into the valid area.
+pub fn trim_nodata_edges(grid: &ElevationGrid, cells: usize) -> ElevationGrid {
+    let (rows, cols) = (grid.rows(), grid.cols());
+    // Grow the NoData mask by a square window, along each row and then down each column.
+    let mut near = Grid::from_fn(rows, cols, |row, col| grid.is_nodata(grid.values[row][col]));
+    for row in near.iter_rows_mut() {
+        let grown = grow_mask(row, cells);
+        row.copy_from_slice(&grown);
+    }
+    let columns: Vec<Vec<bool>> = (0..cols)
+        .map(|col| grow_mask(&(0..rows).map(|row| near[row][col]).collect::<Vec<_>>(), cells))
+        .collect();
+    grid.map_cells(|row, col| (!columns[col][row]).then(|| grid.values[row][col]))
+}
+
+/// Marks every position within `reach` of a set position in a line of flags.
+fn grow_mask(mask: &[bool], reach: usize) -> Vec<bool> {
+    // Running counts of set flags answer each window in constant time.
+    let mut counts = vec![0; mask.len() + 1];
+    for (i, &set) in mask.iter().enumerate() {
+        counts[i + 1] = counts[i] + set as usize;
+    }
+    (0..mask.len())
+        .map(|i| counts[(i + reach + 1).min(mask.len())] > counts[i.saturating_sub(reach)])
+        .collect()
+}
+
+/// Draws the requested overlays on an image rendered from `grid`, masks its NoData cells
+/// with `fill` and encodes it in the output format.
+///
+/// JPEG has no alpha channel, so transparent NoData cells are flattened to black.
+/// WebP is always written losslessly; `quality` only applies to JPEG.
+fn save_image(
+    image: impl Into<DynamicImage>,
+    grid: &ElevationGrid,
+    fill: Option<Rgb<u8>>,
+    path: &Path,
+    text: &[(&str, String)],
+    options: &ProcessOptions,
+) -> Result<()> {
+    let mut image = image.into();
+    match &mut image {
+        DynamicImage::ImageLuma8(gray) => apply_gamma(gray, options.gamma),
+        DynamicImage::ImageRgb8(rgb) => apply_gamma(rgb, options.gamma),
+        DynamicImage::ImageLuma16(gray) => apply_gamma_16(gray, options.gamma),
+        other => unreachable!("rendered images are gray or 8-bit RGB, found {:?}", other.color()),
+    }
+    if let (Some(interval), "image") = (options.contour_interval, options.contour_format.as_str()) {
+        let mut rgb = image.into_rgb8();
+        let segments = trace_contours(grid, interval);
+        draw_contours(&mut rgb, grid, &segments, interval, options.index_contour, options.contour_color);
+        image = rgb.into();
+    }
+    let fill = if options.nodata_policy == "black" { fill.or(Some(Rgb([0, 0, 0]))) } else { fill };
+    let image = if options.edge_trim > 0 {
+        mask_nodata(image, &trim_nodata_edges(grid, options.edge_trim), fill)
+    } else {
+        mask_nodata(image, grid, fill)
+    };
+    let image = match options.canvas {
+        Some([width, height]) => letterbox(&image, width, height, options.pad_color, image_filter(&options.resample)),
+        None => image,
+    };
+    if let Some(size) = options.thumbnail {
+        write_thumbnail(&image, size, path, options)?;
+    }
+    if options.tiles {
+        return save_tiles(image, grid, path, options);
+    }
+    if options.format == "gpkg" {
+        return write_gpkg(path, &image, &grid.geotransform(), options.output_crs());
+    }
+    write_image(image, path, text, options)
+}
+
+/// Scales an image with `filter` to fit a `width` x `height` canvas, keeping its aspect
+/// ratio, and centers it there. The padding is `pad` or transparent.
+///
+/// Gray images stay gray unless the padding has a color, and 16-bit images stay 16-bit.
+pub fn letterbox(
+    image: &DynamicImage,
+    width: u32,
+    height: u32,
+    pad: Option<Rgb<u8>>,
+    filter: FilterType,
+) -> DynamicImage {
+    let resized = image.resize(width, height, filter);
+    let offset = (((width - resized.width()) / 2) as i64, ((height - resized.height()) / 2) as i64);
+    let gray = pad.is_none_or(|Rgb([r, g, b])| r == g && g == b)
+        && matches!(resized.color(), ColorType::L8 | ColorType::La8 | ColorType::L16 | ColorType::La16);
+    let sixteen_bit = resized.color().bytes_per_pixel() / resized.color().channel_count() == 2;
+    let Rgb([r, g, b]) = pad.unwrap_or(Rgb([0, 0, 0]));
+    let alpha = if pad.is_some() { 255 } else { 0 };
+    let wide = |v: u8| v as u16 * 257;
+    match (gray, sixteen_bit) {
+        (true, false) => pad_image(&resized.to_luma_alpha8(), width, height, LumaA([r, alpha]), offset).into(),
+        (true, true) => pad_image(&resized.to_luma_alpha16(), width, height, LumaA([wide(r), wide(alpha)]), offset).into(),
+        (false, false) => pad_image(&resized.to_rgba8(), width, height, Rgba([r, g, b, alpha]), offset).into(),
+        (false, true) => {
+            let pad = Rgba([wide(r), wide(g), wide(b), wide(alpha)]);
+            pad_image(&resized.to_rgba16(), width, height, pad, offset).into()
+        }
+    }
+}
+
+/// Places an image at `(x, y)` on a `width` x `height` canvas filled with `pad`.
+fn pad_image<P: Pixel>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    width: u32,
+    height: u32,
+    pad: P,
+    (x, y): (i64, i64),
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut canvas = ImageBuffer::from_pixel(width, height, pad);
+    image::imageops::replace(&mut canvas, image, x, y);
+    canvas
+}
+
+/// Writes a PNG copy of a finished image scaled down to fit `size` x `size` pixels next to
+/// its output `path`, as `{name}_thumb.png`. Smaller images are copied at full size.
+fn write_thumbnail(image: &DynamicImage, size: u32, path: &Path, options: &ProcessOptions) -> Result<()> {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    let thumb_path = path.with_file_name(format!("{}_thumb.png", name));
+    let thumb = if image.width() > size || image.height() > size {
+        image.resize(size, size, image_filter(&options.resample))
+    } else {
+        image.clone()
+    };
+    write_png(BufWriter::new(File::create(&thumb_path)?), &thumb, &[], &options.png_compression)?;
+    debug!("🖼️ Saved thumbnail to {:?}", thumb_path);
+    Ok(())
+}
+
+/// Cuts a finished web Mercator image into tiles under the directory `dir`.
+fn save_tiles(image: DynamicImage, grid: &ElevationGrid, dir: &Path, options: &ProcessOptions) -> Result<()> {
+    let width = grid.cols() as f64 * grid.cell_width;
+    let height = grid.rows() as f64 * grid.cell_height;
+    let max_zoom = options.max_zoom.unwrap_or_else(|| tiles::native_zoom(grid.cell_width.min(grid.cell_height)));
+    let min_zoom = options.min_zoom.unwrap_or_else(|| tiles::overview_zoom(width, height)).min(max_zoom);
+    let extension = match options.format.as_str() {
+        "jpeg" => "jpg",
+        other => other,
+    };
+    let count = write_tiles(
+        &image.into_rgba8(),
+        &grid.geotransform(),
+        (min_zoom, max_zoom),
+        options.tile_scheme == "tms",
+        dir,
+        extension,
+        |tile, path| write_image(DynamicImage::ImageRgba8(tile.clone()), path, &[], options),
+    )?;
+    debug!("🗺️ Wrote {} tiles for zoom levels {}-{} to {:?}", count, min_zoom, max_zoom, dir);
+    Ok(())
+}
+
+/// Encodes an image in the output format.
+fn write_image(image: DynamicImage, path: &Path, text: &[(&str, String)], options: &ProcessOptions) -> Result<()> {
+    // Multi-page TIFFs take the image as a page named like the file it replaces.
+    if options.format == "multi-tiff" {
+        let pages = options.pages.as_ref().context("multi-tiff output needs a file to append pages to")?;
+        return pages.append(&path.file_stem().unwrap_or_default().to_string_lossy(), &image);
+    }
+    let writer = BufWriter::new(File::create(path)?);
+    match options.format.as_str() {
+        "jpeg" => {
+            let image = match image {
+                DynamicImage::ImageLumaA8(_) | DynamicImage::ImageLuma8(_) => DynamicImage::ImageLuma8(image.into_luma8()),
+                image => DynamicImage::ImageRgb8(image.into_rgb8()),
             };
-            img.put_pixel(col as u32, (rows - 1 - row) as u32, Luma([pixel]));
+            image.write_with_encoder(JpegEncoder::new_with_quality(writer, options.quality))?;
+        }
+        "webp" => image.write_with_encoder(WebPEncoder::new_lossless(writer))?,
+        _ => write_png(writer, &image, text, &options.png_compression)?,
+    }
+    Ok(())
+}
+
+/// Encodes an image as PNG at a `compression` level, with `text` stored as tEXt chunks.
+fn write_png(writer: impl Write, image: &DynamicImage, text: &[(&str, String)], compression: &str) -> Result<()> {
+    let (color, depth) = match image.color() {
+        ColorType::L8 => (png::ColorType::Grayscale, png::BitDepth::Eight),
+        ColorType::La8 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Eight),
+        ColorType::Rgb8 => (png::ColorType::Rgb, png::BitDepth::Eight),
+        ColorType::Rgba8 => (png::ColorType::Rgba, png::BitDepth::Eight),
+        ColorType::L16 => (png::ColorType::Grayscale, png::BitDepth::Sixteen),
+        ColorType::La16 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen),
+        ColorType::Rgb16 => (png::ColorType::Rgb, png::BitDepth::Sixteen),
+        ColorType::Rgba16 => (png::ColorType::Rgba, png::BitDepth::Sixteen),
+        other => bail!("can't write {:?} images as PNG", other),
+    };
+    let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    encoder.set_compression(match compression {
+        "fast" => png::Compression::Fast,
+        "best" => png::Compression::Best,
+        _ => png::Compression::Default,
+    });
+    // Picking a filter per row, as the `image` crate does by default, keeps files small.
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+    for (keyword, value) in text {
+        encoder.add_text_chunk(keyword.to_string(), value.clone())?;
+    }
+
+    // PNG stores 16-bit samples big-endian.
+    let mut writer = encoder.write_header()?;
+    if depth == png::BitDepth::Sixteen {
+        let samples: Vec<u8> = image
+            .as_bytes()
+            .chunks_exact(2)
+            .flat_map(|b| u16::from_ne_bytes([b[0], b[1]]).to_be_bytes())
+            .collect();
+        writer.write_image_data(&samples)?;
+    } else {
+        writer.write_image_data(image.as_bytes())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Writes the grid values as a float GeoTIFF laid out like the rendered images, with
+/// overviews downsampled by the resampling method unless they're turned off.
+fn write_grid_geotiff(path: &Path, grid: &ElevationGrid, options: &ProcessOptions) -> Result<()> {
+    if grid.rounds_in_f32() {
+        warn!("⚠️ Integers beyond ±{} are rounded in the float32 GeoTIFF {:?}", F32_EXACT_INTEGERS, path);
+    }
+    let level = |grid: &ElevationGrid| {
+        let data: Vec<f32> = grid.values.iter().map(|&v| v as f32).collect();
+        (grid.cols() as u32, grid.rows() as u32, data)
+    };
+    let mut levels = vec![level(grid)];
+    if options.overviews {
+        for factor in geotiff::overview_factors(grid.cols() as u32, grid.rows() as u32) {
+            levels.push(level(&grid.downsample(factor as usize, &options.resample)));
         }
     }
+    geotiff::write_gray_f32(path, &levels, &grid.geotransform(), Some(grid.nodata), options.output_crs())
+}
 
-    Ok(img)
+/// Writes a rendered RGB image as a GeoTIFF placed like `grid`, with NoData cells
+/// transparent and overviews resized by the resampling method unless they're turned off.
+fn write_rgb_geotiff(path: &Path, rgb: &RgbImage, grid: &ElevationGrid, options: &ProcessOptions) -> Result<()> {
+    let mask = if options.edge_trim > 0 { &trim_nodata_edges(grid, options.edge_trim) } else { grid };
+    let rgba = mask_nodata(rgb.clone(), mask, None).into_rgba8();
+    let mut overviews = Vec::new();
+    if options.overviews {
+        for factor in geotiff::overview_factors(rgba.width(), rgba.height()) {
+            let (width, height) = (rgba.width().div_ceil(factor), rgba.height().div_ceil(factor));
+            overviews.push(image::imageops::resize(&rgba, width, height, image_filter(&options.resample)));
+        }
+    }
+    geotiff::write_rgba8(path, &rgba, &overviews, &grid.geotransform(), options.output_crs())
 }
 
 /// Converts a grayscale image to a colormap image.
-fn grayscale_to_colormap(gray: &GrayImage) -> RgbImage {
+pub fn grayscale_to_colormap(gray: &GrayImage, colormap: &Colormap) -> RgbImage {
     let (width, height) = gray.dimensions();
     let mut rgb_img = RgbImage::new(width, height);
 
     // Map grayscale values to RGB colors.
     for (x, y, pixel) in gray.enumerate_pixels() {
-        let v = pixel[0] as f32 / 255.0;
-        let r = (v * 255.0) as u8;
-        let g = ((1.0 - (v - 0.5).abs()) * 255.0) as u8;
-        let b = ((1.0 - v) * 128.0) as u8;
-        rgb_img.put_pixel(x, y, Rgb([r, g, b]));
+        let v = pixel[0] as f64 / 255.0;
+        rgb_img.put_pixel(x, y, colormap.sample(v));
+    }
+
+    rgb_img
+}
+
+/// Colors an elevation grid by its raw values, with colormap positions in elevation units.
+pub fn elevation_to_colormap(grid: &ElevationGrid, colormap: &Colormap) -> RgbImage {
+    let width = grid.cols() as u32;
+    let height = grid.rows() as u32;
+    let mut rgb_img = RgbImage::new(width, height);
+
+    for y in 0..height {
+        for (x, &value) in grid.values[y as usize].iter().enumerate() {
+            if !grid.is_nodata(value) {
+                rgb_img.put_pixel(x as u32, y, colormap.sample(value));
+            }
+        }
     }
 
     rgb_img
 }
 
 /// Converts degrees to radians.
-fn deg2rad(deg: f32) -> f32 {
+fn deg2rad(deg: f64) -> f64 {
     deg * (PI / 180.0)
 }
 
-/// Applies hillshading to a grayscale image.
-fn apply_hillshade(gray: &GrayImage, cell_size: f32, azimuth_deg: f32, altitude_deg: f32) -> RgbImage {
-    let (width, height) = gray.dimensions();
+/// Computes the Horn gradient of a row-major 3x3 neighborhood.
+///
+/// Returns the elevation change per map unit along increasing columns and increasing rows.
+fn horn_gradient(z: &[f64; 9], cell_width: f64, cell_height: f64) -> (f64, f64) {
+    let dzdx = ((z[2] + 2.0 * z[5] + z[8]) - (z[0] + 2.0 * z[3] + z[6])) / (8.0 * cell_width);
+    let dzdy = ((z[6] + 2.0 * z[7] + z[8]) - (z[0] + 2.0 * z[1] + z[2])) / (8.0 * cell_height);
+    (dzdx, dzdy)
+}
+
+/// Computes the aspect of a gradient in radians, counterclockwise from the +column axis.
+fn horn_aspect(dzdx: f64, dzdy: f64) -> f64 {
+    if dzdx != 0.0 {
+        let mut a = (dzdy / -dzdx).atan();
+        if dzdx > 0.0 {
+            a += PI;
+        } else if dzdy < 0.0 {
+            a += 2.0 * PI;
+        }
+        a
+    } else if dzdy > 0.0 {
+        PI / 2.0
+    } else {
+        3.0 * PI / 2.0
+    }
+}
+
+/// Computes the slope of every cell in degrees using the cell size from the header.
+///
+/// Elevations are multiplied by `z_factor` first. NoData cells stay NoData.
+pub fn compute_slope(grid: &ElevationGrid, z_factor: f64) -> ElevationGrid {
+    grid.map_cells(|row, col| {
+        let window = grid.window(row, col, z_factor)?;
+        let (dzdx, dzdy) = horn_gradient(&window, grid.cell_width, grid.cell_height);
+        Some((dzdx * dzdx + dzdy * dzdy).sqrt().atan().to_degrees())
+    })
+}
+
+/// Renders a slope grid in degrees as a shade that runs from white on flat cells to black on
+/// vertical ones. NoData cells are black.
+pub fn slope_shade(slope: &ElevationGrid) -> GrayImage {
+    normalize_grid(slope, 90.0, 0.0)
+}
+
+/// Bins a slope grid in degrees into classes numbered from 1, split at the ascending `breaks`:
+/// class 1 lies below the first break and the last class at or above the last one. NoData
+/// cells stay NoData.
+pub fn classify_slope(slope: &ElevationGrid, breaks: &[f64]) -> ElevationGrid {
+    slope.map_cells(|row, col| {
+        let value = slope.values[row][col];
+        (!slope.is_nodata(value)).then(|| (breaks.partition_point(|&b| b <= value) + 1) as f64)
+    })
+}
+
+/// Colors a grid of class numbers from 1 with the color of each class. NoData cells are black.
+pub fn classes_to_image(classes: &ElevationGrid, colors: &[Rgb<u8>]) -> RgbImage {
+    let mut rgb_img = RgbImage::new(classes.cols() as u32, classes.rows() as u32);
+    for (y, row) in rgb_img.rows_mut().enumerate() {
+        for (pixel, &value) in row.zip(&classes.values[y]) {
+            if !classes.is_nodata(value) {
+                *pixel = colors[value as usize - 1];
+            }
+        }
+    }
+    rgb_img
+}
+
+/// Computes the terrain ruggedness index of every cell (Riley et al.).
+///
+/// The index is the root-mean-square difference between a cell and its valid neighbors.
+/// NoData cells stay NoData.
+pub fn compute_tri(grid: &ElevationGrid) -> ElevationGrid {
+    grid.map_cells(|row, col| {
+        let center = grid.values[row][col];
+        if grid.is_nodata(center) {
+            return None;
+        }
+        let (sum, count) = grid
+            .neighbors(row, col, 1)
+            .fold((0.0, 0), |(sum, count), value| (sum + (value - center).powi(2), count + 1));
+        Some(if count == 0 { 0.0 } else { (sum / count as f64).sqrt() })
+    })
+}
+
+/// Computes the topographic position index of every cell.
+///
+/// The index is the cell's elevation minus the mean of the valid cells within `radius`
+/// cells, so ridges are positive and valleys negative. NoData cells stay NoData.
+pub fn compute_tpi(grid: &ElevationGrid, radius: usize) -> ElevationGrid {
+    grid.map_cells(|row, col| {
+        let center = grid.values[row][col];
+        if grid.is_nodata(center) {
+            return None;
+        }
+        let (sum, count) = grid
+            .neighbors(row, col, radius)
+            .fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+        Some(if count == 0 { 0.0 } else { center - sum / count as f64 })
+    })
+}
+
+/// Computes the sky-view factor: the fraction of the sky hemisphere visible from each cell.
+///
+/// The horizon is traced along `directions` evenly spaced azimuths out to `radius` cells, and
+/// each direction contributes `1 - sin` of its highest horizon angle. Flat ground scores 1 and
+/// the bottom of a ditch scores lower. Rows are computed in parallel.
+pub fn compute_sky_view_factor(grid: &ElevationGrid, z_factor: f64, radius: usize, directions: usize) -> ElevationGrid {
+    let steps = azimuth_steps(directions);
+
+    grid.par_map_cells(|row, col| {
+        let center = grid.values[row][col];
+        if grid.is_nodata(center) {
+            return None;
+        }
+        let visible: f64 = steps
+            .iter()
+            .map(|&step| 1.0 - horizon_angle(grid, row, col, step, radius, z_factor).sin())
+            .sum();
+        Some(visible / steps.len() as f64)
+    })
+}
+
+/// Returns the steepest upward angle in radians seen from a cell when walking `radius` cells
+/// along a `(column, row)` direction. NoData cells along the way are skipped.
+fn horizon_angle(
+    grid: &ElevationGrid,
+    row: usize,
+    col: usize,
+    (dx, dy): (f64, f64),
+    radius: usize,
+    z_factor: f64,
+) -> f64 {
+    let center = grid.values[row][col];
+    let mut max_angle: f64 = 0.0;
+    for step in 1..=radius {
+        let r = (row as f64 + dy * step as f64).round();
+        let c = (col as f64 + dx * step as f64).round();
+        if r < 0.0 || c < 0.0 || r >= grid.rows() as f64 || c >= grid.cols() as f64 {
+            break;
+        }
+        let value = grid.values[r as usize][c as usize];
+        if grid.is_nodata(value) {
+            continue;
+        }
+        let distance = (dx * step as f64 * grid.cell_width).hypot(dy * step as f64 * grid.cell_height);
+        max_angle = max_angle.max(((value - center) * z_factor).atan2(distance));
+    }
+    max_angle
+}
+
+/// Returns `count` unit steps in `(column, row)` order for evenly spaced azimuths clockwise from north.
+fn azimuth_steps(count: usize) -> Vec<(f64, f64)> {
+    (0..count)
+        .map(|i| {
+            let azimuth = 2.0 * PI * i as f64 / count as f64;
+            (azimuth.sin(), -azimuth.cos())
+        })
+        .collect()
+}
+
+/// Computes profile, plan or total curvature from the Zevenbergen-Thorne polynomial fitted
+/// to each 3x3 neighborhood, in hundredths of a z unit per map unit squared like ArcGIS.
+///
+/// Profile curvature runs along the slope and is negative where it steepens downhill;
+/// plan curvature runs across it and is positive on spurs. Total curvature is positive on
+/// convex ground. Cells with a NoData neighbor are NoData.
+pub fn compute_curvature(grid: &ElevationGrid, z_factor: f64, kind: &str) -> Result<ElevationGrid> {
+    if !matches!(kind, "profile" | "plan" | "total") {
+        bail!("Unsupported curvature type: {}", kind);
+    }
+    let (cw, ch) = (grid.cell_width, grid.cell_height);
+    Ok(grid.map_cells(|row, col| {
+        let z = grid.valid_window(row, col, z_factor)?;
+        // Second derivatives along and across the axes, then the gradient with y pointing north.
+        let d = ((z[3] + z[5]) / 2.0 - z[4]) / (cw * cw);
+        let e = ((z[1] + z[7]) / 2.0 - z[4]) / (ch * ch);
+        let f = (-z[0] + z[2] + z[6] - z[8]) / (4.0 * cw * ch);
+        let g = (z[5] - z[3]) / (2.0 * cw);
+        let h = (z[1] - z[7]) / (2.0 * ch);
+        let gradient = g * g + h * h;
+        let curvature = match kind {
+            "total" => -2.0 * (d + e),
+            _ if gradient == 0.0 => 0.0,
+            "profile" => -2.0 * (d * g * g + e * h * h + f * g * h) / gradient,
+            _ => 2.0 * (d * h * h + e * g * g - f * g * h) / gradient,
+        };
+        Some(curvature * 100.0)
+    }))
+}
+
+/// Aspect value given to flat cells, where the downslope direction is undefined.
+pub const FLAT_ASPECT: f64 = -1.0;
+
+/// Computes the aspect of every cell in compass degrees, matching ArcGIS.
+///
+/// Values run clockwise from north (0) through east (90) to 360. Flat cells are
+/// [`FLAT_ASPECT`] and NoData cells stay NoData.
+pub fn compute_aspect(grid: &ElevationGrid) -> ElevationGrid {
+    grid.map_cells(|row, col| {
+        let window = grid.window(row, col, 1.0)?;
+        let (dzdx, dzdy) = horn_gradient(&window, grid.cell_width, grid.cell_height);
+        if dzdx == 0.0 && dzdy == 0.0 {
+            return Some(FLAT_ASPECT);
+        }
+        // Grid rows run southward, so the math angle is measured from east towards north.
+        Some((450.0 - horn_aspect(dzdx, dzdy).to_degrees()) % 360.0)
+    })
+}
+
+/// Renders an aspect grid with a cyclic hue colormap. Flat cells are gray and NoData cells are black.
+pub fn aspect_to_image(aspect: &ElevationGrid) -> RgbImage {
+    let width = aspect.cols() as u32;
+    let height = aspect.rows() as u32;
     let mut rgb_img = RgbImage::new(width, height);
 
+    for y in 0..height {
+        for (x, &value) in aspect.values[y as usize].iter().enumerate() {
+            let color = if aspect.is_nodata(value) {
+                Rgb([0, 0, 0])
+            } else if value == FLAT_ASPECT {
+                Rgb([128, 128, 128])
+            } else {
+                hue_to_rgb(value)
+            };
+            rgb_img.put_pixel(x as u32, y, color);
+        }
+    }
+
+    rgb_img
+}
+
+/// Converts a hue in degrees to a fully saturated RGB color.
+fn hue_to_rgb(hue: f64) -> Rgb<u8> {
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    Rgb([(r * 255.0).round() as u8, (g * 255.0).round() as u8, (b * 255.0).round() as u8])
+}
+
+/// Applies hillshading to an elevation grid.
+///
+/// Elevations are multiplied by `z_factor` before computing slopes, which is needed when
+/// the vertical units differ from the horizontal ones (e.g. ~111320 for meters over degrees).
+/// The output is laid out like the image produced by [`grid_to_image`].
+pub fn apply_hillshade(grid: &ElevationGrid, z_factor: f64, azimuth_deg: f64, altitude_deg: f64) -> RgbImage {
     // Convert azimuth and altitude angles to radians.
-    let az_rad = deg2rad(360.0 - azimuth_deg + 90.0) % (2.0 * PI);
+    let az_rad = azimuth_to_rad(azimuth_deg);
     let alt_rad = deg2rad(altitude_deg);
 
-    // Helper function to get pixel values with boundary clamping.
-    let get = |x: i32, y: i32| -> f32 {
-        let cx = x.clamp(0, width as i32 - 1) as u32;
-        let cy = y.clamp(0, height as i32 - 1) as u32;
-        gray.get_pixel(cx, cy)[0] as f32
-    };
-
-    // Compute hillshade values for each pixel.
-    for y in 0..height as i32 {
-        for x in 0..width as i32 {
-            let z1 = get(x - 1, y - 1);
-            let z2 = get(x, y - 1);
-            let z3 = get(x + 1, y - 1);
-            let z4 = get(x - 1, y);
-            let z5 = get(x + 1, y);
-            let z6 = get(x - 1, y + 1);
-            let z7 = get(x, y + 1);
-            let z8 = get(x + 1, y + 1);
+    shade_grid(grid, z_factor, |slope, aspect| {
+        hillshade_intensity(slope, aspect, az_rad, alt_rad)
+    })
+}
 
-            // Calculate slope and aspect.
-            let dzdx = ((z3 + 2.0 * z5 + z8) - (z1 + 2.0 * z4 + z6)) / (8.0 * cell_size);
-            let dzdy = ((z6 + 2.0 * z7 + z8) - (z1 + 2.0 * z2 + z3)) / (8.0 * cell_size);
+/// Azimuths combined by [`apply_multidirectional_hillshade`], as in GDAL's `-multidirectional`.
+const MULTIDIRECTIONAL_AZIMUTHS: [f64; 4] = [225.0, 270.0, 315.0, 360.0];
 
-            let slope = (dzdx * dzdx + dzdy * dzdy).sqrt().atan();
-            let aspect = if dzdx != 0.0 {
-                let mut a = (dzdy / -dzdx).atan();
-                if dzdx > 0.0 {
-                    a += PI;
-                } else if dzdy < 0.0 {
-                    a += 2.0 * PI;
-                }
-                a
-            } else if dzdy > 0.0 {
-                PI / 2.0
-            } else {
-                3.0 * PI / 2.0
+/// Applies hillshading lit from several azimuths to an elevation grid.
+///
+/// Each azimuth is weighted by `sin²(aspect - azimuth)` so every slope is lit from the directions
+/// that give it the most relief; the weights sum to two, hence the final halving.
+pub fn apply_multidirectional_hillshade(grid: &ElevationGrid, z_factor: f64, altitude_deg: f64) -> RgbImage {
+    let alt_rad = deg2rad(altitude_deg);
+    let azimuths = MULTIDIRECTIONAL_AZIMUTHS.map(azimuth_to_rad);
+
+    shade_grid(grid, z_factor, |slope, aspect| {
+        let weighted: f64 = azimuths
+            .iter()
+            .map(|&az_rad| (aspect - az_rad).sin().powi(2) * hillshade_intensity(slope, aspect, az_rad, alt_rad))
+            .sum();
+        weighted / 2.0
+    })
+}
+
+/// Applies hillshading from several tinted lights to an elevation grid.
+///
+/// Each light adds its color scaled by the hillshade it casts; the sum is divided by the
+/// number of lights, so flat ground is as bright as under a single white light.
+pub fn apply_colored_hillshade(
+    grid: &ElevationGrid,
+    z_factor: f64,
+    lights: &[(f64, Rgb<u8>)],
+    altitude_deg: f64,
+) -> RgbImage {
+    let alt_rad = deg2rad(altitude_deg);
+    let lights: Vec<(f64, [f64; 3])> = lights
+        .iter()
+        .map(|&(azimuth, Rgb(color))| (azimuth_to_rad(azimuth), color.map(|c| c as f64 / lights.len() as f64)))
+        .collect();
+
+    shade_cells(grid, z_factor, |slope, aspect| {
+        let mut rgb = [0.0; 3];
+        for (az_rad, color) in &lights {
+            let intensity = hillshade_intensity(slope, aspect, *az_rad, alt_rad);
+            for (channel, c) in rgb.iter_mut().zip(color) {
+                *channel += c * intensity;
+            }
+        }
+        rgb.map(|channel| channel.round().min(255.0) as u8)
+    })
+}
+
+/// Number of directions sampled for ambient occlusion.
+const OCCLUSION_DIRECTIONS: usize = 8;
+
+/// Computes an ambient-occlusion shade in `0..=1`, darkening cells sheltered by higher terrain.
+///
+/// A cheaper cousin of [`compute_sky_view_factor`]: the horizon is only traced in eight
+/// directions out to `radius` cells. `strength` scales how much each sheltered direction
+/// darkens the cell; 1 gives the plain mean of the horizon sines.
+pub fn compute_occlusion(grid: &ElevationGrid, z_factor: f64, radius: usize, strength: f64) -> ElevationGrid {
+    let steps = azimuth_steps(OCCLUSION_DIRECTIONS);
+    grid.par_map_cells(|row, col| {
+        if grid.is_nodata(grid.values[row][col]) {
+            return None;
+        }
+        let occlusion: f64 = steps
+            .iter()
+            .map(|&step| horizon_angle(grid, row, col, step, radius, z_factor).sin())
+            .sum();
+        Some((1.0 - strength * occlusion / steps.len() as f64).clamp(0.0, 1.0))
+    })
+}
+
+/// Converts a compass azimuth in degrees to the radian angle used by the shading math.
+fn azimuth_to_rad(azimuth_deg: f64) -> f64 {
+    deg2rad(360.0 - azimuth_deg + 90.0) % (2.0 * PI)
+}
+
+/// Computes the hillshade intensity in `0.0..=1.0` of a cell with the given slope and aspect.
+fn hillshade_intensity(slope: f64, aspect: f64, az_rad: f64, alt_rad: f64) -> f64 {
+    (alt_rad.cos() * slope.cos()
+        + alt_rad.sin() * slope.sin() * (az_rad - aspect).cos()).max(0.0)
+}
+
+/// Renders a shade for every cell from its slope and aspect in radians.
+fn shade_grid(grid: &ElevationGrid, z_factor: f64, intensity: impl Fn(f64, f64) -> f64 + Sync) -> RgbImage {
+    shade_cells(grid, z_factor, |slope, aspect| {
+        let shade = (255.0 * intensity(slope, aspect)).round() as u8;
+        [shade; 3]
+    })
+}
+
+/// Renders a color for every cell from its slope and aspect in radians.
+fn shade_cells(grid: &ElevationGrid, z_factor: f64, color: impl Fn(f64, f64) -> [u8; 3] + Sync) -> RgbImage {
+    let width = grid.cols() as u32;
+    let height = grid.rows() as u32;
+    let mut rgb_img = RgbImage::new(width, height);
+
+    // Helper function to get scaled elevations in image coordinates with boundary clamping.
+    // NoData cells report `None` so the caller can fall back to the center elevation.
+    let get = |x: i32, y: i32| -> Option<f64> {
+        let cx = x.clamp(0, width as i32 - 1) as usize;
+        let cy = y.clamp(0, height as i32 - 1) as usize;
+        let value = grid.values[cy][cx];
+        (!grid.is_nodata(value)).then_some(value * z_factor)
+    };
+
+    // Compute hillshade values for each pixel, one image row per task.
+    rgb_img.par_chunks_mut(width as usize * 3).enumerate().for_each(|(y, row)| {
+        let y = y as i32;
+        for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+            let x = x as i32;
+            // NoData cells are left black.
+            let Some(z0) = get(x, y) else {
+                continue;
             };
+            let z = |x: i32, y: i32| get(x, y).unwrap_or(z0);
+
+            let window = [
+                z(x - 1, y - 1), z(x, y - 1), z(x + 1, y - 1),
+                z(x - 1, y), z0, z(x + 1, y),
+                z(x - 1, y + 1), z(x, y + 1), z(x + 1, y + 1),
+            ];
 
-            // Calculate hillshade intensity.
-            let hs = (alt_rad.cos() * slope.cos()
-                + alt_rad.sin() * slope.sin() * (az_rad - aspect).cos()).max(0.0);
+            // Calculate slope and aspect.
+            let (dzdx, dzdy) = horn_gradient(&window, grid.cell_width, grid.cell_height);
+
+            let slope = (dzdx * dzdx + dzdy * dzdy).sqrt().atan();
+            let aspect = horn_aspect(dzdx, dzdy);
 
-            let shade = (255.0 * hs).round() as u8;
-            rgb_img.put_pixel(x as u32, y as u32, Rgb([shade, shade, shade]));
+            pixel.copy_from_slice(&color(slope, aspect));
         }
-    }
+    });
 
     rgb_img
 }
 
-/// Blends a colormap image with a hillshade image.
-fn blend_colormap_with_hillshade(color: &RgbImage, shade: &RgbImage) -> RgbImage {
+/// Combines a colormap with a hillshade using one of the blend modes:
+///
+/// - `multiply` scales each color by the shade, darkening everything but lit slopes.
+/// - `overlay` and `soft-light` darken shadows and lighten lit slopes around mid-gray.
+/// - `hsv-value` keeps the hue and saturation of each color and takes its brightness from the shade.
+///
+/// `opacity` mixes the result with the plain colors: 0 returns the colormap and 1 the full blend.
+pub fn blend_colormap_with_hillshade(color: &RgbImage, shade: &RgbImage, mode: &str, opacity: f64) -> Result<RgbImage> {
+    let blend: fn(f32, f32) -> f32 = match mode {
+        "multiply" => |c, s| c * s,
+        "overlay" => |c, s| if c < 0.5 { 2.0 * c * s } else { 1.0 - 2.0 * (1.0 - c) * (1.0 - s) },
+        "soft-light" => |c, s| (1.0 - 2.0 * s) * c * c + 2.0 * s * c,
+        "hsv-value" => |c, _| c,
+        other => bail!("Unsupported blend mode: {}", other),
+    };
     let (width, height) = color.dimensions();
     let mut blended = RgbImage::new(width, height);
 
-    // Blend each pixel by modulating the colormap with the hillshade intensity.
+    // Blend each pixel channel by channel with the hillshade intensity.
     for y in 0..height {
         for x in 0..width {
-            let c = color.get_pixel(x, y);
+            let c = color.get_pixel(x, y).0.map(|v| v as f32 / 255.0);
             let s = shade.get_pixel(x, y)[0] as f32 / 255.0;
-            let r = (c[0] as f32 * s).clamp(0.0, 255.0) as u8;
-            let g = (c[1] as f32 * s).clamp(0.0, 255.0) as u8;
-            let b = (c[2] as f32 * s).clamp(0.0, 255.0) as u8;
-            blended.put_pixel(x, y, Rgb([r, g, b]));
+            // Rescaling the channels so the brightest equals the shade replaces the HSV value.
+            let base = if mode == "hsv-value" {
+                let value = c[0].max(c[1]).max(c[2]);
+                if value > 0.0 { c.map(|v| v * s / value) } else { [s; 3] }
+            } else {
+                c
+            };
+            let mut rgb = [0; 3];
+            for ((out, c), base) in rgb.iter_mut().zip(c).zip(base) {
+                let mixed = blend(base, s) * opacity as f32 + c * (1.0 - opacity as f32);
+                *out = (mixed * 255.0).clamp(0.0, 255.0) as u8;
+            }
+            blended.put_pixel(x, y, Rgb(rgb));
         }
     }
 
-    blended
+    Ok(blended)
 }
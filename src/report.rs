@@ -0,0 +1,79 @@
+use crate::processor::{DemSource, Processed};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Writes a JSON array describing a batch with one object per source: its input, the mode
+/// and path of each output, whether it succeeded and why not, the elevation range of its
+/// valid cells and the time it took in seconds.
+pub fn write_report(
+    path: &Path,
+    modes: &[String],
+    results: &[(&DemSource, Result<Processed, String>, Duration)],
+) -> Result<()> {
+    let entries: Vec<String> = results
+        .iter()
+        .map(|(source, result, elapsed)| format!("  {}", report_entry(source, modes, result, *elapsed)))
+        .collect();
+    let json = format!("[\n{}\n]\n", entries.join(",\n"));
+    fs::write(path, json).with_context(|| format!("writing report {:?}", path))
+}
+
+/// Formats the report object of one source.
+fn report_entry(source: &DemSource, modes: &[String], result: &Result<Processed, String>, elapsed: Duration) -> String {
+    let input = match source {
+        DemSource::File(path) => json_string(&path.to_string_lossy()),
+        DemSource::Mosaic { name, tiles } => {
+            let tiles: Vec<String> = tiles.iter().map(|tile| json_string(&tile.to_string_lossy())).collect();
+            format!("{},\"tiles\":[{}]", json_string(name), tiles.join(","))
+        }
+    };
+    let modes: Vec<String> = modes.iter().map(|mode| json_string(mode)).collect();
+    let (outputs, range, error) = match result {
+        Ok(processed) => {
+            let outputs: Vec<String> = processed
+                .outputs
+                .iter()
+                .map(|(mode, path)| {
+                    format!(r#"{{"mode":{},"path":{}}}"#, json_string(mode), json_string(&path.to_string_lossy()))
+                })
+                .collect();
+            (outputs, processed.elevation_range, "null".to_string())
+        }
+        Err(reason) => (Vec::new(), None, json_string(reason)),
+    };
+    let (min, max) = range.map_or(("null".to_string(), "null".to_string()), |(min, max)| {
+        (min.to_string(), max.to_string())
+    });
+    format!(
+        r#"{{"input":{},"modes":[{}],"outputs":[{}],"success":{},"error":{},"min":{},"max":{},"seconds":{:.3}}}"#,
+        input,
+        modes.join(","),
+        outputs.join(","),
+        result.is_ok(),
+        error,
+        min,
+        max,
+        elapsed.as_secs_f64()
+    )
+}
+
+/// Quotes a string for JSON, escaping quotes, backslashes and control characters.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
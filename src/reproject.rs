@@ -0,0 +1,69 @@
+use crate::crs::Crs;
+use crate::grid::Grid;
+use crate::processor::ElevationGrid;
+use crate::resample::sample_grid;
+use anyhow::{bail, Result};
+
+/// Number of points sampled along each edge of the grid when finding the warped extent.
+const EDGE_SAMPLES: usize = 32;
+
+/// Warps a grid from the `from` system into the `to` system, resampling it by the `method`:
+/// nearest, bilinear or bicubic.
+///
+/// The output covers the warped extent of the input with square cells about as large as
+/// the input cells at the grid center. Cells are interpolated from the input cells around
+/// them; next to NoData the nearest input cell is used instead, so NoData never blends
+/// into valid elevations. Cells outside the input are NoData.
+pub fn reproject_grid(grid: &ElevationGrid, from: Crs, to: Crs, method: &str) -> Result<ElevationGrid> {
+    if from == to {
+        return Ok(grid.clone());
+    }
+    let (rows, cols) = (grid.rows(), grid.cols());
+    let x_max = grid.x_min + cols as f64 * grid.cell_width;
+    let y_max = grid.y_min + rows as f64 * grid.cell_height;
+
+    // The edges of the grid bound the warped extent, since the projections are continuous.
+    let mut bounds = [f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY];
+    for i in 0..=EDGE_SAMPLES {
+        let t = i as f64 / EDGE_SAMPLES as f64;
+        let x = grid.x_min + t * (x_max - grid.x_min);
+        let y = grid.y_min + t * (y_max - grid.y_min);
+        for (px, py) in [(x, grid.y_min), (x, y_max), (grid.x_min, y), (x_max, y)] {
+            let (tx, ty) = from.transform(&to, px, py);
+            bounds = [bounds[0].min(tx), bounds[1].min(ty), bounds[2].max(tx), bounds[3].max(ty)];
+        }
+    }
+    let [tx_min, ty_min, tx_max, ty_max] = bounds;
+    if !bounds.iter().all(|b| b.is_finite()) {
+        bail!("the grid can't be projected to {}", to);
+    }
+
+    // Keep the area of a cell at the center of the grid.
+    let (cx, cy) = (grid.x_min + (x_max - grid.x_min) / 2.0, grid.y_min + (y_max - grid.y_min) / 2.0);
+    let center = from.transform(&to, cx, cy);
+    let east = from.transform(&to, cx + grid.cell_width, cy);
+    let north = from.transform(&to, cx, cy + grid.cell_height);
+    let distance = |(x, y): (f64, f64)| (x - center.0).hypot(y - center.1);
+    let cell_size = (distance(east) * distance(north)).sqrt();
+    let out_cols = ((tx_max - tx_min) / cell_size).ceil().max(1.0) as usize;
+    let out_rows = ((ty_max - ty_min) / cell_size).ceil().max(1.0) as usize;
+
+    let values = Grid::par_from_fn(out_rows, out_cols, |row, col| {
+        let tx = tx_min + (col as f64 + 0.5) * cell_size;
+        let ty = ty_max - (row as f64 + 0.5) * cell_size;
+        let (x, y) = to.transform(&from, tx, ty);
+        // Fractional input position, with whole numbers at cell centers.
+        let src_col = (x - grid.x_min) / grid.cell_width - 0.5;
+        let src_row = (y_max - y) / grid.cell_height - 0.5;
+        sample_grid(grid, src_row, src_col, method).unwrap_or(grid.nodata)
+    });
+
+    Ok(ElevationGrid {
+        values,
+        nodata: grid.nodata,
+        x_min: tx_min,
+        y_min: ty_max - out_rows as f64 * cell_size,
+        cell_width: cell_size,
+        cell_height: cell_size,
+    })
+}
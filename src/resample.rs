@@ -0,0 +1,78 @@
+use crate::processor::ElevationGrid;
+use image::imageops::FilterType;
+
+/// Interpolates a grid at a fractional `(row, col)` position, with whole numbers at cell
+/// centers, by the `method`: nearest, bilinear or bicubic. Positions outside the grid
+/// are `None`.
+///
+/// Interpolation never blends NoData into valid elevations: where a bicubic sample would
+/// weigh NoData or cells outside the grid, the bilinear sample is used, and where that
+/// would, the nearest cell is. NoData cells stay NoData under the nearest method.
+pub fn sample_grid(grid: &ElevationGrid, row: f64, col: f64, method: &str) -> Option<f64> {
+    let (rows, cols) = (grid.rows() as f64, grid.cols() as f64);
+    if !(-0.5..rows - 0.5).contains(&row) || !(-0.5..cols - 0.5).contains(&col) {
+        return None;
+    }
+    let interpolated = match method {
+        "bicubic" => sample_bicubic(grid, row, col).or_else(|| sample_bilinear(grid, row, col)),
+        "bilinear" => sample_bilinear(grid, row, col),
+        _ => None,
+    };
+    interpolated.or_else(|| valid_cell(grid, row.round(), col.round()))
+}
+
+/// Returns the image filter that resizes rendered images like the `method` resamples grids.
+pub fn image_filter(method: &str) -> FilterType {
+    match method {
+        "nearest" => FilterType::Nearest,
+        "bicubic" => FilterType::CatmullRom,
+        _ => FilterType::Triangle,
+    }
+}
+
+/// Interpolates between the four cells around a position, if they're all valid.
+fn sample_bilinear(grid: &ElevationGrid, row: f64, col: f64) -> Option<f64> {
+    let (r0, c0) = (row.floor(), col.floor());
+    let (fr, fc) = (row - r0, col - c0);
+    let a = valid_cell(grid, r0, c0)?;
+    let b = valid_cell(grid, r0, c0 + 1.0)?;
+    let c = valid_cell(grid, r0 + 1.0, c0)?;
+    let d = valid_cell(grid, r0 + 1.0, c0 + 1.0)?;
+    Some((a * (1.0 - fc) + b * fc) * (1.0 - fr) + (c * (1.0 - fc) + d * fc) * fr)
+}
+
+/// Interpolates a Catmull-Rom spline through the 4x4 cells around a position, if they're
+/// all valid.
+fn sample_bicubic(grid: &ElevationGrid, row: f64, col: f64) -> Option<f64> {
+    let (r0, c0) = (row.floor(), col.floor());
+    let (row_weights, col_weights) = (cubic_weights(row - r0), cubic_weights(col - c0));
+    let mut sum = 0.0;
+    for (i, row_weight) in row_weights.iter().enumerate() {
+        for (j, col_weight) in col_weights.iter().enumerate() {
+            let value = valid_cell(grid, r0 + i as f64 - 1.0, c0 + j as f64 - 1.0)?;
+            sum += row_weight * col_weight * value;
+        }
+    }
+    Some(sum)
+}
+
+/// Catmull-Rom weights of the four cells around a fraction `t` of the way between the
+/// middle two.
+fn cubic_weights(t: f64) -> [f64; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        (-t3 + 2.0 * t2 - t) / 2.0,
+        (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+        (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+        (t3 - t2) / 2.0,
+    ]
+}
+
+/// Returns the cell at a whole `(row, col)` position, unless it's NoData or outside the grid.
+fn valid_cell(grid: &ElevationGrid, row: f64, col: f64) -> Option<f64> {
+    if row < 0.0 || col < 0.0 {
+        return None;
+    }
+    let value = *grid.values.get(row as usize, col as usize)?;
+    (!grid.is_nodata(value)).then_some(value)
+}
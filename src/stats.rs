@@ -0,0 +1,96 @@
+use crate::processor::ElevationGrid;
+use std::fmt;
+
+/// Summary statistics of the valid cells of a grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridStats {
+    pub valid_count: usize,
+    pub nodata_count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// The 5th, 25th, 50th, 75th and 95th percentiles.
+    pub percentiles: [f64; 5],
+    /// Whether every valid cell holds a whole number.
+    pub integer: bool,
+}
+
+/// Percentiles reported by [`GridStats`].
+pub const PERCENTILES: [u32; 5] = [5, 25, 50, 75, 95];
+
+impl GridStats {
+    /// Computes the statistics of a grid, or `None` if every cell is NoData.
+    pub fn of(grid: &ElevationGrid) -> Option<Self> {
+        let mut valid: Vec<f64> = grid.values.iter().copied().filter(|&v| !grid.is_nodata(v)).collect();
+        if valid.is_empty() {
+            return None;
+        }
+        valid.sort_by(f64::total_cmp);
+
+        let n = valid.len() as f64;
+        let mean = valid.iter().sum::<f64>() / n;
+        let variance = valid.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        let percentile = |p: u32| valid[((p as f64 / 100.0) * (n - 1.0)).round() as usize];
+
+        Some(Self {
+            valid_count: valid.len(),
+            nodata_count: grid.rows() * grid.cols() - valid.len(),
+            min: valid[0],
+            max: valid[valid.len() - 1],
+            mean,
+            std_dev: variance.sqrt(),
+            percentiles: PERCENTILES.map(percentile),
+            integer: valid.iter().all(|v| v.fract() == 0.0),
+        })
+    }
+
+    /// Returns `int` for grids of whole numbers and `float` otherwise.
+    pub fn value_type(&self) -> &'static str {
+        if self.integer { "int" } else { "float" }
+    }
+
+    /// Formats the statistics as a JSON object.
+    pub fn to_json(&self) -> String {
+        let percentiles: Vec<String> = PERCENTILES
+            .iter()
+            .zip(self.percentiles)
+            .map(|(p, value)| format!(r#""p{}":{}"#, p, value))
+            .collect();
+        format!(
+            r#"{{"value_type":"{}","valid_count":{},"nodata_count":{},"min":{},"max":{},"mean":{},"std_dev":{},"percentiles":{{{}}}}}"#,
+            self.value_type(),
+            self.valid_count,
+            self.nodata_count,
+            self.min,
+            self.max,
+            self.mean,
+            self.std_dev,
+            percentiles.join(",")
+        )
+    }
+}
+
+impl fmt::Display for GridStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Whole-number grids print their extremes and percentiles without decimals.
+        let digits = if self.integer { 0 } else { 2 };
+        write!(
+            f,
+            "{} values, min {:.*}, max {:.*}, mean {:.2}, std dev {:.2}, {} valid and {} NoData cells",
+            self.value_type(),
+            digits,
+            self.min,
+            digits,
+            self.max,
+            self.mean,
+            self.std_dev,
+            self.valid_count,
+            self.nodata_count
+        )?;
+        for (p, value) in PERCENTILES.iter().zip(self.percentiles) {
+            write!(f, ", p{} {:.*}", p, digits, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use crate::geotiff::GeoTransform;
+use anyhow::Result;
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Width and height of a map tile in pixels.
+pub const TILE_SIZE: u32 = 256;
+/// Half the width of the web Mercator world in meters.
+const WORLD_EXTENT: f64 = 20_037_508.342_789_244;
+/// Deepest zoom level tiles can be cut at.
+pub const MAX_ZOOM: u8 = 24;
+
+/// Size of a tile pixel at a zoom level, in web Mercator meters.
+fn pixel_size(zoom: u8) -> f64 {
+    2.0 * WORLD_EXTENT / (TILE_SIZE as f64 * (1u64 << zoom) as f64)
+}
+
+/// The shallowest zoom level whose pixels are no larger than `cell_size` meters.
+pub fn native_zoom(cell_size: f64) -> u8 {
+    (0..MAX_ZOOM).find(|&zoom| pixel_size(zoom) <= cell_size).unwrap_or(MAX_ZOOM)
+}
+
+/// The deepest zoom level at which an extent of `width` by `height` meters fits in one tile.
+pub fn overview_zoom(width: f64, height: f64) -> u8 {
+    let span = width.max(height);
+    (0..=MAX_ZOOM)
+        .rev()
+        .find(|&zoom| pixel_size(zoom) * TILE_SIZE as f64 >= span)
+        .unwrap_or(0)
+}
+
+/// Cuts a web Mercator image into a `z/x/y.{extension}` pyramid of 256x256 tiles under
+/// `dir` and returns the number of tiles written.
+///
+/// Tiles at `max_zoom` are resampled bilinearly from the image; each shallower level is
+/// averaged from the four tiles below it. Tiles without any data are not written. With
+/// `tms`, rows are numbered from the south as in the TMS specification instead of from
+/// the north as in XYZ.
+pub fn write_tiles(
+    image: &RgbaImage,
+    transform: &GeoTransform,
+    zooms: (u8, u8),
+    tms: bool,
+    dir: &Path,
+    extension: &str,
+    write: impl Fn(&RgbaImage, &Path) -> Result<()> + Sync,
+) -> Result<usize> {
+    let (min_zoom, max_zoom) = zooms;
+    let mut tiles = cut_tiles(image, transform, max_zoom);
+    let mut written = 0;
+    for zoom in (min_zoom..=max_zoom).rev() {
+        if zoom < max_zoom {
+            tiles = merge_tiles(&tiles);
+        }
+        let rows = 1u32 << zoom;
+        tiles.par_iter().try_for_each(|(&(x, y), tile)| {
+            let row = if tms { rows - 1 - y } else { y };
+            let column_dir = dir.join(zoom.to_string()).join(x.to_string());
+            fs::create_dir_all(&column_dir)?;
+            write(tile, &column_dir.join(format!("{}.{}", row, extension)))
+        })?;
+        written += tiles.len();
+    }
+    Ok(written)
+}
+
+/// Resamples the image into every tile it touches at a zoom level, keyed by XYZ column and row.
+fn cut_tiles(image: &RgbaImage, transform: &GeoTransform, zoom: u8) -> HashMap<(u32, u32), RgbaImage> {
+    // Map coordinates of the image corners, in whatever order the transform puts them.
+    let xs = [transform[0], transform[0] + image.width() as f64 * transform[1]];
+    let ys = [transform[3], transform[3] + image.height() as f64 * transform[5]];
+    let tile_span = pixel_size(zoom) * TILE_SIZE as f64;
+    let count = 1u32 << zoom;
+    let column = |x: f64| (((x + WORLD_EXTENT) / tile_span).floor().max(0.0) as u32).min(count - 1);
+    let row = |y: f64| (((WORLD_EXTENT - y) / tile_span).floor().max(0.0) as u32).min(count - 1);
+    let (x_min, x_max) = (xs[0].min(xs[1]), xs[0].max(xs[1]));
+    let (y_min, y_max) = (ys[0].min(ys[1]), ys[0].max(ys[1]));
+
+    let positions: Vec<(u32, u32)> = (row(y_max)..=row(y_min))
+        .flat_map(|y| (column(x_min)..=column(x_max)).map(move |x| (x, y)))
+        .collect();
+    positions
+        .into_par_iter()
+        .filter_map(|(x, y)| {
+            let left = -WORLD_EXTENT + x as f64 * tile_span;
+            let top = WORLD_EXTENT - y as f64 * tile_span;
+            let size = pixel_size(zoom);
+            let tile = RgbaImage::from_fn(TILE_SIZE, TILE_SIZE, |px, py| {
+                let map_x = left + (px as f64 + 0.5) * size;
+                let map_y = top - (py as f64 + 0.5) * size;
+                // Image pixel position, with whole numbers at pixel centers.
+                let col = (map_x - transform[0]) / transform[1] - 0.5;
+                let row = (map_y - transform[3]) / transform[5] - 0.5;
+                sample(image, col, row)
+            });
+            tile.pixels().any(|p| p[3] > 0).then_some(((x, y), tile))
+        })
+        .collect()
+}
+
+/// Builds the tiles one zoom level up by averaging each 2x2 block of tiles.
+fn merge_tiles(children: &HashMap<(u32, u32), RgbaImage>) -> HashMap<(u32, u32), RgbaImage> {
+    let mut parents: Vec<(u32, u32)> = children.keys().map(|&(x, y)| (x / 2, y / 2)).collect();
+    parents.sort_unstable();
+    parents.dedup();
+    parents
+        .into_par_iter()
+        .map(|(x, y)| {
+            let tile = RgbaImage::from_fn(TILE_SIZE, TILE_SIZE, |px, py| {
+                let (cx, cy) = (2 * x + px * 2 / TILE_SIZE, 2 * y + py * 2 / TILE_SIZE);
+                let Some(child) = children.get(&(cx, cy)) else {
+                    return Rgba([0, 0, 0, 0]);
+                };
+                let (sx, sy) = ((px * 2) % TILE_SIZE, (py * 2) % TILE_SIZE);
+                let block = [(sx, sy), (sx + 1, sy), (sx, sy + 1), (sx + 1, sy + 1)];
+                blend(block.map(|(bx, by)| (*child.get_pixel(bx, by), 0.25)))
+            });
+            ((x, y), tile)
+        })
+        .collect()
+}
+
+/// Samples an image bilinearly at a fractional pixel position; outside it is transparent.
+fn sample(image: &RgbaImage, col: f64, row: f64) -> Rgba<u8> {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    if !(-0.5..width - 0.5).contains(&col) || !(-0.5..height - 0.5).contains(&row) {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let (c0, r0) = (col.floor(), row.floor());
+    let (fc, fr) = (col - c0, row - r0);
+    let pixel = |c: f64, r: f64| {
+        let c = c.clamp(0.0, width - 1.0) as u32;
+        let r = r.clamp(0.0, height - 1.0) as u32;
+        *image.get_pixel(c, r)
+    };
+    blend([
+        (pixel(c0, r0), (1.0 - fc) * (1.0 - fr)),
+        (pixel(c0 + 1.0, r0), fc * (1.0 - fr)),
+        (pixel(c0, r0 + 1.0), (1.0 - fc) * fr),
+        (pixel(c0 + 1.0, r0 + 1.0), fc * fr),
+    ])
+}
+
+/// Mixes weighted pixels, weighting colors by alpha so transparent pixels don't darken edges.
+fn blend(pixels: [(Rgba<u8>, f64); 4]) -> Rgba<u8> {
+    let alpha: f64 = pixels.iter().map(|(p, w)| p[3] as f64 * w).sum();
+    if alpha == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |i: usize| {
+        let sum: f64 = pixels.iter().map(|(p, w)| p[i] as f64 * p[3] as f64 * w).sum();
+        (sum / alpha).round().clamp(0.0, 255.0) as u8
+    };
+    Rgba([channel(0), channel(1), channel(2), alpha.round().clamp(0.0, 255.0) as u8])
+}
@@ -0,0 +1,34 @@
+use crate::crs::Crs;
+use crate::geotiff::GeoTransform;
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns the world file path for an image, e.g. `tile.pgw` for `tile.png`.
+///
+/// The sidecar extension is the first and last letter of the image extension followed by `w`.
+pub fn world_file_path(image_path: &Path) -> PathBuf {
+    let ext = image_path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut chars = ext.chars();
+    let sidecar = match (chars.next(), chars.last()) {
+        (Some(first), Some(last)) => format!("{}{}w", first, last),
+        _ => format!("{}w", ext),
+    };
+    image_path.with_extension(sidecar)
+}
+
+/// Writes a world file placing an image with the given pixel-corner transform, and a `.prj`
+/// file with the ESRI well-known text of `crs` if known.
+///
+/// World files reference the center of the first pixel, so the origin is moved by half a pixel.
+pub fn write_world_file(image_path: &Path, transform: &GeoTransform, crs: Option<Crs>) -> Result<()> {
+    let [x0, a, b, y0, d, e] = *transform;
+    let center_x = x0 + a / 2.0 + b / 2.0;
+    let center_y = y0 + d / 2.0 + e / 2.0;
+    let contents = format!("{}\n{}\n{}\n{}\n{}\n{}\n", a, d, b, e, center_x, center_y);
+    fs::write(world_file_path(image_path), contents)?;
+    if let Some(crs) = crs {
+        fs::write(image_path.with_extension("prj"), crs.esri_wkt())?;
+    }
+    Ok(())
+}
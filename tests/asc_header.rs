@@ -0,0 +1,52 @@
+//! Checks that malformed ASCII grid headers are reported by line, and the cell size override.
+
+use dem_processor::{override_cell_size, validate_ascii_header};
+use std::io::Cursor;
+
+/// The error message of validating `header`.
+fn error(header: &str) -> String {
+    validate_ascii_header(Cursor::new(header)).unwrap_err().to_string()
+}
+
+#[test]
+fn well_formed_headers_pass() {
+    let header = "ncols 4\nnrows 3\nxllcorner 1000\nyllcorner 2000\ncellsize 10\nNODATA_value -9999\n1 2 3 4\n";
+    validate_ascii_header(Cursor::new(header)).unwrap();
+    // NODATA_value is optional and keywords are case-insensitive.
+    validate_ascii_header(Cursor::new("NCOLS 1\nNROWS 1\nXLLCENTER 0\nYLLCENTER 0\nCELLSIZE 1\n5\n")).unwrap();
+}
+
+#[test]
+fn errors_name_the_line() {
+    assert!(error("ncols 4\n").starts_with("line 2:"));
+    assert!(error("ncols 0\nnrows 3\n").starts_with("line 1:"));
+    assert!(error("ncols 4\nnrows 3\nxllcorner 0\nyllcenter 0\ncellsize 1\n").starts_with("line 4:"));
+    assert_eq!(
+        error("ncols 4\nnrows 3\nxllcorner 0\nyllcorner 0\ncellsize -1\n"),
+        "line 5: cellsize must be positive, found -1"
+    );
+    assert!(error("ncols 4\nnrows 3\nxllcorner 0\nyllcorner 0\ndx 1\ndy 1\n").contains("dx and dy"));
+    assert!(error("ncols 4\nnrows 3\nxllcorner 0\nyllcorner 0\ncellsize 1\nnodata x\n").starts_with("line 6:"));
+}
+
+#[test]
+fn cell_size_is_overridden_in_place() {
+    let (data, original) = override_cell_size(b"ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 5\n7\n", 2.0);
+    assert_eq!(original, Some(5.0));
+    assert_eq!(String::from_utf8(data).unwrap(), "ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 2\n7\n");
+
+    let (data, original) = override_cell_size(b"ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ndx 1\ndy 2\n7\n", 3.0);
+    assert_eq!(original, None);
+    assert_eq!(String::from_utf8(data).unwrap(), "ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 3\n7\n");
+
+    // Headers without a cell size get one after the corner coordinates.
+    let (data, original) = override_cell_size(b"ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\n7\n", 4.0);
+    assert_eq!(original, None);
+    assert_eq!(String::from_utf8(data).unwrap(), "ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 4\n7\n");
+}
+
+#[test]
+fn missing_cell_size_points_to_the_override() {
+    let message = error("ncols 1\nnrows 1\nxllcorner 0\nyllcorner 0\nNODATA_value -9999\n7\n");
+    assert!(message.starts_with("line 5:") && message.contains("--cell-size"), "{}", message);
+}
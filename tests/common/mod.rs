@@ -0,0 +1,41 @@
+//! Synthetic grids shared by the integration tests.
+
+// Each test crate uses only some of the helpers.
+#![allow(dead_code)]
+
+use dem_processor::{ElevationGrid, Grid};
+use std::fmt::Write;
+
+/// NoData value of the synthetic grids.
+pub const NODATA: f64 = -9999.0;
+
+/// A grid of square `cell_size` cells with elevations `z(row, col)`, rows running from
+/// north to south, whose lower-left corner is at the origin.
+pub fn grid(rows: usize, cols: usize, cell_size: f64, z: impl FnMut(usize, usize) -> f64) -> ElevationGrid {
+    ElevationGrid {
+        values: Grid::from_fn(rows, cols, z),
+        nodata: NODATA,
+        x_min: 0.0,
+        y_min: 0.0,
+        cell_width: cell_size,
+        cell_height: cell_size,
+    }
+}
+
+/// Writes a grid as an ESRI ASCII grid, rows running from north to south.
+pub fn asc_text(grid: &ElevationGrid) -> String {
+    let mut text = format!(
+        "ncols {}\nnrows {}\nxllcorner {}\nyllcorner {}\ncellsize {}\nNODATA_value {}\n",
+        grid.cols(),
+        grid.rows(),
+        grid.x_min,
+        grid.y_min,
+        grid.cell_width,
+        grid.nodata
+    );
+    for row in grid.values.iter_rows() {
+        let line: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+        writeln!(text, "{}", line.join(" ")).unwrap();
+    }
+    text
+}
@@ -0,0 +1,32 @@
+use dem_processor::compare_images;
+use image::{DynamicImage, GrayImage, Luma};
+
+#[test]
+fn identical_images_do_not_differ() {
+    let image = DynamicImage::ImageLuma8(GrayImage::from_fn(4, 3, |x, y| Luma([(x * 40 + y) as u8])));
+    let (diff, diff_image) = compare_images(&image, &image).unwrap();
+    assert_eq!((diff.rms, diff.max, diff.differing, diff.pixels), (0.0, 0.0, 0, 12));
+    assert!(diff_image.pixels().all(|p| p.0 == [0]));
+}
+
+#[test]
+fn differences_are_summarized_and_stretched() {
+    let a = DynamicImage::ImageLuma8(GrayImage::from_pixel(2, 2, Luma([100])));
+    let mut b = a.to_luma8();
+    b.put_pixel(1, 0, Luma([110]));
+    let (diff, diff_image) = compare_images(&a, &DynamicImage::ImageLuma8(b)).unwrap();
+
+    assert_eq!((diff.differing, diff.pixels), (1, 4));
+    assert!((diff.max - 10.0).abs() < 1e-3);
+    // Three of the four channels of one pixel in four differ by 10 levels.
+    assert!((diff.rms - (3.0 * 100.0 / 16.0f64).sqrt()).abs() < 1e-3);
+    assert_eq!(diff_image.get_pixel(1, 0).0, [255]);
+    assert_eq!(diff_image.get_pixel(0, 0).0, [0]);
+}
+
+#[test]
+fn images_of_different_sizes_are_rejected() {
+    let a = DynamicImage::new_luma8(2, 2);
+    let b = DynamicImage::new_luma8(3, 2);
+    assert!(compare_images(&a, &b).is_err());
+}
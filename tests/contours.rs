@@ -0,0 +1,76 @@
+//! Checks the marching-squares contour tracing, saddles included.
+
+mod common;
+
+use dem_processor::{join_segments, trace_contours, ElevationGrid};
+
+/// A 2x2 block of cells with the corner elevations clockwise from the top left.
+fn block([top_left, top_right, bottom_right, bottom_left]: [f64; 4]) -> ElevationGrid {
+    common::grid(2, 2, 1.0, |row, col| [[top_left, top_right], [bottom_left, bottom_right]][row][col])
+}
+
+/// Asserts two grid positions are equal to within rounding.
+fn assert_near(actual: (f64, f64), expected: (f64, f64)) {
+    assert!(
+        (actual.0 - expected.0).abs() < 1e-9 && (actual.1 - expected.1).abs() < 1e-9,
+        "{:?} is not {:?}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn saddle_with_a_high_center_joins_the_high_corners() {
+    // High north-west and south-east corners, with a mean of exactly the contour level.
+    let segments = trace_contours(&block([9.0, 1.0, 9.0, 1.0]), 5.0);
+    assert_eq!(segments.len(), 2);
+    // Each segment cuts off one of the low corners.
+    assert_near(segments[0].start, (0.0, 0.5));
+    assert_near(segments[0].end, (0.5, 1.0));
+    assert_near(segments[1].start, (0.5, 0.0));
+    assert_near(segments[1].end, (1.0, 0.5));
+}
+
+#[test]
+fn saddle_with_a_low_center_joins_the_low_corners() {
+    let segments = trace_contours(&block([9.0, 1.0, 7.0, 1.0]), 5.0);
+    assert_eq!(segments.len(), 2);
+    // Each segment cuts off one of the high corners.
+    assert_near(segments[0].start, (0.5, 0.0));
+    assert_near(segments[0].end, (0.0, 0.5));
+    assert_near(segments[1].start, (1.0, 2.0 / 3.0));
+    assert_near(segments[1].end, (2.0 / 3.0, 1.0));
+}
+
+#[test]
+fn ramp_contours_join_into_straight_lines() {
+    // Rising 10 m per cell to the east.
+    let segments = trace_contours(&common::grid(3, 3, 1.0, |_, col| col as f64 * 10.0), 10.0);
+    let mut lines = join_segments(&segments);
+    lines.sort_by(|a, b| a.elevation.total_cmp(&b.elevation));
+
+    assert_eq!(lines.len(), 2);
+    for (line, col) in lines.iter().zip([1.0, 2.0]) {
+        assert_eq!(line.elevation, col * 10.0);
+        let mut points = line.points.clone();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert_eq!(points, [(0.0, col), (1.0, col), (2.0, col)]);
+    }
+}
+
+#[test]
+fn blocks_touching_nodata_have_no_contours() {
+    let segments = trace_contours(&block([9.0, 1.0, 9.0, common::NODATA]), 5.0);
+    assert!(segments.is_empty());
+}
+
+#[test]
+fn levels_are_whole_multiples_of_the_interval() {
+    // Rising from 0 m to 9 m, traced every 0.1 m.
+    let segments = trace_contours(&common::grid(2, 10, 1.0, |_, col| col as f64), 0.1);
+    let mut levels: Vec<f64> = segments.iter().map(|segment| segment.elevation).collect();
+    levels.dedup();
+    // Level 0 touches no block, as every corner is at or above it.
+    let expected: Vec<f64> = (1..=90).map(|k| k as f64 / 10.0).collect();
+    assert_eq!(levels, expected);
+}
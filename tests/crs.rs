@@ -0,0 +1,57 @@
+//! Pins the coordinate system definitions written into GeoPackages and `.prj` files.
+
+use dem_processor::Crs;
+
+const WGS84: &str = concat!(
+    r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],"#,
+    r#"AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],"#,
+    r#"UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]]"#,
+);
+
+#[test]
+fn geographic_wkt_is_wgs84() {
+    assert_eq!(Crs::Geographic.wkt(), WGS84);
+}
+
+#[test]
+fn web_mercator_wkt_projects_on_the_sphere() {
+    let expected = format!(
+        concat!(
+            r#"PROJCS["WGS 84 / Pseudo-Mercator",{},PROJECTION["Mercator_1SP"],PARAMETER["central_meridian",0],"#,
+            r#"PARAMETER["scale_factor",1],PARAMETER["false_easting",0],PARAMETER["false_northing",0],"#,
+            r#"UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],AXIS["Northing",NORTH],"#,
+            r#"EXTENSION["PROJ4","+proj=merc +a=6378137 +b=6378137 +lat_ts=0 +lon_0=0 +x_0=0 +y_0=0 +k=1 "#,
+            r#"+units=m +nadgrids=@null +wktext +no_defs"],AUTHORITY["EPSG","3857"]]"#,
+        ),
+        WGS84
+    );
+    assert_eq!(Crs::WebMercator.wkt(), expected);
+}
+
+#[test]
+fn utm_wkt_names_the_zone_parameters() {
+    let expected = format!(
+        concat!(
+            r#"PROJCS["WGS 84 / UTM zone 33S",{},PROJECTION["Transverse_Mercator"],PARAMETER["latitude_of_origin",0],"#,
+            r#"PARAMETER["central_meridian",15],PARAMETER["scale_factor",0.9996],PARAMETER["false_easting",500000],"#,
+            r#"PARAMETER["false_northing",10000000],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],"#,
+            r#"AXIS["Northing",NORTH],AUTHORITY["EPSG","32733"]]"#,
+        ),
+        WGS84
+    );
+    assert_eq!(Crs::Utm { zone: 33, north: false }.wkt(), expected);
+}
+
+#[test]
+fn esri_utm_wkt_spells_out_the_zone() {
+    assert_eq!(
+        Crs::Utm { zone: 33, north: true }.esri_wkt(),
+        concat!(
+            r#"PROJCS["WGS_1984_UTM_Zone_33N",GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984","#,
+            r#"SPHEROID["WGS_1984",6378137.0,298.257223563]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],"#,
+            r#"PROJECTION["Transverse_Mercator"],PARAMETER["False_Easting",500000.0],PARAMETER["False_Northing",0.0],"#,
+            r#"PARAMETER["Central_Meridian",15.0],PARAMETER["Scale_Factor",0.9996],PARAMETER["Latitude_Of_Origin",0.0],"#,
+            r#"UNIT["Meter",1.0]]"#,
+        )
+    );
+}
@@ -0,0 +1,28 @@
+//! Checks NoData filling on small synthetic grids.
+
+mod common;
+
+use common::NODATA;
+use dem_processor::fill_nodata;
+
+#[test]
+fn small_holes_are_filled_and_large_ones_kept() {
+    // A ramp rising 10 m per column, with a one-cell hole and a two-cell gap on its west edge.
+    let mut ramp = common::grid(3, 5, 1.0, |row, col| match (row, col) {
+        (1, 2) | (0, 0) | (1, 0) => NODATA,
+        _ => col as f64 * 10.0,
+    });
+    assert_eq!(fill_nodata(&mut ramp, 1), 1);
+    assert_eq!(ramp.values[1][2], 20.0);
+    assert!(ramp.is_nodata(ramp.values[0][0]) && ramp.is_nodata(ramp.values[1][0]));
+
+    assert_eq!(fill_nodata(&mut ramp, 2), 2);
+    assert!(ramp.values.iter().all(|&value| !ramp.is_nodata(value)));
+}
+
+#[test]
+fn grid_of_only_nodata_stays_empty() {
+    let mut empty = common::grid(2, 2, 1.0, |_, _| NODATA);
+    fill_nodata(&mut empty, 10);
+    assert!(empty.values.iter().all(|&value| empty.is_nodata(value)));
+}
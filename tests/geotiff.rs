@@ -0,0 +1,107 @@
+//! Checks the GeoTIFFs written for raw grids and rendered images.
+
+mod common;
+
+use dem_processor::geotiff::{read_single_band, write_gray_f32};
+use dem_processor::{
+    process_asc_file_with, read_ascii_grid, read_ascii_grid_from, read_geotiff_grid, Crs, ProcessOptions,
+};
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::path::Path;
+use tiff::decoder::Decoder;
+use tiff::tags::Tag;
+
+const ALL_NODATA: &str = "tests/fixtures/all_nodata.asc";
+const NORTH_UP: &str = "tests/fixtures/north_up.asc";
+
+#[test]
+fn grids_without_valid_cells_have_nothing_to_round() {
+    let grid = read_ascii_grid(Path::new(ALL_NODATA)).unwrap();
+    assert!(grid.is_integer());
+    assert!(!grid.rounds_in_f32());
+}
+
+#[test]
+fn only_large_integer_codes_round_in_f32() {
+    let grid = |cells: &str| {
+        let text = format!("ncols 2\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 1\n{}\n", cells);
+        read_ascii_grid_from(Cursor::new(text)).unwrap()
+    };
+    assert!(grid("1 20000000").rounds_in_f32());
+    assert!(!grid("1 16777216").rounds_in_f32());
+    assert!(!grid("0.5 20000000").rounds_in_f32());
+}
+
+#[test]
+fn all_nodata_grid_is_written_as_nodata() {
+    let dir = std::env::temp_dir().join(format!("dem_processor_geotiff_nodata_{}", std::process::id()));
+    let options = ProcessOptions { format: "geotiff".to_string(), ..ProcessOptions::new(&dir) };
+    process_asc_file_with(Path::new(ALL_NODATA), &options).unwrap();
+    let grid = read_geotiff_grid(&dir.join("all_nodata.tif")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!((grid.rows(), grid.cols()), (2, 3));
+    assert_eq!(grid.nodata, -9999.0);
+    assert!(grid.values.iter().all(|&value| grid.is_nodata(value)));
+}
+
+#[test]
+fn transform_and_nodata_survive_a_round_trip() {
+    let dir = std::env::temp_dir().join(format!("dem_processor_geotiff_round_trip_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("grid.tif");
+    let transform = [1000.0, 10.0, 0.0, 2030.0, 0.0, -10.0];
+    let data = vec![1.5, 2.0, -9999.0, 4.0, 5.25, 6.0];
+    let overview = (2, 1, vec![3.0, 5.0]);
+    write_gray_f32(&path, &[(3, 2, data.clone()), overview], &transform, Some(-9999.0), Some(Crs::WebMercator))
+        .unwrap();
+    let raster = read_single_band(&path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    // The full-resolution image comes back, not the overview stored after it.
+    assert_eq!((raster.width, raster.height), (3, 2));
+    assert_eq!(raster.transform, transform);
+    assert_eq!(raster.nodata, Some(-9999.0));
+    assert_eq!(raster.data, data.iter().map(|&v| v as f64).collect::<Vec<_>>());
+}
+
+#[test]
+fn grid_written_as_geotiff_reads_back_in_place() {
+    let dir = std::env::temp_dir().join(format!("dem_processor_geotiff_grid_{}", std::process::id()));
+    let options = ProcessOptions { format: "geotiff".to_string(), ..ProcessOptions::new(&dir) };
+    process_asc_file_with(Path::new(NORTH_UP), &options).unwrap();
+    let read = read_geotiff_grid(&dir.join("north_up.tif")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    let grid = read_ascii_grid(Path::new(NORTH_UP)).unwrap();
+    assert_eq!(read.geotransform(), grid.geotransform());
+    assert_eq!((read.x_min, read.y_min), (grid.x_min, grid.y_min));
+    assert_eq!(read.values, grid.values);
+}
+
+#[test]
+fn rendered_geotiffs_leave_nodata_transparent() {
+    let dir = std::env::temp_dir().join(format!("dem_processor_geotiff_alpha_{}", std::process::id()));
+    let input = dir.join("holed.asc");
+    fs::create_dir_all(&dir).unwrap();
+    let holed = common::grid(4, 4, 10.0, |row, col| if (row, col) == (1, 2) { common::NODATA } else { (row + col) as f64 });
+    fs::write(&input, common::asc_text(&holed)).unwrap();
+
+    let modes = ["hillshade", "shade", "color-relief"].map(String::from).to_vec();
+    let options = ProcessOptions { modes, format: "geotiff".to_string(), ..ProcessOptions::new(dir.join("out")) };
+    process_asc_file_with(&input, &options).unwrap();
+    let images: Vec<_> = ["hillshade", "shade", "color_relief"]
+        .map(|mode| image::open(dir.join("out").join(format!("holed_{}.tif", mode))).unwrap().into_rgba8())
+        .into();
+    let mut decoder = Decoder::new(File::open(dir.join("out").join("holed_shade.tif")).unwrap()).unwrap();
+    let extra_samples = decoder.get_tag_u32(Tag::ExtraSamples).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    for image in images {
+        assert_eq!(image.get_pixel(2, 1).0, [0, 0, 0, 0]);
+        assert!(image.enumerate_pixels().all(|(x, y, pixel)| (x, y) == (2, 1) || pixel[3] == 255));
+    }
+    // The fourth band is unassociated alpha.
+    assert_eq!(extra_samples, 2);
+}
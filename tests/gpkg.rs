@@ -0,0 +1,51 @@
+//! Checks the GeoPackage tile tables written for rendered images.
+
+use dem_processor::{write_gpkg, Crs};
+use image::{DynamicImage, Rgba, RgbaImage};
+use rusqlite::Connection;
+use std::fs;
+
+#[test]
+fn image_is_stored_as_one_tile_matrix() {
+    let dir = std::env::temp_dir().join(format!("dem_processor_gpkg_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dem.gpkg");
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(300, 10, Rgba([1, 2, 3, 255])));
+    write_gpkg(&path, &image, &[1000.0, 10.0, 0.0, 2030.0, 0.0, -10.0], Some(Crs::WebMercator)).unwrap();
+
+    let db = Connection::open(&path).unwrap();
+    let application_id: i64 = db.query_row("PRAGMA application_id", [], |row| row.get(0)).unwrap();
+    let extent: (f64, f64, f64, f64, i64) = db
+        .query_row("SELECT min_x, min_y, max_x, max_y, srs_id FROM gpkg_contents WHERE table_name = 'dem'", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+        })
+        .unwrap();
+    let matrix: (i64, i64, f64, f64) = db
+        .query_row("SELECT matrix_width, matrix_height, pixel_x_size, pixel_y_size FROM gpkg_tile_matrix", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .unwrap();
+    let tiles: Vec<(i64, i64)> = db
+        .prepare("SELECT tile_column, tile_row FROM dem ORDER BY tile_column")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    drop(db);
+    fs::remove_dir_all(&dir).unwrap();
+
+    // "GPKG" in ASCII.
+    assert_eq!(application_id, 0x4750_4B47);
+    assert_eq!(extent, (1000.0, 1930.0, 4000.0, 2030.0, 3857));
+    assert_eq!(matrix, (2, 1, 10.0, 10.0));
+    assert_eq!(tiles, [(0, 0), (1, 0)]);
+}
+
+#[test]
+fn south_up_images_are_rejected() {
+    let path = std::env::temp_dir().join(format!("dem_processor_gpkg_south_up_{}.gpkg", std::process::id()));
+    let image = DynamicImage::new_rgba8(2, 2);
+    assert!(write_gpkg(&path, &image, &[0.0, 1.0, 0.0, 0.0, 0.0, 1.0], None).is_err());
+    assert!(!path.exists());
+}
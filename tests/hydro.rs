@@ -0,0 +1,64 @@
+//! Checks flow accumulation and sink filling on small grids with known answers.
+
+mod common;
+
+use dem_processor::{compute_flow_accumulation, fill_sinks, ElevationGrid};
+
+#[test]
+fn flow_runs_down_the_ramp() {
+    // Rising to the east, every row drains west on its own.
+    let accumulation = compute_flow_accumulation(&common::grid(3, 4, 1.0, |_, col| col as f64));
+    for row in 0..3 {
+        assert_eq!(accumulation.values[row], [3.0, 2.0, 1.0, 0.0]);
+    }
+}
+
+/// A 5x5 bowl: a 10 m rim with an 8 m notch in the middle of its northern edge, around a
+/// 5 m floor with a 2 m pit in the center.
+fn bowl() -> ElevationGrid {
+    common::grid(5, 5, 1.0, |row, col| match (row, col) {
+        (0, 2) => 8.0,
+        (2, 2) => 2.0,
+        (0 | 4, _) | (_, 0 | 4) => 10.0,
+        _ => 5.0,
+    })
+}
+
+#[test]
+fn bowl_fills_up_to_its_notch() {
+    let mut filled = bowl();
+    assert_eq!(fill_sinks(&mut filled, 0.0), 9);
+    for row in 0..5 {
+        for col in 0..5 {
+            let expected = if (1..4).contains(&row) && (1..4).contains(&col) { 8.0 } else { bowl().values[row][col] };
+            assert_eq!(filled.values[row][col], expected, "cell ({}, {})", row, col);
+        }
+    }
+}
+
+#[test]
+fn tolerance_leaves_a_slope_toward_the_outlet() {
+    let mut filled = bowl();
+    fill_sinks(&mut filled, 0.1);
+    // Every inner cell drains to a strictly lower neighbor.
+    for row in 1..4 {
+        for col in 1..4 {
+            let value = filled.values[row][col];
+            let lowest = (row - 1..=row + 1)
+                .flat_map(|r| (col - 1..=col + 1).map(move |c| (r, c)))
+                .filter(|&pos| pos != (row, col))
+                .map(|(r, c)| filled.values[r][c])
+                .fold(f64::INFINITY, f64::min);
+            assert!(lowest < value, "cell ({}, {}) at {} has no lower neighbor", row, col, value);
+        }
+    }
+    assert!((filled.values[1][2] - 8.1).abs() < 1e-9);
+    assert!((filled.values[3][2] - 8.3).abs() < 1e-9);
+}
+
+#[test]
+fn grids_without_sinks_are_left_alone() {
+    let mut ramp = common::grid(4, 4, 1.0, |_, col| col as f64);
+    assert_eq!(fill_sinks(&mut ramp, 0.0), 0);
+    assert_eq!(ramp.values, common::grid(4, 4, 1.0, |_, col| col as f64).values);
+}
@@ -0,0 +1,41 @@
+//! Checks that merged tiles land where their corners place them.
+
+mod common;
+
+use common::NODATA;
+use dem_processor::{merge_grids, ElevationGrid};
+
+/// A tile of unit cells at `value` whose lower-left corner is at `(x_min, y_min)`.
+fn tile(x_min: f64, y_min: f64, rows: usize, cols: usize, value: f64) -> ElevationGrid {
+    ElevationGrid { x_min, y_min, ..common::grid(rows, cols, 1.0, |_, _| value) }
+}
+
+#[test]
+fn tiles_are_placed_by_their_corners() {
+    // `east` sits one cell further north than `west`.
+    let west = tile(0.0, 0.0, 2, 2, 1.0);
+    let east = tile(2.0, 1.0, 2, 2, 2.0);
+    let merged = merge_grids(&[west, east]).unwrap();
+
+    assert_eq!((merged.rows(), merged.cols()), (3, 4));
+    assert_eq!((merged.x_min, merged.y_min), (0.0, 0.0));
+    assert_eq!(merged.values[0], [NODATA, NODATA, 2.0, 2.0]);
+    assert_eq!(merged.values[1], [1.0, 1.0, 2.0, 2.0]);
+    assert_eq!(merged.values[2], [1.0, 1.0, NODATA, NODATA]);
+}
+
+#[test]
+fn first_valid_tile_wins_where_tiles_overlap() {
+    let mut holed = tile(0.0, 0.0, 1, 2, 1.0);
+    holed.values[0][0] = NODATA;
+    let below = tile(0.0, 0.0, 1, 2, 2.0);
+    let merged = merge_grids(&[holed, below]).unwrap();
+    assert_eq!(merged.values[0], [2.0, 1.0]);
+}
+
+#[test]
+fn tiles_of_different_cell_sizes_are_rejected() {
+    let coarse = ElevationGrid { cell_width: 2.0, cell_height: 2.0, ..tile(0.0, 0.0, 1, 1, 1.0) };
+    assert!(merge_grids(&[tile(0.0, 0.0, 1, 1, 1.0), coarse]).is_err());
+    assert!(merge_grids(&[]).is_err());
+}
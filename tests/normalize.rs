@@ -0,0 +1,75 @@
+//! Checks how elevations are stretched into gray levels, with and without fixed bounds.
+
+mod common;
+
+use clap::Parser;
+use dem_processor::cli::Args;
+use dem_processor::{process_asc_file_with, ProcessOptions};
+use image::GrayImage;
+use std::fs;
+
+/// Renders a 3x3 grid rising from 1 m to 9 m in reading order as a grayscale image with
+/// the bounds and normalization of `options`.
+fn render(name: &str, options: impl FnOnce(ProcessOptions) -> ProcessOptions) -> GrayImage {
+    let dir = std::env::temp_dir().join(format!("dem_processor_normalize_{}_{}", name, std::process::id()));
+    let input = dir.join("ramp.asc");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&input, common::asc_text(&common::grid(3, 3, 1.0, |row, col| (row * 3 + col + 1) as f64))).unwrap();
+
+    process_asc_file_with(&input, &options(ProcessOptions::new(dir.join("out")))).unwrap();
+    let image = image::open(dir.join("out").join("ramp.png")).unwrap().to_luma8();
+    fs::remove_dir_all(&dir).unwrap();
+    image
+}
+
+#[test]
+fn equal_or_inverted_bounds_are_rejected() {
+    let options = |bounds: &[&str]| {
+        let args = Args::try_parse_from(["dem_processor", "-i", "in", "-o", "out"].iter().chain(bounds)).unwrap();
+        ProcessOptions::try_from(&args).map(|_| ())
+    };
+    assert!(options(&["--min-elev", "5", "--max-elev", "6"]).is_ok());
+    assert!(options(&["--min-elev", "5", "--max-elev", "5"]).is_err());
+    assert!(options(&["--min-elev", "6", "--max-elev", "5"]).is_err());
+    assert!(options(&["--min-elev", "NaN"]).is_err());
+}
+
+#[test]
+fn single_bound_beyond_the_tile_renders_it_flat() {
+    let below = render("below", |options| ProcessOptions { min_elev: Some(100.0), ..options });
+    assert!(below.pixels().all(|p| p.0 == [0]));
+    let above = render("above", |options| ProcessOptions { max_elev: Some(-5.0), ..options });
+    assert!(above.pixels().all(|p| p.0 == [255]));
+    // Streamed grids get the same stretch.
+    let streamed = render("streamed", |options| ProcessOptions { min_elev: Some(100.0), low_memory: true, ..options });
+    assert!(streamed.pixels().all(|p| p.0 == [0]));
+}
+
+#[test]
+fn single_bound_within_the_tile_keeps_the_other_end() {
+    let image = render("within", |options| ProcessOptions { min_elev: Some(5.0), ..options });
+    assert_eq!(image.get_pixel(1, 1).0, [0]);
+    assert_eq!(image.get_pixel(2, 2).0, [255]);
+}
+
+#[test]
+fn log_stretch_survives_bounds_beyond_the_tile() {
+    let log = |options: ProcessOptions| ProcessOptions { normalize: "log".to_string(), ..options };
+    let below = render("log_below", |options| ProcessOptions { min_elev: Some(100.0), ..log(options) });
+    assert!(below.pixels().all(|p| p.0 == [0]));
+    // Inverted bounds only get past the command line from code; they leave the grid flat.
+    let inverted = render("log_inverted", |options| ProcessOptions { min_elev: Some(9.0), max_elev: Some(1.0), ..log(options) });
+    assert!(inverted.pixels().all(|&p| p == *inverted.get_pixel(0, 0)));
+}
+
+#[test]
+fn equalized_stretch_survives_bounds_beyond_the_tile() {
+    let equalize = |options: ProcessOptions| ProcessOptions { normalize: "equalize".to_string(), ..options };
+    let below = render("equalize_below", |options| ProcessOptions { min_elev: Some(100.0), ..equalize(options) });
+    assert!(below.pixels().all(|p| p.0 == [0]));
+    let above = render("equalize_above", |options| ProcessOptions { max_elev: Some(-5.0), ..equalize(options) });
+    assert!(above.pixels().all(|p| p.0 == [255]));
+    let inverted =
+        render("equalize_inverted", |options| ProcessOptions { min_elev: Some(9.0), max_elev: Some(1.0), ..equalize(options) });
+    assert!(inverted.pixels().all(|&p| p == *inverted.get_pixel(0, 0)));
+}
@@ -0,0 +1,44 @@
+//! Pins down which way grid rows run through the rendered images.
+//!
+//! `fixtures/north_up.asc` rises from 10 m along its southern row to 30 m along its northern
+//! one, with a 40 m peak in the north-east corner.
+
+use dem_processor::geotiff::pixel_to_map;
+use dem_processor::{ascii_to_image, compute_aspect, read_ascii_grid};
+use std::fs::File;
+use std::path::Path;
+
+const FIXTURE: &str = "tests/fixtures/north_up.asc";
+
+#[test]
+fn first_file_row_is_the_northern_grid_row() {
+    let grid = read_ascii_grid(Path::new(FIXTURE)).unwrap();
+    assert_eq!(grid.values[0], [30.0, 30.0, 30.0, 40.0]);
+    assert_eq!(grid.values[2], [10.0, 10.0, 10.0, 10.0]);
+}
+
+#[test]
+fn image_is_rendered_north_up() {
+    let image = ascii_to_image(File::open(FIXTURE).unwrap()).unwrap();
+    assert_eq!(image.dimensions(), (4, 3));
+    assert_eq!(image.get_pixel(3, 0).0, [255]);
+    assert_eq!(image.get_pixel(0, 0).0, [170]);
+    assert!((0..4).all(|x| image.get_pixel(x, 2).0 == [0]));
+}
+
+#[test]
+fn geotransform_places_the_top_left_pixel_at_the_north_west_corner() {
+    let grid = read_ascii_grid(Path::new(FIXTURE)).unwrap();
+    let transform = grid.geotransform();
+    assert_eq!(pixel_to_map(&transform, 0.0, 0.0), (1000.0, 2030.0));
+    assert_eq!(pixel_to_map(&transform, 4.0, 3.0), (1040.0, 2000.0));
+    // Contour vertices given as grid positions land on the same pixels.
+    assert_eq!(grid.image_position(0.0, 3.0), (3.5, 0.5));
+}
+
+#[test]
+fn slopes_rising_northward_face_south() {
+    let grid = read_ascii_grid(Path::new(FIXTURE)).unwrap();
+    let aspect = compute_aspect(&grid);
+    assert_eq!(aspect.values[1][1], 180.0);
+}
@@ -0,0 +1,176 @@
+//! Checks the shading math against tiny synthetic DEMs with known answers: a flat plane,
+//! a ramp and a pyramid.
+
+mod common;
+
+use dem_processor::{
+    apply_hillshade, apply_multidirectional_hillshade, classify_slope, compute_aspect, compute_slope, grid_to_image,
+    process_asc_file_with, ElevationGrid, ProcessOptions,
+};
+use std::fs;
+
+/// Cell size of the synthetic grids in meters.
+const CELL_SIZE: f64 = 10.0;
+
+/// A plane at 100 m.
+fn flat() -> ElevationGrid {
+    common::grid(8, 8, CELL_SIZE, |_, _| 100.0)
+}
+
+/// A 45° ramp rising to the east.
+fn ramp() -> ElevationGrid {
+    common::grid(8, 8, CELL_SIZE, |_, col| col as f64 * CELL_SIZE)
+}
+
+/// Elevation of a square pyramid peaking in the middle of a 9x9 grid, with 45° faces.
+fn pyramid_z(row: usize, col: usize) -> f64 {
+    (4 - row.abs_diff(4).max(col.abs_diff(4)) as i64) as f64 * CELL_SIZE
+}
+
+fn pyramid() -> ElevationGrid {
+    common::grid(9, 9, CELL_SIZE, pyramid_z)
+}
+
+/// The gray level of a pixel of a hillshade, whose channels are all equal.
+fn shade(image: &image::RgbImage, x: u32, y: u32) -> u8 {
+    image.get_pixel(x, y)[0]
+}
+
+#[test]
+fn flat_plane_has_no_slope() {
+    let slope = compute_slope(&flat(), 1.0);
+    assert!(slope.values.iter().all(|&value| value == 0.0));
+}
+
+#[test]
+fn flat_plane_is_shaded_uniformly() {
+    let expected = (255.0 * 45f64.to_radians().sin()).round() as u8;
+    for azimuth in [0.0, 90.0, 225.0, 315.0] {
+        let image = apply_hillshade(&flat(), 1.0, azimuth, 45.0);
+        assert!(image.pixels().all(|p| p.0 == [expected; 3]), "azimuth {}", azimuth);
+    }
+    let image = apply_multidirectional_hillshade(&flat(), 1.0, 45.0);
+    let first = *image.get_pixel(0, 0);
+    assert!(image.pixels().all(|&p| p == first));
+}
+
+#[test]
+fn ramp_grays_increase_eastward() {
+    let image = grid_to_image(&ramp());
+    for y in 0..image.height() {
+        let row: Vec<u8> = (0..image.width()).map(|x| image.get_pixel(x, y)[0]).collect();
+        assert!(row.windows(2).all(|pair| pair[0] < pair[1]), "row {}: {:?}", y, row);
+    }
+    assert_eq!(image.get_pixel(0, 0)[0], 0);
+    assert_eq!(image.get_pixel(7, 0)[0], 255);
+}
+
+#[test]
+fn ramp_has_its_slope_and_aspect_inside_the_edges() {
+    let (slope, aspect) = (compute_slope(&ramp(), 1.0), compute_aspect(&ramp()));
+    for row in 1..7 {
+        for col in 1..7 {
+            assert!((slope.values[row][col] - 45.0).abs() < 1e-9);
+            assert_eq!(aspect.values[row][col], 270.0);
+        }
+    }
+    // A steeper z-factor steepens the slope.
+    assert!(compute_slope(&ramp(), 2.0).values[4][4] > 60.0);
+}
+
+#[test]
+fn ramp_is_lit_from_the_side_it_faces() {
+    let from_west = apply_hillshade(&ramp(), 1.0, 270.0, 45.0);
+    let from_east = apply_hillshade(&ramp(), 1.0, 90.0, 45.0);
+    // Lit head-on at 45° on a 45° slope, the cosine of the light is 1; from behind it is 0.
+    assert_eq!(shade(&from_west, 4, 4), 255);
+    assert_eq!(shade(&from_east, 4, 4), 0);
+    // Light along the slope's contour falls at the same angle on both sides of the ramp.
+    let from_north = apply_hillshade(&ramp(), 1.0, 0.0, 45.0);
+    let from_south = apply_hillshade(&ramp(), 1.0, 180.0, 45.0);
+    assert_eq!(shade(&from_north, 4, 4), shade(&from_south, 4, 4));
+}
+
+#[test]
+fn pyramid_faces_toward_the_light_are_brighter() {
+    // Face centers as image pixels: north and west face the north-west light.
+    let (north, south, west, east) = ((4, 2), (4, 6), (2, 4), (6, 4));
+    let image = apply_hillshade(&pyramid(), 1.0, 315.0, 45.0);
+    let at = |(x, y)| shade(&image, x, y);
+    assert!(at(north) > at(south));
+    assert!(at(west) > at(east));
+    assert_eq!(at(north), at(west));
+    assert_eq!(at(south), at(east));
+
+    // Lit from the south-east instead, the sides swap.
+    let image = apply_hillshade(&pyramid(), 1.0, 135.0, 45.0);
+    let at = |(x, y)| shade(&image, x, y);
+    assert!(at(south) > at(north));
+    assert!(at(east) > at(west));
+}
+
+#[test]
+fn pyramid_faces_point_away_from_the_peak() {
+    let aspect = compute_aspect(&pyramid());
+    assert_eq!(aspect.values[2][4], 0.0);
+    assert_eq!(aspect.values[4][6], 90.0);
+    assert_eq!(aspect.values[6][4], 180.0);
+    assert_eq!(aspect.values[4][2], 270.0);
+}
+
+#[test]
+fn slope_classes_follow_the_breaks() {
+    let classes = classify_slope(&compute_slope(&ramp(), 1.0), &[5.0, 15.0, 30.0]);
+    assert_eq!(classes.values[4][4], 4.0);
+    let classes = classify_slope(&compute_slope(&flat(), 1.0), &[5.0, 15.0, 30.0]);
+    assert!(classes.values.iter().all(|&class| class == 1.0));
+}
+
+#[test]
+fn rendered_shade_keeps_the_lit_side_on_the_north_west() {
+    let dir = std::env::temp_dir().join(format!("dem_processor_shading_{}", std::process::id()));
+    let input = dir.join("pyramid.asc");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&input, common::asc_text(&pyramid())).unwrap();
+
+    let options = ProcessOptions { modes: vec!["shade".to_string()], ..ProcessOptions::new(dir.join("out")) };
+    process_asc_file_with(&input, &options).unwrap();
+    let image = image::open(dir.join("out").join("pyramid_shade.png")).unwrap().to_luma8();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(image.dimensions(), (9, 9));
+    assert!(image.get_pixel(4, 2)[0] > image.get_pixel(4, 6)[0]);
+    assert!(image.get_pixel(2, 4)[0] > image.get_pixel(6, 4)[0]);
+}
+
+#[test]
+fn half_opacity_blend_leaves_nodata_empty_in_geotiffs_and_geopackages() {
+    let dir = std::env::temp_dir().join(format!("dem_processor_shading_opacity_{}", std::process::id()));
+    let input = dir.join("holed.asc");
+    fs::create_dir_all(&dir).unwrap();
+    let holed = common::grid(9, 9, CELL_SIZE, |row, col| if (row, col) == (4, 4) { common::NODATA } else { pyramid_z(row, col) });
+    fs::write(&input, common::asc_text(&holed)).unwrap();
+
+    let render = |format: &str| {
+        let options = ProcessOptions {
+            modes: vec!["hillshade".to_string()],
+            format: format.to_string(),
+            hillshade_opacity: 0.5,
+            ..ProcessOptions::new(dir.join(format))
+        };
+        process_asc_file_with(&input, &options).unwrap();
+    };
+    render("geotiff");
+    render("gpkg");
+    let geotiff = image::open(dir.join("geotiff").join("holed_hillshade.tif")).unwrap().into_rgba8();
+    let db = rusqlite::Connection::open(dir.join("gpkg").join("holed_hillshade.gpkg")).unwrap();
+    let tile: Vec<u8> = db.query_row("SELECT tile_data FROM holed_hillshade", [], |row| row.get(0)).unwrap();
+    let gpkg = image::load_from_memory(&tile).unwrap().into_rgba8();
+    drop(db);
+    fs::remove_dir_all(&dir).unwrap();
+
+    for image in [geotiff, gpkg] {
+        assert_eq!(image.get_pixel(4, 4).0, [0, 0, 0, 0]);
+        assert_eq!(image.get_pixel(2, 2)[3], 255);
+    }
+}
@@ -0,0 +1,41 @@
+//! Checks the web Mercator tile pyramid: zoom levels and where tiles are written.
+
+use dem_processor::tiles::{native_zoom, overview_zoom, TILE_SIZE};
+use dem_processor::write_tiles;
+use image::{Rgba, RgbaImage};
+use std::fs;
+
+/// Half the width of the web Mercator world in meters.
+const WORLD_EXTENT: f64 = 20_037_508.342_789_244;
+
+#[test]
+fn zoom_levels_follow_the_cell_size_and_extent() {
+    let world_pixel = 2.0 * WORLD_EXTENT / TILE_SIZE as f64;
+    assert_eq!(native_zoom(world_pixel), 0);
+    assert_eq!(native_zoom(world_pixel / 2.0), 1);
+    // Zoom 17 pixels are 1.19 m, zoom 18 ones 0.6 m.
+    assert_eq!(native_zoom(1.0), 18);
+    assert_eq!(overview_zoom(2.0 * WORLD_EXTENT, 2.0 * WORLD_EXTENT), 0);
+    assert_eq!(overview_zoom(WORLD_EXTENT, 1.0), 1);
+}
+
+#[test]
+fn world_image_is_cut_into_a_pyramid() {
+    let dir = std::env::temp_dir().join(format!("dem_processor_tiles_{}", std::process::id()));
+    let image = RgbaImage::from_pixel(TILE_SIZE, TILE_SIZE, Rgba([10, 20, 30, 255]));
+    let pixel = 2.0 * WORLD_EXTENT / TILE_SIZE as f64;
+    let transform = [-WORLD_EXTENT, pixel, 0.0, WORLD_EXTENT, 0.0, -pixel];
+    let written = write_tiles(&image, &transform, (0, 1), true, &dir, "png", |tile, path| Ok(tile.save(path)?));
+    let tile = |path: &str| image::open(dir.join(path)).map(|image| image.to_rgba8());
+    let (root, north_west) = (tile("0/0/0.png"), tile("1/0/1.png"));
+    let south_east = tile("1/1/0.png");
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(written.unwrap(), 5);
+    // TMS rows count from the south, so the north-western tile at zoom 1 is row 1.
+    for tile in [root, north_west, south_east] {
+        let tile = tile.unwrap();
+        assert_eq!(tile.dimensions(), (TILE_SIZE, TILE_SIZE));
+        assert_eq!(tile.get_pixel(128, 128).0, [10, 20, 30, 255]);
+    }
+}
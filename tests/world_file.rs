@@ -0,0 +1,39 @@
+//! Golden tests of the world file and `.prj` sidecars written next to images.
+
+use dem_processor::Crs;
+use dem_processor::world_file::{world_file_path, write_world_file};
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn sidecar_extensions_take_the_first_and_last_letters() {
+    assert_eq!(world_file_path(Path::new("tile.png")), Path::new("tile.pgw"));
+    assert_eq!(world_file_path(Path::new("tile.jpeg")), Path::new("tile.jgw"));
+    assert_eq!(world_file_path(Path::new("tile.tif")), Path::new("tile.tfw"));
+}
+
+#[test]
+fn world_file_places_the_first_pixel_center_and_prj_names_the_crs() {
+    let dir = std::env::temp_dir().join(format!("dem_processor_world_file_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let image = dir.join("tile.png");
+    write_world_file(&image, &[1000.0, 10.0, 0.0, 2030.0, 0.0, -10.0], Some(Crs::WebMercator)).unwrap();
+    let world = fs::read_to_string(dir.join("tile.pgw")).unwrap();
+    let prj = fs::read_to_string(dir.join("tile.prj")).unwrap();
+    write_world_file(&dir.join("bare.png"), &[0.0, 1.0, 0.0, 0.0, 0.0, -1.0], None).unwrap();
+    let bare_prj = dir.join("bare.prj").exists();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(world, "10\n0\n0\n-10\n1005\n2025\n");
+    assert_eq!(
+        prj,
+        concat!(
+            r#"PROJCS["WGS_1984_Web_Mercator_Auxiliary_Sphere",GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984","#,
+            r#"SPHEROID["WGS_1984",6378137.0,298.257223563]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],"#,
+            r#"PROJECTION["Mercator_Auxiliary_Sphere"],PARAMETER["False_Easting",0.0],PARAMETER["False_Northing",0.0],"#,
+            r#"PARAMETER["Central_Meridian",0.0],PARAMETER["Standard_Parallel_1",0.0],"#,
+            r#"PARAMETER["Auxiliary_Sphere_Type",0.0],UNIT["Meter",1.0]]"#,
+        )
+    );
+    assert!(!bare_prj);
+}